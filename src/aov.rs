@@ -0,0 +1,92 @@
+use std::path::Path;
+
+use image::{ImageBuffer, ImageResult, Luma, RgbImage};
+
+use crate::{color::Color, math::{Float, Ray, Vector3}, raytracer::closest_hit, scene::Scene, stats::RayCounters};
+
+//Вспомогательные данные о первом пересечении луча камеры
+#[derive(Debug, Clone, Copy)]
+pub struct PixelAov {
+    pub depth: Float,
+    pub normal: Vector3,
+    pub albedo: Vector3,
+    pub figure: Option<usize>,
+}
+impl PixelAov {
+    pub const MISS: Self = PixelAov {
+        depth: Float::INFINITY,
+        normal: Vector3::new(0.0, 0.0, 0.0),
+        albedo: Vector3::new(0.0, 0.0, 0.0),
+        figure: None,
+    };
+}
+
+pub fn primary_aov(scene: &Scene, r: &Ray, stats: &mut RayCounters) -> PixelAov {
+    match closest_hit(scene, r, stats) {
+        Some((i, h)) => PixelAov {
+            depth: h.t,
+            normal: h.normal,
+            albedo: h.material().color,
+            figure: Some(i),
+        },
+        None => PixelAov::MISS,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AovBuffers {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<PixelAov>,
+}
+impl AovBuffers {
+    pub fn new(width: usize, height: usize, pixels: Vec<PixelAov>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        Self { width, height, pixels }
+    }
+    //Глубина нормируется на самое дальнее пересечение, промахи остаются белыми
+    pub fn depth_image(&self) -> ImageBuffer<Luma<u16>, Vec<u16>> {
+        let max = self.pixels.iter()
+            .map(|p| p.depth)
+            .filter(|d| d.is_finite())
+            .fold(0.0, Float::max);
+        let t = self.pixels.iter().map(|p| {
+            if p.depth.is_finite() && max > 0.0 {
+                (p.depth / max * u16::MAX as Float) as u16
+            } else {
+                u16::MAX
+            }
+        }).collect();
+        ImageBuffer::from_vec(self.width as u32, self.height as u32, t).unwrap()
+    }
+    //Нормали переводятся из [-1, 1] в [0, 1]
+    pub fn normal_image(&self) -> RgbImage {
+        self.to_rgb(|p| if p.figure.is_some() {
+            Color::from_vector3(&(p.normal + Vector3::new(1.0, 1.0, 1.0)).mult(0.5))
+        } else {
+            Color::BLACK
+        })
+    }
+    pub fn albedo_image(&self) -> RgbImage {
+        self.to_rgb(|p| Color::from_vector3(&p.albedo))
+    }
+    pub fn figure_image(&self) -> RgbImage {
+        self.to_rgb(|p| p.figure.map_or(Color::BLACK, id_color))
+    }
+    pub fn save(&self, dir: &Path, prefix: &str) -> ImageResult<()> {
+        self.depth_image().save_with_format(dir.join(format!("{prefix}_depth.png")), image::ImageFormat::Png)?;
+        self.normal_image().save_with_format(dir.join(format!("{prefix}_normal.png")), image::ImageFormat::Png)?;
+        self.albedo_image().save_with_format(dir.join(format!("{prefix}_albedo.png")), image::ImageFormat::Png)?;
+        self.figure_image().save_with_format(dir.join(format!("{prefix}_id.png")), image::ImageFormat::Png)
+    }
+    fn to_rgb(&self, f: impl Fn(&PixelAov) -> Color) -> RgbImage {
+        let t = self.pixels.iter().map(f).flat_map(|c| [c.r, c.g, c.b]).collect();
+        RgbImage::from_vec(self.width as u32, self.height as u32, t).unwrap()
+    }
+}
+
+//Различимый цвет для номера фигуры
+fn id_color(i: usize) -> Color {
+    let h = (i as u32 + 1).wrapping_mul(0x9E37_79B9);
+    Color::new((h >> 24) as u8 | 0x40, (h >> 16) as u8 | 0x40, (h >> 8) as u8 | 0x40)
+}
@@ -0,0 +1,141 @@
+use std::path::Path;
+
+use image::ImageResult;
+
+use crate::{color::luminance, math::{consts, Float, Vector3}};
+
+//Что видят лучи, не попавшие ни в одну фигуру
+#[derive(Debug, Clone)]
+pub enum Background {
+    Color(Vector3),
+    //Небо: от horizon у горизонта к zenith вверху (-y), ниже горизонта - horizon
+    Gradient { horizon: Vector3, zenith: Vector3 },
+    Map(EnvMap),
+}
+impl Background {
+    pub const BLACK: Background = Background::Color(Vector3::new(0.0, 0.0, 0.0));
+    pub const SKY: Background = Background::Gradient { horizon: Vector3::new(0.8, 0.85, 0.9), zenith: Vector3::new(0.25, 0.45, 0.85) };
+
+    pub fn radiance(&self, dir: &Vector3) -> Vector3 {
+        match self {
+            Background::Color(c) => *c,
+            Background::Gradient { horizon, zenith } => horizon.lerp(zenith, (-dir.y).max(0.0)),
+            Background::Map(m) => m.lookup(dir),
+        }
+    }
+}
+
+//Равнопромежуточная (широта-долгота) карта окружения в линейных HDR-значениях.
+//Верхняя строка смотрит вверх (-y), центр карты - вдоль +z.
+//Для выбора направлений хранятся функции распределения по яркости пикселей.
+#[derive(Debug, Clone)]
+pub struct EnvMap {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vector3>,
+    //Накопленные веса строк, нормированные на 1
+    rows_cdf: Vec<Float>,
+    //Накопленные веса пикселей в каждой строке, нормированные на 1
+    cols_cdf: Vec<Float>,
+    //Сумма весов всех пикселей, 0 - выбирать нечего
+    total: Float,
+}
+
+//Выбранное по карте направление, его яркость и плотность по телесному углу
+#[derive(Debug, Clone, Copy)]
+pub struct EnvSample {
+    pub dir: Vector3,
+    pub radiance: Vector3,
+    pub pdf: Float,
+}
+
+//Индекс отрезка cdf, в который попадает u, и положение u внутри него
+fn sample_cdf(cdf: &[Float], u: Float) -> (usize, Float) {
+    let i = cdf.partition_point(|c| *c <= u).min(cdf.len() - 1);
+    let lo = if i == 0 { 0.0 } else { cdf[i - 1] };
+    let w = cdf[i] - lo;
+    (i, if w > 0.0 { ((u - lo) / w).clamp(0.0, 1.0) } else { 0.5 })
+}
+
+fn normalize_cdf(cdf: &mut [Float]) -> Float {
+    let mut sum = 0.0;
+    for c in cdf.iter_mut() {
+        sum += *c;
+        *c = sum;
+    }
+    if sum > 0.0 {
+        cdf.iter_mut().for_each(|c| *c /= sum);
+    }
+    sum
+}
+
+impl EnvMap {
+    pub fn new(width: usize, height: usize, pixels: Vec<Vector3>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        //Пиксели у полюсов занимают меньший телесный угол
+        let mut cols_cdf: Vec<Float> = pixels.iter().map(luminance).map(|l| l.max(0.0)).collect();
+        let mut rows_cdf = vec![0.0; height];
+        for (j, row) in cols_cdf.chunks_mut(width).enumerate() {
+            let sin = (consts::PI * (j as Float + 0.5) / height as Float).sin();
+            rows_cdf[j] = normalize_cdf(row) * sin;
+        }
+        let total = normalize_cdf(&mut rows_cdf);
+        EnvMap { width, height, pixels, rows_cdf, cols_cdf, total }
+    }
+    //Карта из файла, например .hdr
+    pub fn load(path: &Path) -> ImageResult<Self> {
+        let img = image::open(path)?.to_rgb32f();
+        let pixels = img.pixels().map(|p| Vector3::new(p[0] as Float, p[1] as Float, p[2] as Float)).collect();
+        Ok(Self::new(img.width() as usize, img.height() as usize, pixels))
+    }
+    //Координаты на карте в [0, 1)
+    fn dir_to_uv(dir: &Vector3) -> (Float, Float) {
+        let u = 0.5 + dir.x.atan2(dir.z) / (2.0 * consts::PI);
+        let v = (-dir.y).clamp(-1.0, 1.0).acos() / consts::PI;
+        (u, v)
+    }
+    fn uv_to_dir(u: Float, v: Float) -> Vector3 {
+        let phi = (u - 0.5) * 2.0 * consts::PI;
+        let (sin, cos) = (v * consts::PI).sin_cos();
+        Vector3::new(sin * phi.sin(), -cos, sin * phi.cos())
+    }
+    fn pixel_index(&self, dir: &Vector3) -> (usize, usize) {
+        let (u, v) = Self::dir_to_uv(dir);
+        let i = ((u * self.width as Float) as usize).min(self.width - 1);
+        let j = ((v * self.height as Float) as usize).min(self.height - 1);
+        (i, j)
+    }
+    pub fn lookup(&self, dir: &Vector3) -> Vector3 {
+        let (i, j) = self.pixel_index(dir);
+        self.pixels[j * self.width + i]
+    }
+    //Вероятность пикселя (i, j) при выборе по весам
+    fn pixel_probability(&self, i: usize, j: usize) -> Float {
+        let row = &self.cols_cdf[j * self.width..(j + 1) * self.width];
+        let pr = self.rows_cdf[j] - if j == 0 { 0.0 } else { self.rows_cdf[j - 1] };
+        pr * (row[i] - if i == 0 { 0.0 } else { row[i - 1] })
+    }
+    //Плотность sample() по телесному углу. Пиксель занимает 2π/w по долготе и π/h по широте.
+    pub fn pdf(&self, dir: &Vector3) -> Float {
+        if self.total <= 0.0 {
+            return 0.0;
+        }
+        let (i, j) = self.pixel_index(dir);
+        let sin = (1.0 - dir.y * dir.y).max(0.0).sqrt();
+        if sin <= 0.0 {
+            return 0.0;
+        }
+        self.pixel_probability(i, j) * (self.width * self.height) as Float / (2.0 * consts::PI * consts::PI * sin)
+    }
+    //Направление, выбранное пропорционально яркости пикселя и занимаемому им телесному углу
+    pub fn sample(&self, u: (Float, Float)) -> Option<EnvSample> {
+        if self.total <= 0.0 {
+            return None;
+        }
+        let (j, fv) = sample_cdf(&self.rows_cdf, u.1);
+        let (i, fu) = sample_cdf(&self.cols_cdf[j * self.width..(j + 1) * self.width], u.0);
+        let dir = Self::uv_to_dir((i as Float + fu) / self.width as Float, (j as Float + fv) / self.height as Float);
+        let pdf = self.pdf(&dir);
+        (pdf > 0.0).then(|| EnvSample { dir, radiance: self.lookup(&dir), pdf })
+    }
+}
@@ -0,0 +1,348 @@
+use crate::{
+    figure::HitRecord,
+    lights::{LightRef, LightSampler},
+    math::{consts, Float, Ray, Vector3, EPSILON},
+    path::{dielectric_bounce, MAX_DEPTH, ROULETTE_DEPTH},
+    random::Rng,
+    raytracer::{closest_hit, occluded},
+    scene::{LightSource, Scene},
+    stats::RayCounters,
+};
+
+//Вершин в подпути камеры и подпути источника, считая камеру и сам источник
+const MAX_VERTICES: usize = MAX_DEPTH as usize + 1;
+
+#[derive(Debug, Clone, Copy)]
+enum Kind<'a> {
+    Camera,
+    Light(LightRef<'a>),
+    //Попадание и номер фигуры в сцене
+    Surface(HitRecord<'a>, usize),
+}
+
+//Вершина подпути. Плотности - по площади (по точке для точечного источника):
+//pdf_fwd - с которой вершина получена своим подпутём, pdf_rev - встречным.
+#[derive(Debug, Clone, Copy)]
+struct Vertex<'a> {
+    kind: Kind<'a>,
+    point: Vector3,
+    //Нормаль поверхности наружу, у камеры и точечного источника не используется
+    normal: Vector3,
+    //Вклад подпути до этой вершины
+    beta: Vector3,
+    //Подпуть продолжен из вершины зеркально (преломлением или отражением по Френелю)
+    delta: bool,
+    pdf_fwd: Float,
+    pdf_rev: Float,
+}
+
+impl<'a> Vertex<'a> {
+    fn on_surface(&self) -> bool {
+        matches!(self.kind, Kind::Surface(..) | Kind::Light(LightRef::Emitter(_)))
+    }
+    fn is_point_light(&self) -> bool {
+        self.point_light().is_some()
+    }
+    fn point_light(&self) -> Option<&'a LightSource> {
+        match self.kind {
+            Kind::Light(LightRef::Point(l)) => Some(l),
+            _ => None,
+        }
+    }
+    //Плотность по площади в next для плотности pdf_dir по телесному углу из self
+    fn density_at(&self, pdf_dir: Float, next: &Vertex) -> Float {
+        let w = next.point - self.point;
+        let dist_sq = w.len_sq();
+        if dist_sq <= 0.0 {
+            return 0.0;
+        }
+        let mut pdf = pdf_dir / dist_sq;
+        if next.on_surface() {
+            pdf *= next.normal.scalar_product(&w).abs() / dist_sq.sqrt();
+        }
+        pdf
+    }
+    //Доля незеркального отражения: прозрачные материалы с вероятностью transparency преломляют
+    fn diffuse_part(hit: &HitRecord) -> Float {
+        let t = hit.material().transparency;
+        if t > EPSILON { 1.0 - t } else { 1.0 }
+    }
+    //BRDF * cos для света из b, уходящего в a. Направления - от вершины.
+    fn eval(&self, a: &Vector3, b: &Vector3) -> Vector3 {
+        let Kind::Surface(hit, _) = &self.kind else { return Vector3::new(0.0, 0.0, 0.0) };
+        let m = hit.material();
+        let n = if self.normal.scalar_product(a) >= 0.0 { self.normal } else { -self.normal };
+        m.as_pbr().eval(&m.color, &n, a, b).mult(Self::diffuse_part(hit))
+    }
+    //Плотность, с которой вершина порождает next, если в неё пришли из prev
+    fn pdf(&self, prev: Option<&Vertex>, next: &Vertex) -> Float {
+        let b = (next.point - self.point).normalize();
+        let pdf_dir = match (&self.kind, prev) {
+            (Kind::Surface(hit, _), Some(prev)) => {
+                let a = (prev.point - self.point).normalize();
+                let n = if self.normal.scalar_product(&a) >= 0.0 { self.normal } else { -self.normal };
+                hit.material().as_pbr().pdf(&n, &a, &b) * Self::diffuse_part(hit)
+            }
+            (Kind::Light(LightRef::Point(_)), _) => 1.0 / (4.0 * consts::PI),
+            (Kind::Light(LightRef::Emitter(_)), _) => self.normal.scalar_product(&b).abs() / (2.0 * consts::PI),
+            _ => 0.0,
+        };
+        self.density_at(pdf_dir, next)
+    }
+}
+
+//Двунаправленная трассировка путей (Veach): подпуть из камеры и подпуть из источника,
+//выбранного по мощности, соединяются всеми способами, вклады взвешиваются по MIS.
+//Соединения подпути источника прямо с камерой (t = 1) не используются: изображение
+//считается по пикселям. Свет, дошедший через прозрачные фигуры, приходит по путям
+//с преломлением, а не через ослабленные тени. Точечные источники ослабевают с расстоянием,
+//как в shadow_part. Фон виден только подпути камеры.
+pub fn bidirectional(scene: &Scene, r: &Ray, lights: &LightSampler, rng: &mut Rng, stats: &mut RayCounters) -> Vector3 {
+    let mut radiance = Vector3::new(0.0, 0.0, 0.0);
+    let one = Vector3::new(1.0, 1.0, 1.0);
+    let mut camera = vec![Vertex { kind: Kind::Camera, point: r.pos, normal: r.dir, beta: one, delta: false, pdf_fwd: 1.0, pdf_rev: 0.0 }];
+    if let Some((ray, beta)) = random_walk(scene, *r, one, 1.0, rng, false, &mut camera, stats) {
+        radiance += beta.mult_per_element(&scene.background.radiance(&ray.dir));
+    }
+    let light_path = light_subpath(scene, lights, rng, stats);
+    for t in 2..=camera.len() {
+        for s in 0..=light_path.len() {
+            //Не длиннее путей, которые находит path_trace
+            if s + t > MAX_VERTICES + 1 {
+                break;
+            }
+            radiance += connect(scene, lights, &light_path, &camera, s, t, rng, stats);
+        }
+    }
+    radiance
+}
+
+//Равномерное направление на сфере
+fn uniform_sphere(u: (Float, Float)) -> Vector3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * consts::PI * u.1;
+    Vector3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+//Направление по косинусу вокруг n
+fn cosine_hemisphere(n: &Vector3, u: (Float, Float)) -> Vector3 {
+    let (a, b) = n.orthonormal_basis();
+    let phi = 2.0 * consts::PI * u.1;
+    a.mult(u.0.sqrt() * phi.cos()) + b.mult(u.0.sqrt() * phi.sin()) + n.mult((1.0 - u.0).sqrt())
+}
+
+//Вершина на источнике и её вклад, делённый на вероятность выбора точки
+fn light_vertex<'a>(light: &LightRef<'a>, prob: Float, rng: &mut Rng) -> Option<Vertex<'a>> {
+    match light {
+        LightRef::Point(l) => {
+            let beta = l.color.mult(l.intencity / prob);
+            Some(Vertex { kind: Kind::Light(*light), point: l.pos, normal: Vector3::new(0.0, 0.0, 0.0), beta, delta: false, pdf_fwd: prob, pdf_rev: 0.0 })
+        }
+        LightRef::Emitter(f) => {
+            let u = (rng.next_f32() as Float, rng.next_f32() as Float);
+            let ((q, n), area) = (f.sample_surface(u)?, f.area()?);
+            let beta = f.get_material().emission.mult(area / prob);
+            Some(Vertex { kind: Kind::Light(*light), point: q, normal: n, beta, delta: false, pdf_fwd: prob / area, pdf_rev: 0.0 })
+        }
+    }
+}
+
+fn light_subpath<'a>(scene: &'a Scene, lights: &LightSampler<'a>, rng: &mut Rng, stats: &mut RayCounters) -> Vec<Vertex<'a>> {
+    let mut path = vec![];
+    let Some((light, prob)) = lights.pick(rng.next_f32() as Float) else { return path };
+    let Some(v) = light_vertex(light, prob, rng) else { return path };
+    let u = (rng.next_f32() as Float, rng.next_f32() as Float);
+    //Светящиеся поверхности излучают в обе стороны по закону Ламберта
+    let (ray, beta, pdf_dir) = match light {
+        LightRef::Point(_) => (Ray { pos: v.point, dir: uniform_sphere(u) }, v.beta.mult(4.0 * consts::PI * consts::PI), 1.0 / (4.0 * consts::PI)),
+        LightRef::Emitter(_) => {
+            let side = if rng.next_f32() < 0.5 { v.normal } else { -v.normal };
+            let dir = cosine_hemisphere(&side, u);
+            let pdf_dir = side.scalar_product(&dir) / (2.0 * consts::PI);
+            if pdf_dir <= 0.0 {
+                return path;
+            }
+            (Ray::spawn(&v.point, &side, dir), v.beta.mult(2.0 * consts::PI), pdf_dir)
+        }
+    };
+    path.push(v);
+    random_walk(scene, ray, beta, pdf_dir, rng, true, &mut path, stats);
+    path
+}
+
+//Продолжает подпуть из его последней вершины лучом ray. pdf_dir - плотность выбора ray.
+//Для подпути камеры возвращает луч, ушедший в фон, и его вклад.
+#[allow(clippy::too_many_arguments)]
+fn random_walk<'a>(scene: &'a Scene, mut ray: Ray, mut beta: Vector3, mut pdf_dir: Float, rng: &mut Rng, adjoint: bool, path: &mut Vec<Vertex<'a>>, stats: &mut RayCounters) -> Option<(Ray, Vector3)> {
+    let mut start = 0.0;
+    while path.len() < MAX_VERTICES {
+        let depth = path.len() - 1;
+        stats.traced_at_depth(depth as u32);
+        let Some((index, hit)) = closest_hit(scene, &ray, stats) else {
+            return (!adjoint).then_some((ray, beta));
+        };
+        let prev = path[depth];
+        //Свет точечного источника ослабевает по его Falloff вместо 1 / d²
+        if let Some(l) = prev.point_light() {
+            beta = beta.mult(l.falloff.apply(hit.t * hit.t, hit.t));
+        }
+        if depth == 0 {
+            start = beta.x.max(beta.y).max(beta.z);
+        }
+        let mut v = Vertex { kind: Kind::Surface(hit, index), point: hit.point, normal: hit.normal, beta, delta: false, pdf_fwd: 0.0, pdf_rev: 0.0 };
+        v.pdf_fwd = prev.density_at(pdf_dir, &v);
+        let m = hit.material();
+        let wo = -ray.dir;
+        let pdf_back;
+        if m.transparency > EPSILON && (rng.next_f32() as Float) < m.transparency {
+            let (next, weight) = dielectric_bounce(&ray, &hit, rng, adjoint, None, stats);
+            ray = next;
+            beta = beta.mult_per_element(&weight);
+            v.delta = true;
+            pdf_dir = 0.0;
+            pdf_back = 0.0;
+        } else {
+            let p = m.as_pbr();
+            let n = if hit.front_face { hit.normal } else { -hit.normal };
+            let u = [rng.next_f32() as Float, rng.next_f32() as Float, rng.next_f32() as Float];
+            let Some(s) = p.sample(&m.color, &n, &wo, u) else {
+                path.push(v);
+                break;
+            };
+            beta = beta.mult_per_element(&s.weight);
+            pdf_dir = s.pdf * Vertex::diffuse_part(&hit);
+            pdf_back = p.pdf(&n, &s.dir, &wo) * Vertex::diffuse_part(&hit);
+            ray = Ray::spawn(&hit.point, &hit.normal, s.dir);
+            stats.reflection += 1;
+        }
+        path[depth].pdf_rev = v.density_at(pdf_back, &prev);
+        path.push(v);
+        if beta.x.max(beta.y).max(beta.z) <= 0.0 {
+            break;
+        }
+        if depth >= ROULETTE_DEPTH as usize {
+            let q = (beta.x.max(beta.y).max(beta.z) / start).min(0.95);
+            if (rng.next_f32() as Float) >= q {
+                break;
+            }
+            beta = beta.div(q);
+        }
+    }
+    None
+}
+
+//Точка to видна из вершины поверхности from
+fn unoccluded(scene: &Scene, from: &HitRecord, to: &Vector3, stats: &mut RayCounters) -> bool {
+    let d = to - &from.point;
+    let ray = Ray::spawn(&from.point, &from.normal, d.normalize());
+    //Точка на поверхности не должна перекрываться самой поверхностью
+    let t_max = d.len() - (ray.pos - from.point).len() - Ray { pos: *to, dir: ray.dir }.t_min();
+    stats.shadow += 1;
+    !occluded(scene, &ray, ray.t_min(), t_max, stats)
+}
+
+//Вклад пути из s вершин подпути источника и t вершин подпути камеры с весом MIS.
+//При s = 1 точка на источнике выбирается заново, как в прямом освещении.
+#[allow(clippy::too_many_arguments)]
+fn connect<'a>(scene: &'a Scene, lights: &LightSampler<'a>, light_path: &[Vertex<'a>], camera: &[Vertex<'a>], s: usize, t: usize, rng: &mut Rng, stats: &mut RayCounters) -> Vector3 {
+    let zero = Vector3::new(0.0, 0.0, 0.0);
+    let pt = &camera[t - 1];
+    let Kind::Surface(hit, index) = pt.kind else { return zero };
+    let to_camera = (camera[t - 2].point - pt.point).normalize();
+    let (radiance, sampled) = match s {
+        0 => {
+            let m = hit.material();
+            if !m.is_emissive() {
+                return zero;
+            }
+            //Фигуру, которую нельзя выбрать как источник, находят только лучи камеры
+            if lights.figure_weight(index) <= 0.0 {
+                return pt.beta.mult_per_element(&m.emission);
+            }
+            (pt.beta.mult_per_element(&m.emission), None)
+        }
+        1 => {
+            let Some((light, prob)) = lights.pick(rng.next_f32() as Float) else { return zero };
+            let Some(q) = light_vertex(light, prob, rng) else { return zero };
+            let d = q.point - pt.point;
+            let dir = d.normalize();
+            let f = pt.eval(&to_camera, &dir);
+            if f.len_sq() <= 0.0 || !unoccluded(scene, &hit, &q.point, stats) {
+                return zero;
+            }
+            //Как в pbr_light: BRDF * cos умножается на π
+            let light = match light {
+                LightRef::Point(l) => q.beta.mult(l.falloff.apply(consts::PI, d.len())),
+                LightRef::Emitter(_) => q.beta.mult(q.normal.scalar_product(&dir).abs() / d.len_sq()),
+            };
+            (pt.beta.mult_per_element(&f).mult_per_element(&light), Some(q))
+        }
+        _ => {
+            let qs = &light_path[s - 1];
+            let Kind::Surface(..) = qs.kind else { return zero };
+            let d = qs.point - pt.point;
+            let dir = d.normalize();
+            let f_camera = pt.eval(&to_camera, &dir);
+            let f_light = qs.eval(&(light_path[s - 2].point - qs.point).normalize(), &-dir);
+            if f_camera.len_sq() <= 0.0 || f_light.len_sq() <= 0.0 || !unoccluded(scene, &hit, &qs.point, stats) {
+                return zero;
+            }
+            (pt.beta.mult_per_element(&f_camera).mult_per_element(&f_light).mult_per_element(&qs.beta).div(d.len_sq()), None)
+        }
+    };
+    radiance.mult(mis_weight(lights, light_path, camera, sampled.as_ref(), s, t))
+}
+
+//Вес стратегии (s, t) по степенной эвристике среди всех стратегий с t ≥ 2, которые могли
+//построить тот же путь. Плотности соединяемых вершин пересчитываются для этого пути.
+fn mis_weight(lights: &LightSampler, light_path: &[Vertex], camera: &[Vertex], sampled: Option<&Vertex>, s: usize, t: usize) -> Float {
+    //(pdf_rev, pdf_fwd, delta)
+    let mut cam: Vec<(Float, Float, bool)> = camera[..t].iter().map(|v| (v.pdf_rev, v.pdf_fwd, v.delta)).collect();
+    let light_vertices: Vec<&Vertex> = match sampled {
+        Some(q) => vec![q],
+        None => light_path[..s].iter().collect(),
+    };
+    let mut lig: Vec<(Float, Float, bool)> = light_vertices.iter().map(|v| (v.pdf_rev, v.pdf_fwd, v.delta)).collect();
+    let pt = &camera[t - 1];
+    let pt_minus = &camera[t - 2];
+    match light_vertices.last() {
+        Some(qs) => {
+            let qs_minus = (s > 1).then(|| light_vertices[s - 2]);
+            cam[t - 1].0 = qs.pdf(qs_minus, pt);
+            cam[t - 2].0 = pt.pdf(Some(qs), pt_minus);
+            lig[s - 1].0 = pt.pdf(Some(pt_minus), qs);
+            lig[s - 1].2 = false;
+            if let Some(qs_minus) = qs_minus {
+                lig[s - 2].0 = qs.pdf(Some(pt), qs_minus);
+            }
+        }
+        None => {
+            //Камера попала в источник: его точка и направление, как при выборе источника
+            let Kind::Surface(hit, index) = pt.kind else { return 0.0 };
+            let area = hit.figure.area().unwrap_or(0.0);
+            cam[t - 1].0 = if area > 0.0 { lights.figure_weight(index) / area } else { 0.0 };
+            let emitter = Vertex { kind: Kind::Light(LightRef::Emitter(hit.figure)), ..*pt };
+            cam[t - 2].0 = emitter.pdf(None, pt_minus);
+        }
+    }
+    cam[t - 1].2 = false;
+    let remap = |p: Float| if p != 0.0 { p } else { 1.0 };
+    let mut sum = 0.0;
+    let mut ri = 1.0;
+    for i in (2..t).rev() {
+        ri *= remap(cam[i].0) / remap(cam[i].1);
+        if !cam[i].2 && !cam[i - 1].2 {
+            sum += ri;
+        }
+    }
+    ri = 1.0;
+    for i in (0..s).rev() {
+        ri *= remap(lig[i].0) / remap(lig[i].1);
+        let delta_light = if i > 0 { lig[i - 1].2 } else { light_vertices[0].is_point_light() };
+        if !lig[i].2 && !delta_light {
+            sum += ri;
+        }
+    }
+    1.0 / (1.0 + sum)
+}
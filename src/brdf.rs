@@ -0,0 +1,111 @@
+use crate::math::{consts, Float, Vector3};
+
+//Отражательная способность диэлектриков при нормальном падении (IOR 1.5)
+pub const DIELECTRIC_F0: Float = 0.04;
+//Меньшая шероховатость вырождает распределение GGX в дельта-функцию
+pub const MIN_ROUGHNESS: Float = 0.03;
+
+//Модель metallic/roughness, как в glTF 2.0: базовый цвет - альбедо диэлектрика
+//или цвет отражения металла, roughness перцептивная (alpha = roughness^2)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pbr {
+    pub metallic: Float,
+    pub roughness: Float,
+}
+
+//Направление, выбранное по BRDF, и вес f * cos / pdf
+#[derive(Debug, Clone, Copy)]
+pub struct BrdfSample {
+    pub dir: Vector3,
+    pub weight: Vector3,
+    pub pdf: Float,
+}
+
+//Распределение нормалей микрограней GGX (Trowbridge-Reitz)
+pub fn ggx_d(n_h: Float, alpha: Float) -> Float {
+    if n_h <= 0.0 {
+        return 0.0;
+    }
+    let a2 = alpha * alpha;
+    let d = n_h * n_h * (a2 - 1.0) + 1.0;
+    a2 / (consts::PI * d * d)
+}
+//Маскирование Смита для GGX в одном направлении
+pub fn smith_g1(n_x: Float, alpha: Float) -> Float {
+    let a2 = alpha * alpha;
+    2.0 * n_x / (n_x + (a2 + (1.0 - a2) * n_x * n_x).sqrt())
+}
+//Приближение Шлика
+pub fn fresnel_schlick(f0: &Vector3, cos: Float) -> Vector3 {
+    let k = (1.0 - cos).clamp(0.0, 1.0).powi(5);
+    f0 + &(Vector3::new(1.0, 1.0, 1.0) - *f0).mult(k)
+}
+
+impl Pbr {
+    pub fn alpha(&self) -> Float {
+        let r = self.roughness.max(MIN_ROUGHNESS);
+        r * r
+    }
+    pub fn f0(&self, base_color: &Vector3) -> Vector3 {
+        Vector3::new(DIELECTRIC_F0, DIELECTRIC_F0, DIELECTRIC_F0).lerp(base_color, self.metallic)
+    }
+    //BRDF, умноженная на косинус с l. Все векторы единичные, v и l направлены от поверхности.
+    //Диффузная часть получает только то, что не отразилось по Френелю, у металла её нет.
+    pub fn eval(&self, base_color: &Vector3, n: &Vector3, v: &Vector3, l: &Vector3) -> Vector3 {
+        let (n_v, n_l) = (n.scalar_product(v), n.scalar_product(l));
+        if n_v <= 0.0 || n_l <= 0.0 {
+            return Vector3::new(0.0, 0.0, 0.0);
+        }
+        let h = (v + l).normalize();
+        let alpha = self.alpha();
+        let f = fresnel_schlick(&self.f0(base_color), v.scalar_product(&h));
+        let spec = ggx_d(n.scalar_product(&h), alpha) * smith_g1(n_v, alpha) * smith_g1(n_l, alpha) / (4.0 * n_v * n_l);
+        //Френель на входе и на выходе: BRDF остаётся симметричной
+        let one = Vector3::new(1.0, 1.0, 1.0);
+        let f0 = self.f0(base_color);
+        let transmitted = (one - fresnel_schlick(&f0, n_v)).mult_per_element(&(one - fresnel_schlick(&f0, n_l)));
+        let diffuse = transmitted.mult_per_element(base_color).mult((1.0 - self.metallic) / consts::PI);
+        (f.mult(spec) + diffuse).mult(n_l)
+    }
+    //Доля зеркального отражения для трассировки Уиттеда, где размытого отражения нет:
+    //Френель, ослабленный шероховатостью
+    pub fn mirror_reflectance(&self, base_color: &Vector3, n_v: Float) -> Vector3 {
+        let smooth = 1.0 - self.roughness.clamp(0.0, 1.0);
+        fresnel_schlick(&self.f0(base_color), n_v).mult(smooth * smooth)
+    }
+    //Доля сэмплов, выбираемых по GGX, остальные - по косинусу
+    fn specular_probability(&self) -> Float {
+        0.5 + 0.5 * self.metallic
+    }
+    //Плотность sample() по телесному углу
+    pub fn pdf(&self, n: &Vector3, v: &Vector3, l: &Vector3) -> Float {
+        let (n_v, n_l) = (n.scalar_product(v), n.scalar_product(l));
+        if n_v <= 0.0 || n_l <= 0.0 {
+            return 0.0;
+        }
+        let h = (v + l).normalize();
+        let spec = ggx_d(n.scalar_product(&h), self.alpha()) * n.scalar_product(&h) / (4.0 * v.scalar_product(&h));
+        let p = self.specular_probability();
+        p * spec + (1.0 - p) * n_l / consts::PI
+    }
+    //u - три равномерных числа из [0, 1): выбор лепестка и точка на нём
+    pub fn sample(&self, base_color: &Vector3, n: &Vector3, v: &Vector3, u: [Float; 3]) -> Option<BrdfSample> {
+        let (t, b) = n.orthonormal_basis();
+        let phi = 2.0 * consts::PI * u[2];
+        let local = |sin: Float, cos: Float| t.mult(sin * phi.cos()) + b.mult(sin * phi.sin()) + n.mult(cos);
+        let dir = if u[0] < self.specular_probability() {
+            let alpha = self.alpha();
+            let cos = ((1.0 - u[1]) / (1.0 + (alpha * alpha - 1.0) * u[1])).sqrt();
+            let h = local((1.0 - cos * cos).max(0.0).sqrt(), cos);
+            h.mult(2.0 * v.scalar_product(&h)) - *v
+        } else {
+            local(u[1].sqrt(), (1.0 - u[1]).sqrt())
+        };
+        let pdf = self.pdf(n, v, &dir);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let weight = self.eval(base_color, n, v, &dir).div(pdf);
+        Some(BrdfSample { dir, weight, pdf })
+    }
+}
@@ -1,4 +1,4 @@
-use crate::math::Vector3;
+use crate::math::{Float, Vector3};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Color {
@@ -13,9 +13,13 @@ impl Color {
     pub const BLACK: Self = Color{r:0, g: 0, b: 0};
     pub const WHITE: Self = Color{r: 255, g: 255, b: 255};
     pub fn to_vector3(&self) -> Vector3 {
-        Vector3 { x: self.r as f32 / 255.0, y: self.g as f32 / 255.0, z: self.b as f32 / 255.0 }
+        Vector3 { x: self.r as Float / 255.0, y: self.g as Float / 255.0, z: self.b as Float / 255.0 }
     }
     pub fn from_vector3(v: &Vector3) -> Self {
         Color { r: (v.x.clamp(0.0, 1.0) * 255.0) as u8, g: (v.y.clamp(0.0, 1.0) * 255.0) as u8, b: (v.z.clamp(0.0, 1.0) * 255.0) as u8 }
     }
-}
\ No newline at end of file
+}
+//Яркость линейного RGB (Rec. 709)
+pub fn luminance(c: &Vector3) -> Float {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
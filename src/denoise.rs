@@ -0,0 +1,118 @@
+use rayon::prelude::*;
+
+use crate::{aov::AovBuffers, color::luminance, math::{Float, Vector3}};
+
+//Веса сплайна B3 для à-trous фильтра
+const KERNEL: [Float; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+#[derive(Debug, Clone, Copy)]
+pub struct DenoiseSettings {
+    pub iterations: u32,
+    pub sigma_color: Float,
+    pub sigma_normal: Float,
+    pub sigma_depth: Float,
+    pub sigma_albedo: Float,
+}
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            sigma_color: 0.5,
+            sigma_normal: 0.1,
+            sigma_depth: 0.05,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+//Edge-avoiding à-trous (Dammertz et al. 2010).
+//Освещённость отделяется от альбедо перед фильтрацией, чтобы не размывать текстуры,
+//а глубина, нормаль и альбедо останавливают фильтр на границах объектов.
+//Фильтруется линейная яркость из render_radiance: после обрезки до 8 бит деление
+//на тёмное альбедо превращает шаги квантования в большие ошибки.
+pub fn denoise(color: &[Vector3], aov: &AovBuffers, settings: &DenoiseSettings) -> Vec<Vector3> {
+    let (w, h) = (aov.width, aov.height);
+    assert_eq!(color.len(), w * h);
+    let albedo: Vec<_> = aov.pixels.iter().map(|p| p.albedo).collect();
+    let mut current: Vec<_> = color.iter().zip(&albedo)
+        .map(|(c, a)| demodulate(c, a))
+        .collect();
+    let mut next = current.clone();
+    for i in 0..settings.iterations {
+        let step = 1usize << i;
+        let sigma_color = settings.sigma_color / (1 << i) as Float;
+        next.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+            for (x, out) in row.iter_mut().enumerate() {
+                *out = filter_pixel(&current, aov, settings, sigma_color, x, y, step);
+            }
+        });
+        std::mem::swap(&mut current, &mut next);
+    }
+    current.iter().zip(&albedo)
+        .map(|(c, a)| remodulate(c, a))
+        .collect()
+}
+
+fn filter_pixel(
+    c: &[Vector3],
+    aov: &AovBuffers,
+    s: &DenoiseSettings,
+    sigma_color: Float,
+    x: usize,
+    y: usize,
+    step: usize,
+) -> Vector3 {
+    let (w, h) = (aov.width as isize, aov.height as isize);
+    let i = y * aov.width + x;
+    let p = &aov.pixels[i];
+    let mut sum = Vector3::new(0.0, 0.0, 0.0);
+    let mut weight = 0.0;
+    for (ky, hy) in KERNEL.iter().enumerate() {
+        let qy = y as isize + (ky as isize - 2) * step as isize;
+        if qy < 0 || qy >= h { continue; }
+        for (kx, hx) in KERNEL.iter().enumerate() {
+            let qx = x as isize + (kx as isize - 2) * step as isize;
+            if qx < 0 || qx >= w { continue; }
+            let j = qy as usize * aov.width + qx as usize;
+            let q = &aov.pixels[j];
+            if p.figure.is_some() != q.figure.is_some() { continue; }
+
+            let w_color = (-(compress(&c[i]) - compress(&c[j])).len_sq() / sigma_color.powi(2)).exp();
+            let w_normal = (-(p.normal - q.normal).len_sq() / s.sigma_normal.powi(2)).exp();
+            let w_albedo = (-(p.albedo - q.albedo).len_sq() / s.sigma_albedo.powi(2)).exp();
+            //Нулевая или бесконечная глубина (фон) дала бы деление на 0
+            let usable = |d: Float| d.is_finite() && d > 0.0;
+            let w_depth = if usable(p.depth) && usable(q.depth) {
+                (-(p.depth - q.depth).abs() / (s.sigma_depth * p.depth * step as Float)).exp()
+            } else { 1.0 };
+
+            let k = hx * hy * w_color * w_normal * w_albedo * w_depth;
+            sum += c[j].mult(k);
+            weight += k;
+        }
+    }
+    sum.div(weight)
+}
+
+//Яркости сравниваются после сжатия c / (1 + c): иначе одиночный яркий выброс в HDR
+//отличается от соседей сильнее любой границы и остаётся несглаженным
+#[inline(always)]
+fn compress(c: &Vector3) -> Vector3 {
+    c.div(1.0 + luminance(c))
+}
+#[inline(always)]
+fn demodulate(c: &Vector3, albedo: &Vector3) -> Vector3 {
+    Vector3::new(safe_div(c.x, albedo.x), safe_div(c.y, albedo.y), safe_div(c.z, albedo.z))
+}
+#[inline(always)]
+fn remodulate(c: &Vector3, albedo: &Vector3) -> Vector3 {
+    Vector3::new(
+        if albedo.x > 0.0 { c.x * albedo.x } else { c.x },
+        if albedo.y > 0.0 { c.y * albedo.y } else { c.y },
+        if albedo.z > 0.0 { c.z * albedo.z } else { c.z },
+    )
+}
+#[inline(always)]
+fn safe_div(a: Float, b: Float) -> Float {
+    if b > 0.0 { a / b } else { a }
+}
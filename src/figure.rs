@@ -1,10 +1,13 @@
-use std::mem::swap;
+use std::{mem::swap, sync::Arc};
 
 use crate::{
     material::Material,
-    math::{Ray, Vector3, EPSILON},
+    math::{consts, Float, Ray, Transform, Vector3, EPSILON},
+    sdf::{Sdf, HIT_EPSILON},
 };
 
+//Фигуры хранятся подряд в векторе сцены: крупный куб без косвенности быстрее пересекается
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub enum FigureKind {
     Side { pos: [Vector3; 3], normal: Vector3, m: Material },
@@ -15,9 +18,49 @@ pub enum FigureKind {
     //Передняя нижняя правая, Передняя нижняя левая
     //Нормали: верхняя, задняя, правая, передняя, левая, нижняя
     Cube { pos: [Vector3; 8], normals: [Vector3; 6], m: Material },
-    Sphere { r: f32, pos: Vector3, m: Material },
+    Sphere { r: Float, pos: Vector3, m: Material },
+    //Бесконечная плоскость через точку pos
+    Plane { pos: Vector3, normal: Vector3, m: Material },
+    Disk { pos: Vector3, normal: Vector3, r: Float, m: Material },
+    //Цилиндр с крышками: центр нижнего основания, единичная ось к верхнему, радиус и высота
+    Cylinder { pos: Vector3, axis: Vector3, r: Float, h: Float, m: Material },
+    //Конус с основанием: центр основания, единичная ось к вершине, радиус основания и высота
+    Cone { pos: Vector3, axis: Vector3, r: Float, h: Float, m: Material },
+    //Тор: центр, единичная ось симметрии, радиус окружности центров трубки и радиус трубки
+    Torus { pos: Vector3, axis: Vector3, big_r: Float, r: Float, m: Material },
+    //Тело, заданное функцией расстояния, пересекается сфера-трассировкой
+    Sdf { sdf: Sdf, m: Material },
+    //Общая геометрия, размещённая в сцене преобразованием
+    Instance { figure: Arc<FigureKind>, transform: Transform },
+    //Булева операция над замкнутыми телами. Плоскость считается полупространством за нормалью.
+    Csg { op: CsgOp, a: Arc<FigureKind>, b: Arc<FigureKind> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+}
+impl CsgOp {
+    fn inside(&self, a: bool, b: bool) -> bool {
+        match self {
+            CsgOp::Union => a || b,
+            CsgOp::Intersection => a && b,
+            CsgOp::Difference => a && !b,
+        }
+    }
+}
+
+//Отрезок луча внутри тела. enter равен None, если луч начинается внутри,
+//exit - если тело не замкнуто вдоль луча
+#[derive(Debug, Clone, Copy)]
+pub struct Span<'a> {
+    pub enter: Option<HitRecord<'a>>,
+    pub exit: Option<HitRecord<'a>>,
 }
 impl FigureKind {
+    pub const KIND_NAMES: [&'static str; 11] = ["side", "cube", "sphere", "plane", "disk", "cylinder", "cone", "torus", "sdf", "instance", "csg"];
     //Вектор нормали смотрит по направлению взгляда на углы.
     pub fn new_side(top_left: &Vector3, top_right: &Vector3, down_left: &Vector3, m: Material) -> Self {
         let normal = Self::plane_normal(top_left, top_right, down_left);
@@ -36,7 +79,7 @@ impl FigureKind {
         let front_top_right = front_top_left + &w_v;
         let front_down_left = front_top_left + &h_v;
         let back_down_right = back_down_left + &w_v;
-        let front_down_right = front_down_left + h_v;
+        let front_down_right = front_down_left + w_v;
         let top_normal = -Self::plane_normal(back_top_left, back_top_right, front_top_left);
         let down_normal = -top_normal;
         let back_normal = Self::plane_normal(back_top_left, back_top_right, back_down_left);
@@ -50,111 +93,323 @@ impl FigureKind {
             ], m}
         
     }
+    pub fn new_plane(pos: &Vector3, normal: &Vector3, m: Material) -> Self {
+        Self::Plane { pos: *pos, normal: normal.normalize(), m }
+    }
+    pub fn new_disk(pos: &Vector3, normal: &Vector3, r: Float, m: Material) -> Self {
+        Self::Disk { pos: *pos, normal: normal.normalize(), r, m }
+    }
+    pub fn new_cylinder(base: &Vector3, top: &Vector3, r: Float, m: Material) -> Self {
+        let d = top - base;
+        Self::Cylinder { pos: *base, axis: d.normalize(), r, h: d.len(), m }
+    }
+    pub fn new_cone(base: &Vector3, apex: &Vector3, r: Float, m: Material) -> Self {
+        let d = apex - base;
+        Self::Cone { pos: *base, axis: d.normalize(), r, h: d.len(), m }
+    }
+    pub fn new_torus(pos: &Vector3, axis: &Vector3, big_r: Float, r: Float, m: Material) -> Self {
+        Self::Torus { pos: *pos, axis: axis.normalize(), big_r, r, m }
+    }
+    pub fn new_instance(figure: &Arc<FigureKind>, transform: Transform) -> Self {
+        Self::Instance { figure: figure.clone(), transform }
+    }
+    pub fn new_csg(op: CsgOp, a: &Arc<FigureKind>, b: &Arc<FigureKind>) -> Self {
+        Self::Csg { op, a: a.clone(), b: b.clone() }
+    }
     pub fn get_material(&self) -> &Material {
         match self {
             FigureKind::Side { m, .. } => m,
             FigureKind::Cube { m, .. } => m,
             FigureKind::Sphere { m, .. } => m,
+            FigureKind::Plane { m, .. } => m,
+            FigureKind::Disk { m, .. } => m,
+            FigureKind::Cylinder { m, .. } => m,
+            FigureKind::Cone { m, .. } => m,
+            FigureKind::Torus { m, .. } => m,
+            FigureKind::Sdf { m, .. } => m,
+            FigureKind::Instance { figure, .. } => figure.get_material(),
+            FigureKind::Csg { a, .. } => a.get_material(),
         }
     }   
-    pub fn intersect(&self, ray: &Ray) -> Option<Vector3> {
+    pub fn kind_index(&self) -> usize {
         match self {
-            FigureKind::Side { pos, normal, .. } => Self::rectangle_intersect(ray, &pos[0], &pos[1], &pos[2], normal),
-            FigureKind::Cube { pos, normals, .. } => Self::cube_intersect(ray, pos, normals).map(|x|x.0),
-            FigureKind::Sphere { r, pos, .. } => Self::sphere_intersect(ray, *r, pos),
+            FigureKind::Side { .. } => 0,
+            FigureKind::Cube { .. } => 1,
+            FigureKind::Sphere { .. } => 2,
+            FigureKind::Plane { .. } => 3,
+            FigureKind::Disk { .. } => 4,
+            FigureKind::Cylinder { .. } => 5,
+            FigureKind::Cone { .. } => 6,
+            FigureKind::Torus { .. } => 7,
+            FigureKind::Sdf { .. } => 8,
+            FigureKind::Instance { .. } => 9,
+            FigureKind::Csg { .. } => 10,
         }
     }
-    //Точка пересечения и нормаль
-    pub fn intersect_with_normal(&self, ray: &Ray) -> Option<(Vector3, Vector3)> {
+    //Площадь поверхности фигур, которые могут служить источником света
+    pub fn area(&self) -> Option<Float> {
         match self {
-            FigureKind::Side { pos, normal, ..} => Self::rectangle_intersect(ray, &pos[0], &pos[1], &pos[2], normal).map(|x|(x, *normal)),
-            FigureKind::Cube { pos, normals, .. } => Self::cube_intersect(ray, pos, normals).map(|x|(x.0, normals[x.1])),
-            FigureKind::Sphere { r, pos, .. } => Self::sphere_intersect(ray, *r, pos).map(|x|
-                (x, (&x - pos).normalize())
-            ),
+            FigureKind::Side { pos, .. } => Some((pos[1] - pos[0]).cross_product(&(pos[2] - pos[0])).len()),
+            FigureKind::Sphere { r, .. } => Some(4.0 * consts::PI * r * r),
+            FigureKind::Disk { r, .. } => Some(consts::PI * r * r),
+            _ => None,
         }
     }
-    pub fn plane_normal(pp1: &Vector3, pp2: &Vector3, pp3: &Vector3) -> Vector3 {
-        let v1 = pp1 - pp2;
-        let v2 = pp1 - pp3;
-        v1.cross_product(&v2).normalize()
+    //Точка поверхности, равномерно распределённая по площади, и нормаль в ней.
+    //u - два числа из [0, 1).
+    pub fn sample_surface(&self, u: (Float, Float)) -> Option<(Vector3, Vector3)> {
+        match self {
+            FigureKind::Side { pos, normal, .. } => {
+                Some((pos[0] + (pos[1] - pos[0]).mult(u.0) + (pos[2] - pos[0]).mult(u.1), *normal))
+            }
+            FigureKind::Sphere { r, pos, .. } => {
+                let z = 1.0 - 2.0 * u.0;
+                let s = (1.0 - z * z).max(0.0).sqrt();
+                let phi = 2.0 * consts::PI * u.1;
+                let n = Vector3::new(s * phi.cos(), s * phi.sin(), z);
+                Some((*pos + n.mult(*r), n))
+            }
+            FigureKind::Disk { pos, normal, r, .. } => {
+                let (a, b) = normal.orthonormal_basis();
+                let (d, phi) = (r * u.0.sqrt(), 2.0 * consts::PI * u.1);
+                Some((*pos + a.mult(d * phi.cos()) + b.mult(d * phi.sin()), *normal))
+            }
+            _ => None,
+        }
     }
-    pub fn cube_intersect(r: &Ray, dots: &[Vector3; 8], normals: &[Vector3; 6]) -> Option<(Vector3, usize)> {
-        let mut d = f32::MAX;
-        let mut t = (Vector3::new(0.0, 0.0, 0.0), usize::MAX);
-        if let Some(p) = Self::rectangle_intersect(r, &dots[0], &dots[1], &dots[4], &normals[0]) {
-            let tmp = (p - r.pos).len_sq();
-            if tmp < d {
-                d = tmp;
-                t = (p, 0)
+    //Ближайшее пересечение со всеми данными для шейдинга
+    pub fn hit(&self, ray: &Ray) -> Option<HitRecord<'_>> {
+        self.hit_in(ray, ray.t_min(), Float::INFINITY)
+    }
+    //Ближайшее пересечение с t из интервала [t_min, t_max]
+    pub fn hit_in(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let (t, normal, uv) = match self {
+            FigureKind::Side { pos, normal, .. } => {
+                let (t, uv) = Self::rectangle_hit(ray, &pos[0], &pos[1], &pos[2], normal, t_min, t_max)?;
+                (t, *normal, uv)
+            }
+            FigureKind::Cube { pos, normals, .. } => {
+                let (t, face, uv) = Self::cube_hit(ray, pos, normals, t_min, t_max)?;
+                (t, normals[face], uv)
+            }
+            FigureKind::Sphere { r, pos, .. } => {
+                let t = Self::sphere_hit(ray, *r, pos, t_min, t_max)?;
+                let n = (ray.point_from_t(t) - *pos).normalize();
+                (t, n, Self::sphere_uv(&n))
+            }
+            FigureKind::Plane { pos, normal, .. } => {
+                let t = Self::plane_hit(ray, pos, normal, t_min, t_max)?;
+                let (e1, e2) = normal.orthonormal_basis();
+                let q = ray.point_from_t(t) - *pos;
+                (t, *normal, (q.scalar_product(&e1), q.scalar_product(&e2)))
+            }
+            FigureKind::Disk { pos, normal, r, .. } => {
+                let t = Self::disk_hit(ray, pos, normal, *r, t_min, t_max)?;
+                (t, *normal, Self::polar_uv(&(ray.point_from_t(t) - *pos), normal, *r))
+            }
+            FigureKind::Cylinder { pos, axis, r, h, .. } => Self::cylinder_hit(ray, pos, axis, *r, *h, t_min, t_max)?,
+            FigureKind::Cone { pos, axis, r, h, .. } => Self::cone_hit(ray, pos, axis, *r, *h, t_min, t_max)?,
+            FigureKind::Torus { pos, axis, big_r, r, .. } => {
+                let t = Self::torus_hit(ray, pos, axis, *big_r, *r, t_min, t_max)?;
+                let (n, uv) = Self::torus_normal_uv(&(ray.point_from_t(t) - *pos), axis, *big_r);
+                (t, n, uv)
+            }
+            FigureKind::Sdf { sdf, .. } => {
+                let t = sdf.march(ray, t_min, t_max)?;
+                let p = ray.point_from_t(t);
+                let n = sdf.normal(&p, HIT_EPSILON * t.max(1.0));
+                (t, n, Self::sphere_uv(&n))
+            }
+            FigureKind::Instance { figure, transform } => {
+                let (local, len) = Self::instance_ray(ray, transform);
+                let h = figure.hit_in(&local, t_min * len, t_max * len)?;
+                let mut res = HitRecord::new(ray, h.t / len, transform.transform_normal(&h.normal), h.uv, self);
+                res.material = h.material;
+                return Some(res);
+            }
+            //Ближайшая граница результата операции
+            FigureKind::Csg { .. } => {
+                return self.spans(ray, t_min).iter()
+                    .flat_map(|s| [s.enter, s.exit])
+                    .flatten()
+                    .next()
+                    .filter(|h| h.t <= t_max);
+            }
+        };
+        Some(HitRecord::new(ray, t, normal, uv, self))
+    }
+    //Есть ли хоть одно пересечение в интервале. Не ищет ближайшее и не считает нормали,
+    //поэтому дешевле hit_in для теневых лучей
+    pub fn occludes(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        match self {
+            FigureKind::Side { pos, normal, .. } => Self::rectangle_hit(ray, &pos[0], &pos[1], &pos[2], normal, t_min, t_max).is_some(),
+            FigureKind::Cube { pos, normals, .. } => Self::CUBE_FACES.iter().enumerate().any(|(i, [a, b, c])|
+                Self::rectangle_hit(ray, &pos[*a], &pos[*b], &pos[*c], &normals[i], t_min, t_max).is_some()
+            ),
+            FigureKind::Sphere { r, pos, .. } => Self::sphere_hit(ray, *r, pos, t_min, t_max).is_some(),
+            FigureKind::Plane { pos, normal, .. } => Self::plane_hit(ray, pos, normal, t_min, t_max).is_some(),
+            FigureKind::Disk { pos, normal, r, .. } => Self::disk_hit(ray, pos, normal, *r, t_min, t_max).is_some(),
+            FigureKind::Cylinder { .. } | FigureKind::Cone { .. } | FigureKind::Torus { .. } | FigureKind::Sdf { .. } | FigureKind::Csg { .. } => {
+                self.hit_in(ray, t_min, t_max).is_some()
+            }
+            FigureKind::Instance { figure, transform } => {
+                let (local, len) = Self::instance_ray(ray, transform);
+                figure.occludes(&local, t_min * len, t_max * len)
             }
         }
-        
-        
-        if let Some(p) = Self::rectangle_intersect(r, &dots[0], &dots[1], &dots[3], &normals[1]) {
-            let tmp = (p - r.pos).len_sq();
-            if tmp < d {
-                d = tmp;
-                t = (p, 1)
+    }
+    //Отрезки луча с t от t_min, лежащие внутри тела, по возрастанию t.
+    //Для простых фигур собираются из последовательных пересечений: вход - пересечение
+    //с лицевой стороной, выход - с обратной.
+    pub fn spans(&self, ray: &Ray, t_min: Float) -> Vec<Span<'_>> {
+        if let FigureKind::Csg { op, a, b } = self {
+            return Self::combine(*op, &a.spans(ray, t_min), &b.spans(ray, t_min), self);
+        }
+        const MAX_CROSSINGS: usize = 32;
+        let mut res = vec![];
+        let mut open: Option<HitRecord> = None;
+        let mut t = t_min;
+        for _ in 0..MAX_CROSSINGS {
+            let Some(h) = self.hit_in(ray, t, Float::INFINITY) else { break };
+            if h.front_face {
+                open = open.or(Some(h));
+            } else if open.is_some() || res.is_empty() {
+                res.push(Span { enter: open.take(), exit: Some(h) });
             }
+            //Следующее пересечение ищется за ошибкой округления найденной точки
+            t = h.t + Ray { pos: h.point, dir: ray.dir }.t_min() / ray.dir.len();
+        }
+        if open.is_some() {
+            res.push(Span { enter: open, exit: None });
         }
-        if let Some(p) = Self::rectangle_intersect(r, &dots[1], &dots[5], &dots[2], &normals[2]) {
-            let tmp = (p - r.pos).len_sq();
-            if tmp < d {
-                d = tmp;
-                t = (p, 2)
+        //Луч, не пересекающий плоскость, целиком внутри её полупространства или целиком снаружи
+        if let (true, FigureKind::Plane { pos, normal, .. }) = (res.is_empty(), self) {
+            if (ray.point_from_t(t_min) - *pos).scalar_product(normal) < 0.0 {
+                res.push(Span { enter: None, exit: None });
             }
         }
-        if let Some(p) = Self::rectangle_intersect(r, &dots[4], &dots[5], &dots[7], &normals[3]) {
-            let tmp = (p - r.pos).len_sq();
-            if tmp < d {
-                d = tmp;
-                t = (p, 3)
+        res
+    }
+    //Отрезки результата булевой операции. Границы b в разности смотрят внутрь полости
+    //и получают материал a.
+    fn combine<'a>(op: CsgOp, a: &[Span<'a>], b: &[Span<'a>], node: &'a FigureKind) -> Vec<Span<'a>> {
+        let starts_inside = |s: &[Span]| s.first().is_some_and(|s| s.enter.is_none());
+        let mut events: Vec<(bool, bool, HitRecord)> = vec![];
+        for (is_b, spans) in [(false, a), (true, b)] {
+            for s in spans {
+                events.extend(s.enter.map(|h| (is_b, true, h)));
+                events.extend(s.exit.map(|h| (is_b, false, h)));
             }
         }
-        if let Some(p) = Self::rectangle_intersect(r, &dots[4], &dots[0], &dots[7], &normals[4]) {
-            let tmp = (p - r.pos).len_sq();
-            if tmp < d {
-                d = tmp;
-                t = (p, 4)
+        events.sort_by(|x, y| x.2.t.total_cmp(&y.2.t));
+        let (mut in_a, mut in_b) = (starts_inside(a), starts_inside(b));
+        let mut inside = op.inside(in_a, in_b);
+        let mut open: Option<Option<HitRecord>> = if inside { Some(None) } else { None };
+        let mut res = vec![];
+        for (is_b, entering, h) in events {
+            if is_b { in_b = entering } else { in_a = entering }
+            let now = op.inside(in_a, in_b);
+            if now == inside {
+                continue;
+            }
+            inside = now;
+            let mut h = h;
+            h.figure = node;
+            if is_b && op == CsgOp::Difference {
+                h.normal = -h.normal;
+                h.front_face = !h.front_face;
+                h.material = node.get_material();
+            }
+            if inside {
+                open = Some(Some(h));
+            } else {
+                res.push(Span { enter: open.take().flatten(), exit: Some(h) });
             }
         }
-        if let Some(p) = Self::rectangle_intersect(r, &dots[3], &dots[2], &dots[7], &normals[5]) {
-            let tmp = (p - r.pos).len_sq();
-            if tmp < d {
-                t = (p, 5)
+        if let Some(enter) = open {
+            res.push(Span { enter, exit: None });
+        }
+        res
+    }
+    //Луч в локальных координатах экземпляра с единичным направлением и
+    //множитель, переводящий мировое t в локальное
+    fn instance_ray(ray: &Ray, transform: &Transform) -> (Ray, Float) {
+        let local = transform.inverse_ray(ray);
+        let len = local.dir.len();
+        (Ray { pos: local.pos, dir: local.dir.div(len) }, len)
+    }
+    pub fn intersect(&self, ray: &Ray) -> Option<Vector3> {
+        self.hit(ray).map(|h| h.point)
+    }
+    //Точка пересечения и нормаль
+    pub fn intersect_with_normal(&self, ray: &Ray) -> Option<(Vector3, Vector3)> {
+        self.hit(ray).map(|h| (h.point, h.normal))
+    }
+    pub fn plane_normal(pp1: &Vector3, pp2: &Vector3, pp3: &Vector3) -> Vector3 {
+        let v1 = pp1 - pp2;
+        let v2 = pp1 - pp3;
+        v1.cross_product(&v2).normalize()
+    }
+    //Грани заданы тремя углами: верхний левый, верхний правый, нижний левый
+    pub(crate) const CUBE_FACES: [[usize; 3]; 6] = [[0, 1, 4], [0, 1, 3], [1, 5, 2], [4, 5, 7], [4, 0, 7], [3, 2, 7]];
+    pub fn cube_intersect(r: &Ray, dots: &[Vector3; 8], normals: &[Vector3; 6]) -> Option<(Vector3, usize)> {
+        Self::cube_hit(r, dots, normals, 0.0, Float::INFINITY).map(|(t, face, _)| (r.point_from_t(t), face))
+    }
+    //t, номер грани и UV на этой грани
+    pub fn cube_hit(r: &Ray, dots: &[Vector3; 8], normals: &[Vector3; 6], t_min: Float, t_max: Float) -> Option<(Float, usize, (Float, Float))> {
+        let mut res: Option<(Float, usize, (Float, Float))> = None;
+        let mut t_max = t_max;
+        for (i, [a, b, c]) in Self::CUBE_FACES.iter().enumerate() {
+            if let Some((t, uv)) = Self::rectangle_hit(r, &dots[*a], &dots[*b], &dots[*c], &normals[i], t_min, t_max) {
+                if res.is_none_or(|x| t < x.0) {
+                    res = Some((t, i, uv));
+                    t_max = t;
+                }
             }
         }
-        if t.1 != usize::MAX {
-            Some(t)
-        } else {None}
+        res
     }
-    pub fn sphere_intersect(r: &Ray, radius: f32, pos: &Vector3) -> Option<Vector3> {
+    pub fn sphere_intersect(r: &Ray, radius: Float, pos: &Vector3) -> Option<Vector3> {
+        Self::sphere_hit(r, radius, pos, 0.0, Float::INFINITY).map(|t| r.point_from_t(t))
+    }
+    pub fn sphere_hit(r: &Ray, radius: Float, pos: &Vector3, t_min: Float, t_max: Float) -> Option<Float> {
         let l = pos - &r.pos;
+        let r2 = radius * radius;
         let tca = l.scalar_product(&r.dir);
-        if tca < 0.0 {return None};
-        let d2 = l.scalar_product(&l) - tca * tca;
-        if (d2) > radius {return None;}
-        let thc = (radius - d2).sqrt();
+        let l2 = l.scalar_product(&l);
+        //Центр позади луча, а начало луча снаружи - пересечений впереди нет
+        if tca < 0.0 && l2 > r2 {return None};
+        let d2 = l2 - tca * tca;
+        if d2 > r2 {return None;}
+        let thc = (r2 - d2).sqrt();
         let mut t0 = tca - thc;
         let mut t1 = tca + thc;
         if t0 > t1 {swap(&mut t0, &mut t1);}
-        if t0 < 0.0 {
+        if t0 < t_min {
             t0 = t1;
-            if t0 < 0.0 {return  None;}
         }
-        Some(r.point_from_t(t0))
+        if t0 < t_min || t0 > t_max {return None;}
+        Some(t0)
+    }
+    //Сферические координаты единичной нормали
+    pub fn sphere_uv(n: &Vector3) -> (Float, Float) {
+        let u = 0.5 + n.z.atan2(n.x) / (2.0 * consts::PI);
+        let v = n.y.clamp(-1.0, 1.0).acos() / consts::PI;
+        (u, v)
     }
-    //Return t from ray equation r.pos + r.dir * t.
     pub fn plane_intersect(r: &Ray, pp1: &Vector3, normal: &Vector3) -> Option<Vector3> {
+        Self::plane_hit(r, pp1, normal, 0.0, Float::INFINITY).map(|t| r.point_from_t(t))
+    }
+    //Return t from ray equation r.pos + r.dir * t.
+    pub fn plane_hit(r: &Ray, pp1: &Vector3, normal: &Vector3, t_min: Float, t_max: Float) -> Option<Float> {
         let denom = normal.scalar_product(&r.dir);
         if denom.abs() > EPSILON {
             let p010 = pp1 - &r.pos;
             let t = p010.scalar_product(normal) / denom;
-            if t <= EPSILON * 10.0 {
+            if t < t_min || t > t_max {
                 None
             } else {
-                Some(r.point_from_t(t))
+                Some(t)
             }
         } else {
             None
@@ -168,26 +423,240 @@ impl FigureKind {
         down_left: &Vector3,
         normal: &Vector3
     ) -> Option<Vector3> {
+        Self::rectangle_hit(r, top_left, top_right, down_left, normal, 0.0, Float::INFINITY).map(|(t, _)| r.point_from_t(t))
+    }
+    //t и UV, u идёт от верхнего левого угла к верхнему правому, v - к нижнему левому
+    pub fn rectangle_hit(
+        r: &Ray,
+        top_left: &Vector3,
+        top_right: &Vector3,
+        down_left: &Vector3,
+        normal: &Vector3,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(Float, (Float, Float))> {
 
         let b = top_left;
         let c = top_right;
         let e = down_left;
         let c_b = c - b;
         let e_b = e - b;
-        //let pn = e_b.cross_product(&c_b).normalize();
         
-        let a = Self::plane_intersect(r, top_left, normal)?;
+        let t = Self::plane_hit(r, top_left, normal, t_min, t_max)?;
+        let a = r.point_from_t(t) - *b;
         //https://math.stackexchange.com/questions/476608/how-to-check-if-point-is-within-a-rectangle-on-a-plane-in-3d-space
-        let t1 = a.scalar_product(&c_b);
-        let t2 = a.scalar_product(&e_b);
-        if b.scalar_product(&c_b) - EPSILON <= t1
-            && t1 <= c.scalar_product(&c_b) + EPSILON
-            && b.scalar_product(&e_b) - EPSILON <= t2
-            && t2 <= e.scalar_product(&e_b) + EPSILON
-        {
-            Some(a)
+        //Координаты в долях сторон, поэтому допуск не зависит от размера прямоугольника
+        let u = a.scalar_product(&c_b) / c_b.len_sq();
+        let v = a.scalar_product(&e_b) / e_b.len_sq();
+        if (-EPSILON..=1.0 + EPSILON).contains(&u) && (-EPSILON..=1.0 + EPSILON).contains(&v) {
+            Some((t, (u, v)))
+        } else {
+            None
+        }
+    }
+    pub fn disk_hit(r: &Ray, pos: &Vector3, normal: &Vector3, radius: Float, t_min: Float, t_max: Float) -> Option<Float> {
+        let t = Self::plane_hit(r, pos, normal, t_min, t_max)?;
+        if (r.point_from_t(t) - *pos).len_sq() <= radius * radius {
+            Some(t)
         } else {
             None
         }
     }
+    //Доля оборота вокруг оси, [0, 1)
+    fn azimuth(q: &Vector3, axis: &Vector3) -> Float {
+        let (e1, e2) = axis.orthonormal_basis();
+        0.5 + q.scalar_product(&e2).atan2(q.scalar_product(&e1)) / (2.0 * consts::PI)
+    }
+    //u - доля оборота вокруг центра, v - доля радиуса
+    fn polar_uv(q: &Vector3, normal: &Vector3, radius: Float) -> (Float, Float) {
+        (Self::azimuth(q, normal), q.len() / radius)
+    }
+    //Крышки цилиндра и основание конуса
+    fn caps_hit(r: &Ray, caps: &[(Vector3, Vector3)], radius: Float, t_min: Float, res: Option<SurfaceHit>, t_max: Float) -> Option<SurfaceHit> {
+        let mut res = res;
+        let mut t_max = t_max;
+        for (c, n) in caps {
+            if let Some(t) = Self::disk_hit(r, c, n, radius, t_min, t_max) {
+                res = Some((t, *n, Self::polar_uv(&(r.point_from_t(t) - *c), n, radius)));
+                t_max = t;
+            }
+        }
+        res
+    }
+    //Начало луча, перенесённое к ближайшей к середине оси точке, и t этой точки.
+    //Коэффициенты квадратных уравнений тогда порядка размера фигуры, а не расстояния до неё.
+    fn near_axis_origin(r: &Ray, pos: &Vector3, axis: &Vector3, h: Float) -> (Float, Vector3) {
+        let mid = pos + &axis.mult(h * 0.5);
+        let tc = (mid - r.pos).scalar_product(&r.dir) / r.dir.len_sq();
+        (tc, r.point_from_t(tc) - *pos)
+    }
+    //t, внешняя нормаль и UV: на боковой поверхности u - доля оборота, v - доля высоты
+    pub fn cylinder_hit(r: &Ray, pos: &Vector3, axis: &Vector3, radius: Float, h: Float, t_min: Float, t_max: Float) -> Option<SurfaceHit> {
+        let (tc, o) = Self::near_axis_origin(r, pos, axis, h);
+        let (oy, dy) = (o.scalar_product(axis), r.dir.scalar_product(axis));
+        let op = o - axis.mult(oy);
+        let dp = r.dir - axis.mult(dy);
+        let mut res = None;
+        let mut t_max = t_max;
+        let roots = solve_quadratic(dp.len_sq(), 2.0 * op.scalar_product(&dp), op.len_sq() - radius * radius);
+        for t in roots.into_iter().flat_map(|(t0, t1)| [t0, t1]) {
+            let y = oy + dy * t;
+            if tc + t >= t_min && tc + t <= t_max && (0.0..=h).contains(&y) {
+                let q = op + dp.mult(t);
+                res = Some((tc + t, q.normalize(), (Self::azimuth(&q, axis), y / h)));
+                t_max = tc + t;
+                break;
+            }
+        }
+        Self::caps_hit(r, &[(*pos, -axis), (pos + &axis.mult(h), *axis)], radius, t_min, res, t_max)
+    }
+    //Как у цилиндра, радиус линейно убывает от основания к вершине
+    pub fn cone_hit(r: &Ray, pos: &Vector3, axis: &Vector3, radius: Float, h: Float, t_min: Float, t_max: Float) -> Option<SurfaceHit> {
+        let k = radius / h;
+        let (tc, o) = Self::near_axis_origin(r, pos, axis, h);
+        let (oy, dy) = (o.scalar_product(axis), r.dir.scalar_product(axis));
+        let op = o - axis.mult(oy);
+        let dp = r.dir - axis.mult(dy);
+        //|op + dp t| = r0 + rd t
+        let r0 = radius - k * oy;
+        let rd = -k * dy;
+        let a = dp.len_sq() - rd * rd;
+        let b = 2.0 * (op.scalar_product(&dp) - r0 * rd);
+        let c = op.len_sq() - r0 * r0;
+        let mut res = None;
+        let mut t_max = t_max;
+        for t in solve_quadratic(a, b, c).into_iter().flat_map(|(t0, t1)| [t0, t1]) {
+            let y = oy + dy * t;
+            if tc + t >= t_min && tc + t <= t_max && (0.0..=h).contains(&y) {
+                let q = op + dp.mult(t);
+                let n = (q.normalize() + axis.mult(k)).normalize();
+                res = Some((tc + t, n, (Self::azimuth(&q, axis), y / h)));
+                t_max = tc + t;
+                break;
+            }
+        }
+        Self::caps_hit(r, &[(*pos, -axis)], radius, t_min, res, t_max)
+    }
+    //Корень уравнения четвёртой степени (|q|² + R² - r²)² = 4R²(|q|² - (q·axis)²).
+    //Отрезок поиска обрезается описанной сферой, а начало луча переносится к её
+    //поверхности, чтобы коэффициенты не теряли точность вдали от тора.
+    pub fn torus_hit(r: &Ray, pos: &Vector3, axis: &Vector3, big_r: Float, radius: Float, t_min: Float, t_max: Float) -> Option<Float> {
+        let o = r.pos - *pos;
+        let d = r.dir;
+        //С запасом, чтобы корни на самой сфере (x = ±(R + r) на экваторе) не выпали из отрезка
+        let bound = (big_r + radius) * 1.01;
+        let (s0, s1) = solve_quadratic(d.len_sq(), 2.0 * o.scalar_product(&d), o.len_sq() - bound * bound)?;
+        let lo = s0.max(t_min);
+        let hi = s1.min(t_max);
+        if lo > hi {
+            return None;
+        }
+        let o = o + d.mult(lo);
+        let (dd, od, oo) = (d.len_sq(), o.scalar_product(&d), o.len_sq());
+        let (dy, oy) = (d.scalar_product(axis), o.scalar_product(axis));
+        let k = oo + big_r * big_r - radius * radius;
+        let r4 = 4.0 * big_r * big_r;
+        let c = [
+            k * k - r4 * (oo - oy * oy),
+            4.0 * od * k - 2.0 * r4 * (od - dy * oy),
+            4.0 * od * od + 2.0 * dd * k - r4 * (dd - dy * dy),
+            4.0 * dd * od,
+            dd * dd,
+        ];
+        poly_roots(&c, 0.0, hi - lo).first().map(|s| lo + s)
+    }
+    //Нормаль от центра трубки; u - доля оборота вокруг оси, v - вокруг трубки
+    pub fn torus_normal_uv(q: &Vector3, axis: &Vector3, big_r: Float) -> (Vector3, (Float, Float)) {
+        let y = q.scalar_product(axis);
+        let qp = q - &axis.mult(y);
+        let n = (q - &qp.normalize().mult(big_r)).normalize();
+        let v = 0.5 + y.atan2(qp.len() - big_r) / (2.0 * consts::PI);
+        (n, (Self::azimuth(q, axis), v))
+    }
+}
+
+//t, внешняя нормаль и UV
+pub type SurfaceHit = (Float, Vector3, (Float, Float));
+
+//Корни по возрастанию, без потери точности при b² >> 4ac
+fn solve_quadratic(a: Float, b: Float, c: Float) -> Option<(Float, Float)> {
+    let disc = b * b - 4.0 * a * c;
+    if disc < 0.0 {
+        return None;
+    }
+    let q = -0.5 * (b + b.signum() * disc.sqrt());
+    let (t0, t1) = (q / a, c / q);
+    if t0 > t1 { Some((t1, t0)) } else { Some((t0, t1)) }
+}
+//Вещественные корни многочлена c[0] + c[1]x + ... на отрезке [lo, hi] по возрастанию.
+//Корни производной делят отрезок на участки монотонности, на каждом корень ищется делением пополам.
+fn poly_roots(c: &[Float], lo: Float, hi: Float) -> Vec<Float> {
+    let n = c.iter().rposition(|x| *x != 0.0).unwrap_or(0);
+    let c = &c[..=n];
+    if n == 0 {
+        return vec![];
+    }
+    if n == 1 {
+        let x = -c[0] / c[1];
+        return if (lo..=hi).contains(&x) { vec![x] } else { vec![] };
+    }
+    let eval = |x: Float| c.iter().rev().fold(0.0, |acc, k| acc * x + k);
+    let deriv: Vec<Float> = c.iter().enumerate().skip(1).map(|(i, k)| k * i as Float).collect();
+    let mut points = vec![lo];
+    points.extend(poly_roots(&deriv, lo, hi));
+    points.push(hi);
+    let mut res: Vec<Float> = vec![];
+    for w in points.windows(2) {
+        let (mut a, mut b) = (w[0], w[1]);
+        let (mut fa, fb) = (eval(a), eval(b));
+        if fa == 0.0 {
+            if res.last() != Some(&a) { res.push(a); }
+            continue;
+        }
+        if fb == 0.0 || fa.signum() == fb.signum() {
+            continue;
+        }
+        for _ in 0..64 {
+            let m = 0.5 * (a + b);
+            if m <= a || m >= b { break; }
+            let fm = eval(m);
+            if fm.signum() == fa.signum() { a = m; fa = fm; } else { b = m; }
+        }
+        res.push(0.5 * (a + b));
+    }
+    if eval(hi) == 0.0 && res.last() != Some(&hi) {
+        res.push(hi);
+    }
+    res
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HitRecord<'a> {
+    pub t: Float,
+    pub point: Vector3,
+    //Нормаль поверхности, смотрит наружу фигуры
+    pub normal: Vector3,
+    pub uv: (Float, Float),
+    //Луч пришёл снаружи фигуры
+    pub front_face: bool,
+    //Фигура сцены целиком: для экземпляров и CSG это не та часть, в которую попал луч
+    pub figure: &'a FigureKind,
+    //Материал части, в которую попал луч
+    pub material: &'a Material,
+}
+impl<'a> HitRecord<'a> {
+    pub fn new(ray: &Ray, t: Float, outward_normal: Vector3, uv: (Float, Float), figure: &'a FigureKind) -> Self {
+        Self {
+            t,
+            point: ray.point_from_t(t),
+            normal: outward_normal,
+            uv,
+            front_face: ray.dir.scalar_product(&outward_normal) < 0.0,
+            figure,
+            material: figure.get_material(),
+        }
+    }
+    pub fn material(&self) -> &'a Material {
+        self.material
+    }
 }
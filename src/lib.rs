@@ -0,0 +1,20 @@
+pub mod aov;
+pub mod background;
+pub mod bdpt;
+pub mod brdf;
+pub mod color;
+pub mod denoise;
+pub mod figure;
+pub mod library;
+pub mod lights;
+pub mod material;
+pub mod math;
+pub mod packet;
+pub mod path;
+pub mod photon;
+pub mod random;
+pub mod raytracer;
+pub mod scene;
+pub mod sdf;
+pub mod spectrum;
+pub mod stats;
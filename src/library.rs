@@ -0,0 +1,155 @@
+use std::{collections::HashMap, fmt::{self, Display}, fs, io, path::Path};
+
+use crate::{brdf::Pbr, material::Material, math::{Float, Vector3}};
+
+//Встроенные материалы, с которых начинается любая библиотека
+const BUILTIN: [(&str, Material); 17] = [
+    ("frontwalls", Material::FRONTWALLS),
+    ("backwalls", Material::BACKWALLS),
+    ("leftwall", Material::LEFTWALL),
+    ("rightwall", Material::RIGHTWALL),
+    ("cube", Material::CUBE),
+    ("cubemetalic", Material::CUBEMETALIC),
+    ("cubetransparent", Material::CUBETRANSPARENT),
+    ("mirror", Material::MIRRORMATERIAL),
+    ("flintglass", Material::FLINTGLASS),
+    ("pbr_floor", Material::PBR_FLOOR),
+    ("gold", Material::GOLD),
+    ("red_plastic", Material::RED_PLASTIC),
+    ("panel_light", Material::PANEL_LIGHT),
+    ("slit_light", Material::SLIT_LIGHT),
+    ("orange_lamp", Material::ORANGE_LAMP),
+    ("amber_lamp", Material::AMBER_LAMP),
+    ("yellow_lamp", Material::YELLOW_LAMP),
+];
+
+#[derive(Debug)]
+pub enum LibraryError {
+    Io(io::Error),
+    //Ошибка в строке line (с 1)
+    Parse { line: usize, message: String },
+    //Материала с таким именем нет в библиотеке
+    Unknown(String),
+}
+impl Display for LibraryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LibraryError::Io(e) => write!(f, "{e}"),
+            LibraryError::Parse { line, message } => write!(f, "line {line}: {message}"),
+            LibraryError::Unknown(name) => write!(f, "unknown material '{name}'"),
+        }
+    }
+}
+impl std::error::Error for LibraryError {}
+impl From<io::Error> for LibraryError {
+    fn from(e: io::Error) -> Self {
+        LibraryError::Io(e)
+    }
+}
+
+//Именованные материалы. Сцены берут материалы отсюда по имени, так что файл
+//с описаниями меняет вид сцены без перекомпиляции. Формат файла:
+//
+//  #комментарий
+//  [gold : cubemetalic]    новый материал на основе уже известного
+//  color = 1 0.77 0.34
+//  metallic = 1
+//  [cube]                  правка существующего материала
+//  diff = 0.5
+//
+//Поля: color, emission (три числа), refl, diff, specular, shininess, transparency,
+//refraction, dispersion, base_illumination, metallic, roughness. metallic и roughness
+//переводят материал на модель metallic/roughness, второй параметр берётся из as_pbr.
+#[derive(Debug, Clone)]
+pub struct MaterialLibrary {
+    materials: HashMap<String, Material>,
+}
+impl Default for MaterialLibrary {
+    fn default() -> Self {
+        MaterialLibrary { materials: BUILTIN.iter().map(|(name, m)| (name.to_string(), *m)).collect() }
+    }
+}
+impl MaterialLibrary {
+    pub fn load(path: &Path) -> Result<Self, LibraryError> {
+        let mut lib = Self::default();
+        lib.parse(&fs::read_to_string(path)?)?;
+        Ok(lib)
+    }
+    pub fn get(&self, name: &str) -> Option<&Material> {
+        self.materials.get(name)
+    }
+    //Копия материала или ошибка с его именем
+    pub fn material(&self, name: &str) -> Result<Material, LibraryError> {
+        self.get(name).copied().ok_or_else(|| LibraryError::Unknown(name.to_string()))
+    }
+    pub fn insert(&mut self, name: &str, m: Material) {
+        self.materials.insert(name.to_string(), m);
+    }
+
+    //Добавляет материалы из текста в формате файла. Основа должна быть описана раньше.
+    pub fn parse(&mut self, text: &str) -> Result<(), LibraryError> {
+        let mut current: Option<(String, Material)> = None;
+        for (i, line) in text.lines().enumerate() {
+            let err = |message: String| LibraryError::Parse { line: i + 1, message };
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header.strip_suffix(']').ok_or_else(|| err("expected ']'".to_string()))?;
+                if let Some((name, m)) = current.take() {
+                    self.materials.insert(name, m);
+                }
+                let (name, base) = match header.split_once(':') {
+                    Some((name, base)) => (name.trim(), base.trim()),
+                    None => (header.trim(), header.trim()),
+                };
+                if name.is_empty() {
+                    return Err(err("empty material name".to_string()));
+                }
+                let m = *self.get(base).ok_or_else(|| err(format!("unknown base material '{base}'")))?;
+                current = Some((name.to_string(), m));
+                continue;
+            }
+            let Some((_, m)) = current.as_mut() else {
+                return Err(err("field outside of a material".to_string()));
+            };
+            let (key, value) = line.split_once('=').ok_or_else(|| err("expected 'field = value'".to_string()))?;
+            let (key, value) = (key.trim(), value.trim());
+            let numbers = value.split_whitespace().map(|t| t.parse::<Float>()).collect::<Result<Vec<_>, _>>()
+                .map_err(|_| err(format!("bad number in '{value}'")))?;
+            set_field(m, key, &numbers).map_err(err)?;
+        }
+        if let Some((name, m)) = current {
+            self.materials.insert(name, m);
+        }
+        Ok(())
+    }
+}
+
+fn set_field(m: &mut Material, key: &str, v: &[Float]) -> Result<(), String> {
+    let vector = || match v {
+        [x, y, z] => Ok(Vector3::new(*x, *y, *z)),
+        _ => Err(format!("{key} needs three numbers")),
+    };
+    let scalar = || match v {
+        [x] => Ok(*x),
+        _ => Err(format!("{key} needs one number")),
+    };
+    match key {
+        "color" => m.color = vector()?,
+        "emission" => m.emission = vector()?,
+        "refl" => m.refl = scalar()?,
+        "diff" => m.diff = scalar()?,
+        "specular" => m.specular = scalar()?,
+        "shininess" => m.shininess = scalar()?,
+        "transparency" => m.transparency = scalar()?,
+        "refraction" => m.refraction = scalar()?,
+        "dispersion" => m.dispersion = scalar()?,
+        "base_illumination" => m.base_illumination = scalar()?,
+        "metallic" => m.pbr = Some(Pbr { metallic: scalar()?, ..m.as_pbr() }),
+        "roughness" => m.pbr = Some(Pbr { roughness: scalar()?, ..m.as_pbr() }),
+        _ => return Err(format!("unknown field '{key}'")),
+    }
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use crate::{
+    color::luminance,
+    figure::FigureKind,
+    math::{consts, Float},
+    random::Rng,
+    scene::{LightSource, Scene},
+};
+
+//Источник, который можно выбрать для прямого освещения
+#[derive(Debug, Clone, Copy)]
+pub enum LightRef<'a> {
+    Point(&'a LightSource),
+    Emitter(&'a FigureKind),
+}
+
+//Выбор источников для прямого освещения. При samples = 0 или если источников не больше samples,
+//освещают все источники, иначе в каждой точке выбираются samples источников с вероятностью,
+//пропорциональной мощности, и вклад делится на ожидаемое число выборов.
+//Время на точку тогда не зависит от числа источников.
+#[derive(Debug, Clone)]
+pub struct LightSampler<'a> {
+    pub lights: Vec<LightRef<'a>>,
+    //Накопленные мощности, нормированные на 1
+    cdf: Vec<Float>,
+    //Ожидаемое число выборов светящейся фигуры по номеру фигуры в сцене, 0 - не источник
+    figure_weight: Vec<Float>,
+    pub samples: u32,
+}
+impl<'a> LightSampler<'a> {
+    pub fn new(scene: &'a Scene, samples: u32) -> Self {
+        let mut lights = vec![];
+        let mut power = vec![];
+        for l in &scene.lights {
+            lights.push(LightRef::Point(l));
+            power.push(l.intencity * luminance(&l.color));
+        }
+        let mut emitter_figures = vec![];
+        for (i, f) in scene.figures.iter().enumerate() {
+            if let (true, Some(area)) = (f.get_material().is_emissive(), f.area()) {
+                lights.push(LightRef::Emitter(f));
+                power.push(luminance(&f.get_material().emission) * area * consts::PI);
+                emitter_figures.push(i);
+            }
+        }
+        let total: Float = power.iter().sum();
+        let mut cdf = Vec::with_capacity(power.len());
+        let mut sum = 0.0;
+        for p in &power {
+            sum += p;
+            cdf.push(if total > 0.0 { sum / total } else { 0.0 });
+        }
+        let all = samples == 0 || lights.len() <= samples as usize;
+        let mut figure_weight = vec![0.0; scene.figures.len()];
+        let first_emitter = scene.lights.len();
+        for (k, i) in emitter_figures.into_iter().enumerate() {
+            figure_weight[i] = if all { 1.0 } else if total > 0.0 { samples as Float * power[first_emitter + k] / total } else { 0.0 };
+        }
+        LightSampler { lights, cdf, figure_weight, samples }
+    }
+    //Ожидаемое число выборов светящейся фигуры с номером i за одну точку
+    pub fn figure_weight(&self, i: usize) -> Float {
+        self.figure_weight.get(i).copied().unwrap_or(0.0)
+    }
+    //Вызывает f для каждого выбранного источника с множителем вклада, генератор передаётся дальше
+    pub fn for_each_choice(&self, rng: &mut Rng, mut f: impl FnMut(&LightRef<'a>, Float, &mut Rng)) {
+        if self.samples == 0 || self.lights.len() <= self.samples as usize {
+            self.lights.iter().for_each(|l| f(l, 1.0, rng));
+            return;
+        }
+        for _ in 0..self.samples {
+            if let Some((l, p)) = self.pick(rng.next_f32() as Float) {
+                f(l, 1.0 / (self.samples as Float * p), rng);
+            }
+        }
+    }
+    //Один источник, выбранный по мощности при равномерном u, и вероятность его выбора
+    pub fn pick(&self, u: Float) -> Option<(&LightRef<'a>, Float)> {
+        if self.cdf.last().is_none_or(|c| *c <= 0.0) {
+            return None;
+        }
+        let i = self.cdf.partition_point(|c| *c <= u).min(self.cdf.len() - 1);
+        let p = self.cdf[i] - if i == 0 { 0.0 } else { self.cdf[i - 1] };
+        (p > 0.0).then(|| (&self.lights[i], p))
+    }
+}
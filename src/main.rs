@@ -1,26 +1,148 @@
-use std::{time::Instant, env::{self}};
+use std::{time::Instant, env::{self}, fmt::Display, path::Path, process, str::FromStr};
 
 
-use raytracer::{render, save_to_image};
-use scene::Scene;
+use raytracer::{
+    background::{Background, EnvMap},
+    color::Color,
+    math::{Float, Vector3},
+    photon::PhotonMap,
+    raytracer::{render_aovs, render_radiance, save_to_image, Integrator, RenderSettings},
+    denoise::{denoise, DenoiseSettings},
+    library::MaterialLibrary,
+    scene::{Falloff, Scene},
+    stats::RenderStats,
+};
 
-pub mod color;
-pub mod figure;
-pub mod material;
-pub mod math;
-pub mod raytracer;
-pub mod scene;
+//Сообщение об ошибке в аргументах и выход с ненулевым кодом
+fn fail(message: impl Display) -> ! {
+    eprintln!("{message}");
+    process::exit(1)
+}
+//Значение после флага
+fn value(flag: &str, v: Option<String>) -> String {
+    v.unwrap_or_else(|| fail(format!("{flag}: missing value")))
+}
+//Число после флага
+fn number<T: FromStr>(flag: &str, v: Option<String>) -> T {
+    let t = value(flag, v);
+    t.parse().unwrap_or_else(|_| fail(format!("{flag}: bad number {t}")))
+}
 
 fn main() {
     let default_res = 500;
-    let pixels = if let Some(t) = env::args().nth(1) {
-        t.parse().unwrap_or(default_res)
-    } else {default_res};
-    let s = Scene::get_room();
+    let mut pixels = default_res;
+    let mut settings = RenderSettings::default();
+    let mut stats_json = None;
+    let mut aovs = false;
+    let mut denoised = false;
+    let mut scene = String::from("room");
+    let mut background = None;
+    let mut falloff = None;
+    let mut materials = None;
+    let mut caustics: Option<usize> = None;
+    let mut light_samples = None;
+    let mut args = env::args().skip(1);
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "--stats-json" => stats_json = Some(value(&a, args.next())),
+            "--samples" => settings.samples = number(&a, args.next()),
+            "--seed" => settings.seed = number(&a, args.next()),
+            "--aov" => aovs = true,
+            "--denoise" => denoised = true,
+            "--scene" => scene = value(&a, args.next()),
+            "--background" => background = Some(value(&a, args.next())),
+            "--falloff" => falloff = Some(value(&a, args.next())),
+            "--materials" => materials = Some(value(&a, args.next())),
+            "--spectral" => settings.spectral = true,
+            "--caustics" => caustics = Some(number(&a, args.next())),
+            "--light-samples" => light_samples = Some(number(&a, args.next())),
+            "--integrator" => settings.integrator = match args.next().as_deref() {
+                Some("whitted") => Integrator::Whitted,
+                Some("path") => Integrator::Path,
+                Some("bdpt") => Integrator::Bidirectional,
+                other => fail(format!("unknown integrator {}, expected whitted, path or bdpt", other.unwrap_or("(none)"))),
+            },
+            t => pixels = t.parse().unwrap_or_else(|_| fail(format!("unknown argument {t}, expected an option or the image size"))),
+        }
+    }
+    //Подпуть источника в bdpt всегда начинается с одного источника, выбранного по мощности
+    if let Some(n) = light_samples {
+        if settings.integrator == Integrator::Bidirectional {
+            fail("--light-samples is not supported by --integrator bdpt, which starts each light subpath from one light");
+        }
+        settings.light_samples = n;
+    }
+    //Файл с именованными материалами поверх встроенных
+    let lib = match materials {
+        Some(p) => MaterialLibrary::load(Path::new(&p)).unwrap_or_else(|e| fail(format!("{p}: {e}"))),
+        None => MaterialLibrary::default(),
+    };
+    let s = match scene.as_str() {
+        "primitives" => Scene::get_primitives_in(&lib),
+        "csg" => Scene::get_csg_in(&lib),
+        "sdf" => Scene::get_sdf_in(&lib),
+        "pbr" => Scene::get_pbr_in(&lib),
+        "cornell" => Scene::get_cornell_in(&lib),
+        "lights" => Scene::get_many_lights_in(&lib),
+        "prism" => Scene::get_prism_in(&lib),
+        "room" => Scene::get_room_in(&lib),
+        other => fail(format!("unknown scene {other}, expected room, primitives, csg, sdf, pbr, cornell, lights or prism")),
+    };
+    let mut s = s.unwrap_or_else(|e| fail(e));
+    //sky, цвет r,g,b или путь к карте окружения
+    if let Some(b) = background {
+        let rgb: Vec<Float> = b.split(',').filter_map(|t| t.parse().ok()).collect();
+        s.background = match (b.as_str(), rgb.as_slice()) {
+            ("sky", _) => Background::SKY,
+            (_, [r, g, b]) => Background::Color(Vector3::new(*r, *g, *b)),
+            _ => Background::Map(EnvMap::load(Path::new(&b)).unwrap_or_else(|e| fail(format!("{b}: {e}")))),
+        };
+    }
+    //Ослабление света всех источников: none, linear, square, cutoff:радиус
+    //или коэффициенты constant,linear,quadratic
+    if let Some(f) = falloff {
+        let k: Result<Vec<Float>, _> = f.split(',').map(|t| t.parse()).collect();
+        let f = match (f.as_str(), f.strip_prefix("cutoff:"), k.as_deref()) {
+            ("none", _, _) => Falloff::None,
+            ("linear", _, _) => Falloff::Linear,
+            ("square", _, _) => Falloff::InverseSquare,
+            (_, Some(radius), _) => Falloff::Cutoff { radius: number("--falloff cutoff", Some(radius.to_string())) },
+            (_, _, Ok([constant, linear, quadratic])) => Falloff::Custom { constant: *constant, linear: *linear, quadratic: *quadratic },
+            _ => fail(format!("unknown falloff {f}, expected none, linear, square, cutoff:radius or constant,linear,quadratic")),
+        };
+        for l in &mut s.lights {
+            l.falloff = f;
+        }
+    }
+    //Число фотонов карты каустик
+    if let Some(n) = caustics {
+        let begin = Instant::now();
+        s.caustics = Some(PhotonMap::build(&s, n, settings.seed));
+        println!("Photons: {:?}", begin.elapsed());
+    }
     let x = pixels;
     let y = pixels;
+    let mut stats = RenderStats::default();
     let begin = Instant::now();
-    let t = render(&s, x, y);
+    let t = render_radiance(&s, x, y, &settings, &mut stats);
     println!("Elapsed: {:?}", begin.elapsed());
-    save_to_image(&t, x, y).save_with_format("./output.png", image::ImageFormat::Png).unwrap();
+    let begin = Instant::now();
+    save_to_image(&t.iter().map(Color::from_vector3).collect::<Vec<_>>(), x, y).save_with_format("./output.png", image::ImageFormat::Png).unwrap();
+    stats.output += begin.elapsed();
+    if aovs || denoised {
+        let a = render_aovs(&s, x, y, &mut stats);
+        let begin = Instant::now();
+        if aovs {
+            a.save(Path::new("."), "output").unwrap();
+        }
+        if denoised {
+            let d = denoise(&t, &a, &DenoiseSettings::default());
+            save_to_image(&d.iter().map(Color::from_vector3).collect::<Vec<_>>(), x, y).save_with_format("./output_denoised.png", image::ImageFormat::Png).unwrap();
+        }
+        stats.output += begin.elapsed();
+    }
+    println!("{stats}");
+    if let Some(path) = stats_json {
+        std::fs::write(path, stats.to_json()).unwrap();
+    }
 }
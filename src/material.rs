@@ -1,21 +1,30 @@
-use crate::math::Vector3;
+use crate::{brdf::Pbr, math::{Float, Vector3}};
 
 
-pub const AIR_REFRACTION: f32 = 1.000273; 
-pub const SMALL_GLASS_REFRACTION: f32 = 1.2;
-pub const GLASS_REFRACTION: f32 = 1.5;
-pub const PLASTIC_REFRACTION: f32 = 2.5;
+pub const AIR_REFRACTION: Float = 1.000273; 
+pub const SMALL_GLASS_REFRACTION: Float = 1.2;
+pub const GLASS_REFRACTION: Float = 1.5;
+pub const PLASTIC_REFRACTION: Float = 2.5;
+//Жёлтая линия натрия, нм: на ней задаются показатели преломления
+pub const REFERENCE_WAVELENGTH: Float = 589.3;
 
 #[derive(Debug, Clone, Copy,)]
 pub struct Material {
     pub color: Vector3,
-    pub refl: f32,
-    pub diff: f32,
-    pub specular : f32,
-    pub shininess : f32,
-    pub transparency: f32,
-    pub refraction: f32,
-    pub base_illumination: f32,
+    pub refl: Float,
+    pub diff: Float,
+    pub specular : Float,
+    pub shininess : Float,
+    pub transparency: Float,
+    //Показатель преломления на длине волны REFERENCE_WAVELENGTH
+    pub refraction: Float,
+    //Коэффициент B формулы Коши n(λ) = A + B / λ², λ в микрометрах. 0 - без дисперсии.
+    pub dispersion: Float,
+    pub base_illumination: Float,
+    //Модель metallic/roughness вместо refl, diff, specular и shininess
+    pub pbr: Option<Pbr>,
+    //Излучаемая яркость: фигура видна камере и освещает сцену как источник
+    pub emission: Vector3,
 }
 impl Material {
     pub const FRONTWALLS: Material = Material {
@@ -26,7 +35,10 @@ impl Material {
         shininess: 1.0,
         transparency: 0.0,
         refraction: AIR_REFRACTION,
+        dispersion: 0.0,
         base_illumination: 0.05,
+        pbr: None,
+        emission: Vector3::new(0.0, 0.0, 0.0),
     };
     pub const BACKWALLS: Material = Material {
         color: Vector3::new(0.2, 0.5, 0.2),
@@ -36,7 +48,10 @@ impl Material {
         shininess: 1.0,
         transparency: 0.0,
         refraction: AIR_REFRACTION,
+        dispersion: 0.0,
         base_illumination: 0.05,
+        pbr: None,
+        emission: Vector3::new(0.0, 0.0, 0.0),
     };
     pub const LEFTWALL: Material = Material {
         color: Vector3::new(1.0, 0.2, 0.2),
@@ -46,7 +61,10 @@ impl Material {
         shininess: 1.0,
         transparency: 0.0,
         refraction: AIR_REFRACTION,
+        dispersion: 0.0,
         base_illumination: 0.05,
+        pbr: None,
+        emission: Vector3::new(0.0, 0.0, 0.0),
     };
     pub const RIGHTWALL: Material = Material {
         color: Vector3::new(0.2, 0.2, 1.0),
@@ -56,7 +74,10 @@ impl Material {
         shininess: 1.0,
         transparency: 0.0,
         refraction: AIR_REFRACTION,
+        dispersion: 0.0,
         base_illumination: 0.05,
+        pbr: None,
+        emission: Vector3::new(0.0, 0.0, 0.0),
     };
     pub const CUBE: Material = Material {
         color: Vector3::new(1.0, 1.0, 0.2),
@@ -66,7 +87,10 @@ impl Material {
         shininess: 1.0,
         transparency: 0.0,
         refraction: AIR_REFRACTION,
+        dispersion: 0.0,
         base_illumination: 0.05,
+        pbr: None,
+        emission: Vector3::new(0.0, 0.0, 0.0),
     };
     pub const CUBEMETALIC: Material = Material {
         color: Vector3::new(0.9, 0.9, 0.9),
@@ -76,7 +100,10 @@ impl Material {
         shininess: 0.6,
         transparency: 0.0,
         refraction: AIR_REFRACTION,
+        dispersion: 0.0,
         base_illumination: 0.05,
+        pbr: None,
+        emission: Vector3::new(0.0, 0.0, 0.0),
     };
     pub const CUBETRANSPARENT: Material = Material {
         color: Vector3::new(0.9, 0.9, 0.9),
@@ -88,7 +115,10 @@ impl Material {
         shininess: 1.0,
         transparency: 0.99,
         refraction: GLASS_REFRACTION,
+        dispersion: 0.0,
         base_illumination: 0.01,
+        pbr: None,
+        emission: Vector3::new(0.0, 0.0, 0.0),
     };
     pub const MIRRORMATERIAL: Material = Material {
         color: Vector3::new(1.0, 1.0, 1.0),
@@ -98,6 +128,85 @@ impl Material {
         shininess: 1.0,
         transparency: 0.0,
         refraction: AIR_REFRACTION,
+        dispersion: 0.0,
         base_illumination: 0.01,  
+        pbr: None,
+        emission: Vector3::new(0.0, 0.0, 0.0),
     };
+
+    //Тяжёлый флинт: сильная дисперсия, призма раскладывает свет в спектр
+    pub const FLINTGLASS: Material = Material {
+        color: Vector3::new(1.0, 1.0, 1.0),
+        refl: 0.0,
+        diff: 0.0,
+        specular: 0.05,
+        shininess: 1.0,
+        transparency: 1.0,
+        refraction: 1.78,
+        dispersion: 0.018,
+        base_illumination: 0.0,
+        pbr: None,
+        emission: Vector3::new(0.0, 0.0, 0.0),
+    };
+
+    //Материалы сцен pbr, cornell, lights и prism
+    pub const PBR_FLOOR: Material = Material::pbr(Vector3::new(0.8, 0.8, 0.8), 0.0, 0.6);
+    pub const GOLD: Material = Material::pbr(Vector3::new(1.0, 0.77, 0.34), 1.0, 0.05);
+    pub const RED_PLASTIC: Material = Material::pbr(Vector3::new(0.8, 0.1, 0.1), 0.0, 0.05);
+    pub const PANEL_LIGHT: Material = Material::emissive(Vector3::new(12.0, 12.0, 12.0));
+    pub const SLIT_LIGHT: Material = Material::emissive(Vector3::new(4.0, 4.0, 4.0));
+    pub const ORANGE_LAMP: Material = Material::emissive(Vector3::new(4.0, 2.0, 1.0));
+    pub const AMBER_LAMP: Material = Material::emissive(Vector3::new(4.0, 3.0, 1.0));
+    pub const YELLOW_LAMP: Material = Material::emissive(Vector3::new(4.0, 4.0, 1.0));
+
+    //Материал metallic/roughness. Показатель преломления соответствует F0 = 0.04 диэлектрика.
+    pub const fn pbr(base_color: Vector3, metallic: Float, roughness: Float) -> Material {
+        Material {
+            color: base_color,
+            refl: 0.0,
+            diff: 0.0,
+            specular: 0.0,
+            shininess: 1.0,
+            transparency: 0.0,
+            refraction: GLASS_REFRACTION,
+            dispersion: 0.0,
+            base_illumination: 0.05,
+            pbr: Some(Pbr { metallic, roughness }),
+            emission: Vector3::new(0.0, 0.0, 0.0),
+        }
+    }
+    //Светящийся материал, сам свет не отражает
+    pub const fn emissive(emission: Vector3) -> Material {
+        Material {
+            color: Vector3::new(0.0, 0.0, 0.0),
+            refl: 0.0,
+            diff: 0.0,
+            specular: 0.0,
+            shininess: 1.0,
+            transparency: 0.0,
+            refraction: AIR_REFRACTION,
+            dispersion: 0.0,
+            base_illumination: 0.0,
+            pbr: None,
+            emission,
+        }
+    }
+    //Показатель преломления на длине волны в нм, None - на REFERENCE_WAVELENGTH
+    pub fn ior(&self, wavelength: Option<Float>) -> Float {
+        match wavelength {
+            Some(w) if self.dispersion != 0.0 => {
+                let inv_sq = |nm: Float| 1e6 / (nm * nm);
+                self.refraction + self.dispersion * (inv_sq(w) - inv_sq(REFERENCE_WAVELENGTH))
+            }
+            _ => self.refraction,
+        }
+    }
+    pub fn is_emissive(&self) -> bool {
+        self.emission.x > 0.0 || self.emission.y > 0.0 || self.emission.z > 0.0
+    }
+    //Параметры BRDF для трассировки путей. У старых материалов доля зеркального
+    //отражения становится металличностью, а почти зеркальные поверхности - гладкими.
+    pub fn as_pbr(&self) -> Pbr {
+        self.pbr.unwrap_or(Pbr { metallic: self.refl, roughness: 1.0 - self.refl })
+    }
 }
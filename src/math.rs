@@ -1,6 +1,28 @@
 use std::{ops, fmt::Display};
 
-pub const EPSILON: f32 = 1e-6;
+pub use precision::*;
+
+//Точность геометрии выбирается фичей f64, по умолчанию f32
+#[cfg(not(feature = "f64"))]
+mod precision {
+    pub type Float = f32;
+    pub use std::f32::consts;
+    pub const EPSILON: Float = 1e-6;
+    #[inline(always)]
+    pub(super) fn add_ulps(x: Float, ulps: i32) -> Float {
+        Float::from_bits((x.to_bits() as i32).wrapping_add(ulps) as u32)
+    }
+}
+#[cfg(feature = "f64")]
+mod precision {
+    pub type Float = f64;
+    pub use std::f64::consts;
+    pub const EPSILON: Float = 1e-9;
+    #[inline(always)]
+    pub(super) fn add_ulps(x: Float, ulps: i32) -> Float {
+        Float::from_bits((x.to_bits() as i64).wrapping_add(ulps as i64) as u64)
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct Ray {
@@ -16,29 +38,61 @@ impl Ray {
         Ray { pos, dir: dir.normalize() }
     }
     #[inline(always)]
-    pub fn point_from_t(&self, t: f32) -> Vector3 {
+    pub fn point_from_t(&self, t: Float) -> Vector3 {
         self.pos + self.dir.mult(t)
     }
-    pub fn move_forward(&self, len: f32) -> Ray {
-        Ray{ pos: self.pos + self.dir.mult(len), dir: self.dir }
+    //Луч, выпущенный с поверхности. Начало сдвигается по нормали поверхности
+    //в сторону dir, чтобы луч не пересёк ту же поверхность в t ≈ 0.
+    pub fn spawn(point: &Vector3, normal: &Vector3, dir: Vector3) -> Self {
+        let n = if dir.scalar_product(normal) < 0.0 { -normal } else { *normal };
+        Ray { pos: offset_ray_origin(point, &n), dir }
+    }
+    //Минимальное t для запросов пересечения: пересечения ближе - шум округления
+    //в начале луча, например соседняя грань на общем ребре. Растёт с масштабом координат.
+    #[inline(always)]
+    pub fn t_min(&self) -> Float {
+        //2^-16 для f32
+        const T_SCALE: Float = Float::EPSILON * 128.0;
+        const ORIGIN: Float = 1.0 / 32.0;
+        self.pos.x.abs().max(self.pos.y.abs()).max(self.pos.z.abs()).max(ORIGIN) * T_SCALE
     }
 }
-#[derive(Debug, Clone, Copy)]
+//Wächter, Binder. "A Fast and Robust Method for Avoiding Self-Intersection", Ray Tracing Gems, 2019.
+//Сдвиг на фиксированное число ULP в каждой координате, поэтому он растёт вместе с
+//масштабом координат. Около нуля, где ULP вырождаются, используется абсолютный сдвиг.
+pub fn offset_ray_origin(p: &Vector3, n: &Vector3) -> Vector3 {
+    const ORIGIN: Float = 1.0 / 32.0;
+    //1/65536 для f32
+    const FLOAT_SCALE: Float = Float::EPSILON * 128.0;
+    const INT_SCALE: Float = 256.0;
+    #[inline(always)]
+    fn offset(p: Float, n: Float) -> Float {
+        if p.abs() < ORIGIN {
+            p + FLOAT_SCALE * n
+        } else {
+            let of_i = (INT_SCALE * n) as i32;
+            add_ulps(p, if p < 0.0 { -of_i } else { of_i })
+        }
+    }
+    Vector3::new(offset(p.x, n.x), offset(p.y, n.y), offset(p.z, n.z))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vector3{
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
+    pub x: Float,
+    pub y: Float,
+    pub z: Float,
 }
 impl Vector3 {
     #[inline(always)]
-    pub fn len_sq(&self) -> f32 {
+    pub fn len_sq(&self) -> Float {
         self.x.powi(2) + self.y.powi(2) + self.z.powi(2)
     }
-    pub const fn new(x:f32, y:f32, z:f32) -> Self {
+    pub const fn new(x:Float, y:Float, z:Float) -> Self {
         Self { x, y, z }
     }
     #[inline(always)]
-    pub fn len(&self) -> f32 {
+    pub fn len(&self) -> Float {
         self.len_sq().sqrt()
     }
     #[inline(always)]
@@ -55,15 +109,15 @@ impl Vector3 {
         }
     }
     #[inline(always)]
-    pub fn scalar_product(&self, other: &Self) -> f32 {
+    pub fn scalar_product(&self, other: &Self) -> Float {
         self.x * other.x + self.y * other.y + self.z * other.z
     }
     #[inline(always)]
-    pub fn mult(&self, n: f32) -> Self {
+    pub fn mult(&self, n: Float) -> Self {
         Vector3 { x: self.x * n, y: self.y * n, z: self.z * n }
     }
     #[inline(always)]
-    pub fn div(&self, n: f32) -> Self {
+    pub fn div(&self, n: Float) -> Self {
         Vector3 { x: self.x / n, y: self.y / n, z: self.z / n }
     }
     #[inline(always)]
@@ -74,22 +128,32 @@ impl Vector3 {
     pub fn mult_per_element(&self, other: &Self) -> Self {
         Self { x: self.x * other.x, y: self.y * other.y, z: self.z * other.z }
     }
-    pub fn lerp(&self, other: &Self, portion: f32) -> Self {
+    //Два единичных вектора, вместе с единичным self образующих правую тройку (Duff et al. 2017)
+    pub fn orthonormal_basis(&self) -> (Self, Self) {
+        let sign = (1.0 as Float).copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            Vector3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vector3::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+    pub fn lerp(&self, other: &Self, portion: Float) -> Self {
         self + &(other - self).mult(portion)
     }
-    pub fn rotate_x_axis(&self, angle: f32) -> Self {
+    pub fn rotate_x_axis(&self, angle: Float) -> Self {
         Self { 
             x: self.x, 
             y: self.y * angle.cos() - self.z * angle.sin(), 
             z: self.y * angle.sin() + self.z * angle.cos(), }
     }
-    pub fn rotate_y_axis(&self, angle: f32) -> Self {
+    pub fn rotate_y_axis(&self, angle: Float) -> Self {
         Self { 
             x: self.x * angle.cos() + self.z * angle.sin(), 
             y: self.y, 
             z: -self.x * angle.sin() + self.z * angle.cos(), }
     }
-    pub fn rotate_z_axis(&self, angle: f32) -> Self {
+    pub fn rotate_z_axis(&self, angle: Float) -> Self {
         Self { 
             x: self.x * angle.cos() - self.y * angle.sin(), 
             y: self.x * angle.sin() + self.y * angle.cos(),
@@ -157,4 +221,462 @@ impl Display for Vector3 {
         let t = format!("[x:{:.decimals$}, y:{:.decimals$}, z:{:.decimals$}]", self.x, self.y, self.z);
         f.pad_integral(true, "", &t)
     }
-}
\ No newline at end of file
+}
+//Матрица 4x4 по строкам, точки и векторы - столбцы справа: p' = M * p
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[Float; 4]; 4],
+}
+impl Matrix4 {
+    pub const IDENTITY: Self = Matrix4 { m: [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ] };
+    pub const fn new(m: [[Float; 4]; 4]) -> Self {
+        Self { m }
+    }
+    pub fn translation(v: &Vector3) -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, v.x],
+            [0.0, 1.0, 0.0, v.y],
+            [0.0, 0.0, 1.0, v.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    pub fn scaling(v: &Vector3) -> Self {
+        Self::new([
+            [v.x, 0.0, 0.0, 0.0],
+            [0.0, v.y, 0.0, 0.0],
+            [0.0, 0.0, v.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    //Повороты совпадают с Vector3::rotate_*_axis
+    pub fn rotation_x(angle: Float) -> Self {
+        let (s, c) = angle.sin_cos();
+        Self::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, c, -s, 0.0],
+            [0.0, s, c, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    pub fn rotation_y(angle: Float) -> Self {
+        let (s, c) = angle.sin_cos();
+        Self::new([
+            [c, 0.0, s, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-s, 0.0, c, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    pub fn rotation_z(angle: Float) -> Self {
+        let (s, c) = angle.sin_cos();
+        Self::new([
+            [c, -s, 0.0, 0.0],
+            [s, c, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    //Поворот вокруг произвольной оси (формула Родрига)
+    pub fn rotation(axis: &Vector3, angle: Float) -> Self {
+        let a = axis.normalize();
+        let (s, c) = angle.sin_cos();
+        let t = 1.0 - c;
+        Self::new([
+            [t * a.x * a.x + c, t * a.x * a.y - s * a.z, t * a.x * a.z + s * a.y, 0.0],
+            [t * a.x * a.y + s * a.z, t * a.y * a.y + c, t * a.y * a.z - s * a.x, 0.0],
+            [t * a.x * a.z - s * a.y, t * a.y * a.z + s * a.x, t * a.z * a.z + c, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    pub fn transpose(&self) -> Self {
+        let mut r = [[0.0; 4]; 4];
+        for (i, row) in r.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = self.m[j][i];
+            }
+        }
+        Self::new(r)
+    }
+    //Обратная матрица методом Гаусса-Жордана, None для вырожденной
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::IDENTITY.m;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))?;
+            if a[pivot][col].abs() < EPSILON {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let d = a[col][col];
+            for j in 0..4 {
+                a[col][j] /= d;
+                inv[col][j] /= d;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let k = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= k * a[col][j];
+                        inv[row][j] -= k * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Self::new(inv))
+    }
+    //Верхний левый блок 3x3: поворот, масштаб и сдвиг без переноса
+    pub fn linear(&self) -> Matrix3 {
+        let m = &self.m;
+        Matrix3::new([
+            [m[0][0], m[0][1], m[0][2]],
+            [m[1][0], m[1][1], m[1][2]],
+            [m[2][0], m[2][1], m[2][2]],
+        ])
+    }
+    pub fn from_linear(l: &Matrix3, translation: &Vector3) -> Self {
+        let m = &l.m;
+        Self::new([
+            [m[0][0], m[0][1], m[0][2], translation.x],
+            [m[1][0], m[1][1], m[1][2], translation.y],
+            [m[2][0], m[2][1], m[2][2], translation.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    #[inline(always)]
+    pub fn transform_point(&self, p: &Vector3) -> Vector3 {
+        let m = &self.m;
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        Vector3::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        ).div(w)
+    }
+    #[inline(always)]
+    pub fn transform_vector(&self, v: &Vector3) -> Vector3 {
+        let m = &self.m;
+        Vector3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+impl ops::Mul for Matrix4 {
+    type Output = Matrix4;
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut r = [[0.0; 4]; 4];
+        for (i, row) in r.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Matrix4::new(r)
+    }
+}
+
+//Аффинное преобразование вместе с обратным, чтобы не обращать матрицу на каждом луче
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub matrix: Matrix4,
+    pub inverse: Matrix4,
+}
+impl Transform {
+    pub const IDENTITY: Self = Transform { matrix: Matrix4::IDENTITY, inverse: Matrix4::IDENTITY };
+    //None, если матрица вырождена
+    pub fn from_matrix(matrix: Matrix4) -> Option<Self> {
+        Some(Self { matrix, inverse: matrix.inverse()? })
+    }
+    pub fn translate(v: &Vector3) -> Self {
+        Self { matrix: Matrix4::translation(v), inverse: Matrix4::translation(&-v) }
+    }
+    pub fn scale(v: &Vector3) -> Self {
+        Self { matrix: Matrix4::scaling(v), inverse: Matrix4::scaling(&v.inverse()) }
+    }
+    pub fn rotate_x(angle: Float) -> Self {
+        Self { matrix: Matrix4::rotation_x(angle), inverse: Matrix4::rotation_x(-angle) }
+    }
+    pub fn rotate_y(angle: Float) -> Self {
+        Self { matrix: Matrix4::rotation_y(angle), inverse: Matrix4::rotation_y(-angle) }
+    }
+    pub fn rotate_z(angle: Float) -> Self {
+        Self { matrix: Matrix4::rotation_z(angle), inverse: Matrix4::rotation_z(-angle) }
+    }
+    pub fn rotate(axis: &Vector3, angle: Float) -> Self {
+        Self { matrix: Matrix4::rotation(axis, angle), inverse: Matrix4::rotation(axis, -angle) }
+    }
+    //Сначала self, потом next
+    pub fn then(&self, next: &Transform) -> Self {
+        Self { matrix: next.matrix * self.matrix, inverse: self.inverse * next.inverse }
+    }
+    pub fn inverse(&self) -> Self {
+        Self { matrix: self.inverse, inverse: self.matrix }
+    }
+    pub fn transform_point(&self, p: &Vector3) -> Vector3 {
+        self.matrix.transform_point(p)
+    }
+    pub fn transform_vector(&self, v: &Vector3) -> Vector3 {
+        self.matrix.transform_vector(v)
+    }
+    pub fn from_rotation(q: &Quaternion) -> Self {
+        let r = q.to_matrix3();
+        Self { matrix: Matrix4::from_linear(&r, &Vector3::new(0.0, 0.0, 0.0)), inverse: Matrix4::from_linear(&r.transpose(), &Vector3::new(0.0, 0.0, 0.0)) }
+    }
+    //Масштаб, затем поворот, затем перенос - удобно для анимации, где каждая часть интерполируется отдельно
+    pub fn from_trs(translation: &Vector3, rotation: &Quaternion, scale: &Vector3) -> Self {
+        Self::scale(scale)
+            .then(&Self::from_rotation(rotation))
+            .then(&Self::translate(translation))
+    }
+    //Нормали преобразуются обратной транспонированной матрицей
+    pub fn normal_matrix(&self) -> Matrix3 {
+        self.inverse.linear().transpose()
+    }
+    pub fn transform_normal(&self, n: &Vector3) -> Vector3 {
+        (self.normal_matrix() * *n).normalize()
+    }
+    pub fn apply<T: Transformable>(&self, x: &T) -> T {
+        x.transformed(self)
+    }
+    //Луч в локальные координаты. Направление не нормируется, поэтому t совпадает с мировым
+    pub fn inverse_ray(&self, r: &Ray) -> Ray {
+        Ray { pos: self.inverse.transform_point(&r.pos), dir: self.inverse.transform_vector(&r.dir) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix3 {
+    pub m: [[Float; 3]; 3],
+}
+impl Matrix3 {
+    pub const IDENTITY: Self = Matrix3 { m: [
+        [1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, 0.0, 1.0],
+    ] };
+    pub const fn new(m: [[Float; 3]; 3]) -> Self {
+        Self { m }
+    }
+    pub fn from_columns(a: &Vector3, b: &Vector3, c: &Vector3) -> Self {
+        Self::new([
+            [a.x, b.x, c.x],
+            [a.y, b.y, c.y],
+            [a.z, b.z, c.z],
+        ])
+    }
+    pub fn transpose(&self) -> Self {
+        let m = &self.m;
+        Self::new([
+            [m[0][0], m[1][0], m[2][0]],
+            [m[0][1], m[1][1], m[2][1]],
+            [m[0][2], m[1][2], m[2][2]],
+        ])
+    }
+    pub fn determinant(&self) -> Float {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+    //Через присоединённую матрицу, None для вырожденной
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det.abs() < EPSILON {
+            return None;
+        }
+        let m = &self.m;
+        let c = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+        Some(Self::new([
+            [c(1, 2, 1, 2), -c(0, 2, 1, 2), c(0, 1, 1, 2)],
+            [-c(1, 2, 0, 2), c(0, 2, 0, 2), -c(0, 1, 0, 2)],
+            [c(1, 2, 0, 1), -c(0, 2, 0, 1), c(0, 1, 0, 1)],
+        ]).mult(1.0 / det))
+    }
+    pub fn mult(&self, k: Float) -> Self {
+        Self::new(self.m.map(|row| row.map(|x| x * k)))
+    }
+}
+impl ops::Mul for Matrix3 {
+    type Output = Matrix3;
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut r = [[0.0; 3]; 3];
+        for (i, row) in r.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..3).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Matrix3::new(r)
+    }
+}
+impl ops::Mul<Vector3> for Matrix3 {
+    type Output = Vector3;
+    #[inline(always)]
+    fn mul(self, v: Vector3) -> Self::Output {
+        let m = &self.m;
+        Vector3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+//Единичный кватернион задаёт поворот: q = (cos(a/2), sin(a/2) * axis)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub w: Float,
+    pub x: Float,
+    pub y: Float,
+    pub z: Float,
+}
+impl Quaternion {
+    pub const IDENTITY: Self = Quaternion { w: 1.0, x: 0.0, y: 0.0, z: 0.0 };
+    pub const fn new(w: Float, x: Float, y: Float, z: Float) -> Self {
+        Self { w, x, y, z }
+    }
+    pub fn from_axis_angle(axis: &Vector3, angle: Float) -> Self {
+        let a = axis.normalize();
+        let (s, c) = (angle * 0.5).sin_cos();
+        Self::new(c, a.x * s, a.y * s, a.z * s)
+    }
+    //Тот же порядок, что и последовательные rotate_x_axis, rotate_y_axis, rotate_z_axis
+    pub fn from_euler(x: Float, y: Float, z: Float) -> Self {
+        Self::from_axis_angle(&Vector3::new(0.0, 0.0, 1.0), z)
+            * Self::from_axis_angle(&Vector3::new(0.0, 1.0, 0.0), y)
+            * Self::from_axis_angle(&Vector3::new(1.0, 0.0, 0.0), x)
+    }
+    pub fn vector(&self) -> Vector3 {
+        Vector3::new(self.x, self.y, self.z)
+    }
+    pub fn dot(&self, other: &Self) -> Float {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+    pub fn len(&self) -> Float {
+        self.dot(self).sqrt()
+    }
+    pub fn normalize(&self) -> Self {
+        self.mult(1.0 / self.len())
+    }
+    pub fn conjugate(&self) -> Self {
+        Self::new(self.w, -self.x, -self.y, -self.z)
+    }
+    pub fn mult(&self, k: Float) -> Self {
+        Self::new(self.w * k, self.x * k, self.y * k, self.z * k)
+    }
+    pub fn rotate(&self, v: &Vector3) -> Vector3 {
+        //v' = v + 2w(q x v) + 2 q x (q x v)
+        let q = self.vector();
+        let t = q.cross_product(v).mult(2.0);
+        *v + t.mult(self.w) + q.cross_product(&t)
+    }
+    pub fn to_matrix3(&self) -> Matrix3 {
+        let Quaternion { w, x, y, z } = *self;
+        Matrix3::new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y)],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x)],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y)],
+        ])
+    }
+    //Сферическая интерполяция по кратчайшей дуге
+    pub fn slerp(&self, other: &Self, t: Float) -> Self {
+        let mut cos = self.dot(other);
+        let mut b = *other;
+        if cos < 0.0 {
+            cos = -cos;
+            b = b.mult(-1.0);
+        }
+        //Почти совпадающие повороты: sin(theta) близок к нулю, хватает линейной интерполяции
+        if cos > 1.0 - 1e-4 {
+            return (self.mult(1.0 - t) + b.mult(t)).normalize();
+        }
+        let theta = cos.acos();
+        let sin = theta.sin();
+        let ka = ((1.0 - t) * theta).sin() / sin;
+        let kb = (t * theta).sin() / sin;
+        self.mult(ka) + b.mult(kb)
+    }
+}
+impl ops::Mul for Quaternion {
+    type Output = Quaternion;
+    //Сначала rhs, потом self
+    fn mul(self, r: Self) -> Self::Output {
+        Quaternion::new(
+            self.w * r.w - self.x * r.x - self.y * r.y - self.z * r.z,
+            self.w * r.x + self.x * r.w + self.y * r.z - self.z * r.y,
+            self.w * r.y - self.x * r.z + self.y * r.w + self.z * r.x,
+            self.w * r.z + self.x * r.y - self.y * r.x + self.z * r.w,
+        )
+    }
+}
+impl ops::Add for Quaternion {
+    type Output = Quaternion;
+    fn add(self, r: Self) -> Self::Output {
+        Quaternion::new(self.w + r.w, self.x + r.x, self.y + r.y, self.z + r.z)
+    }
+}
+
+//Точка: переносится преобразованием целиком
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point3(pub Vector3);
+//Единичная нормаль: преобразуется обратной транспонированной матрицей
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Normal3(Vector3);
+impl Point3 {
+    pub const fn new(x: Float, y: Float, z: Float) -> Self {
+        Self(Vector3::new(x, y, z))
+    }
+    pub fn to_vector3(self) -> Vector3 {
+        self.0
+    }
+}
+impl Normal3 {
+    pub fn new(v: &Vector3) -> Self {
+        Self(v.normalize())
+    }
+    pub fn to_vector3(self) -> Vector3 {
+        self.0
+    }
+}
+impl ops::Sub for Point3 {
+    type Output = Vector3;
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.0 - rhs.0
+    }
+}
+impl ops::Add<Vector3> for Point3 {
+    type Output = Point3;
+    fn add(self, rhs: Vector3) -> Self::Output {
+        Point3(self.0 + rhs)
+    }
+}
+
+//Что и как преобразуется: точки, направления и нормали ведут себя по-разному
+pub trait Transformable {
+    fn transformed(&self, t: &Transform) -> Self;
+}
+impl Transformable for Vector3 {
+    fn transformed(&self, t: &Transform) -> Self {
+        t.transform_vector(self)
+    }
+}
+impl Transformable for Point3 {
+    fn transformed(&self, t: &Transform) -> Self {
+        Point3(t.transform_point(&self.0))
+    }
+}
+impl Transformable for Normal3 {
+    fn transformed(&self, t: &Transform) -> Self {
+        Normal3(t.transform_normal(&self.0))
+    }
+}
+impl Transformable for Ray {
+    fn transformed(&self, t: &Transform) -> Self {
+        Ray { pos: t.transform_point(&self.pos), dir: t.transform_vector(&self.dir) }
+    }
+}
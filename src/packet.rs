@@ -0,0 +1,298 @@
+use std::ops;
+
+use crate::{
+    figure::{CsgOp, FigureKind, HitRecord},
+    math::{Float, Ray, Vector3, EPSILON},
+    scene::Scene,
+    stats::RayCounters,
+};
+
+//Ширина пакета. Лучи пакета обходятся скалярными циклами по массивам
+//фиксированной длины; явных SIMD-инструкций нет, векторизация на усмотрение компилятора.
+pub const LANES: usize = 4;
+
+type Lanes = [Float; LANES];
+
+#[inline(always)]
+fn lanes(f: impl FnMut(usize) -> Float) -> Lanes {
+    std::array::from_fn(f)
+}
+
+//Векторы всех лучей пакета, по координатам (SoA)
+#[derive(Debug, Clone, Copy)]
+pub struct VectorPacket {
+    pub x: Lanes,
+    pub y: Lanes,
+    pub z: Lanes,
+}
+impl VectorPacket {
+    pub fn splat(v: &Vector3) -> Self {
+        Self { x: [v.x; LANES], y: [v.y; LANES], z: [v.z; LANES] }
+    }
+    pub fn from_fn(f: impl Fn(usize) -> Vector3) -> Self {
+        let v: [Vector3; LANES] = std::array::from_fn(f);
+        Self { x: lanes(|i| v[i].x), y: lanes(|i| v[i].y), z: lanes(|i| v[i].z) }
+    }
+    pub fn lane(&self, i: usize) -> Vector3 {
+        Vector3::new(self.x[i], self.y[i], self.z[i])
+    }
+    #[inline(always)]
+    pub fn scalar_product(&self, o: &Self) -> Lanes {
+        lanes(|i| self.x[i] * o.x[i] + self.y[i] * o.y[i] + self.z[i] * o.z[i])
+    }
+    #[inline(always)]
+    pub fn mult(&self, t: &Lanes) -> Self {
+        Self { x: lanes(|i| self.x[i] * t[i]), y: lanes(|i| self.y[i] * t[i]), z: lanes(|i| self.z[i] * t[i]) }
+    }
+}
+impl ops::Add for VectorPacket {
+    type Output = VectorPacket;
+    #[inline(always)]
+    fn add(self, o: Self) -> Self::Output {
+        Self { x: lanes(|i| self.x[i] + o.x[i]), y: lanes(|i| self.y[i] + o.y[i]), z: lanes(|i| self.z[i] + o.z[i]) }
+    }
+}
+impl ops::Sub for VectorPacket {
+    type Output = VectorPacket;
+    #[inline(always)]
+    fn sub(self, o: Self) -> Self::Output {
+        Self { x: lanes(|i| self.x[i] - o.x[i]), y: lanes(|i| self.y[i] - o.y[i]), z: lanes(|i| self.z[i] - o.z[i]) }
+    }
+}
+
+//Пакет когерентных лучей. Неполный пакет дополняется копиями первого луча,
+//которые помечаются неактивными.
+#[derive(Debug, Clone, Copy)]
+pub struct RayPacket {
+    pub pos: VectorPacket,
+    pub dir: VectorPacket,
+    pub t_min: Lanes,
+    pub active: [bool; LANES],
+}
+impl RayPacket {
+    pub fn new(rays: &[Ray]) -> Self {
+        assert!(!rays.is_empty() && rays.len() <= LANES);
+        let r = |i: usize| rays.get(i).unwrap_or(&rays[0]);
+        Self {
+            pos: VectorPacket::from_fn(|i| r(i).pos),
+            dir: VectorPacket::from_fn(|i| r(i).dir),
+            t_min: lanes(|i| r(i).t_min()),
+            active: std::array::from_fn(|i| i < rays.len()),
+        }
+    }
+    pub fn point_from_t(&self, t: &Lanes) -> VectorPacket {
+        self.pos + self.dir.mult(t)
+    }
+    pub fn ray(&self, i: usize) -> Ray {
+        Ray { pos: self.pos.lane(i), dir: self.dir.lane(i) }
+    }
+}
+
+//Попадания лучей пакета, None - промах
+pub type PacketHits<'a> = [Option<HitRecord<'a>>; LANES];
+
+//Ограничивающий параллелепипед, выровненный по осям
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+impl Aabb {
+    pub const INFINITE: Self = Aabb {
+        min: Vector3::new(Float::NEG_INFINITY, Float::NEG_INFINITY, Float::NEG_INFINITY),
+        max: Vector3::new(Float::INFINITY, Float::INFINITY, Float::INFINITY),
+    };
+    pub fn from_points(p: &[Vector3]) -> Self {
+        let mut min = p[0];
+        let mut max = p[0];
+        for v in &p[1..] {
+            min = Vector3::new(min.x.min(v.x), min.y.min(v.y), min.z.min(v.z));
+            max = Vector3::new(max.x.max(v.x), max.y.max(v.y), max.z.max(v.z));
+        }
+        //Плоские фигуры дают нулевую толщину, запас делает тест консервативным
+        let pad = (max - min).len() * 1e-4 + EPSILON;
+        let pad = Vector3::new(pad, pad, pad);
+        Self { min: min - pad, max: max + pad }
+    }
+    //Точки, раздутые на радиус r
+    pub fn around(p: &[Vector3], r: Float) -> Self {
+        let d = Vector3::new(r, r, r);
+        let p: Vec<_> = p.iter().flat_map(|p| [*p - d, *p + d]).collect();
+        Self::from_points(&p)
+    }
+    pub fn union(&self, o: &Self) -> Self {
+        Self {
+            min: Vector3::new(self.min.x.min(o.min.x), self.min.y.min(o.min.y), self.min.z.min(o.min.z)),
+            max: Vector3::new(self.max.x.max(o.max.x), self.max.y.max(o.max.y), self.max.z.max(o.max.z)),
+        }
+    }
+    pub fn intersection(&self, o: &Self) -> Self {
+        Self {
+            min: Vector3::new(self.min.x.max(o.min.x), self.min.y.max(o.min.y), self.min.z.max(o.min.z)),
+            max: Vector3::new(self.max.x.min(o.max.x), self.max.y.min(o.max.y), self.max.z.min(o.max.z)),
+        }
+    }
+    pub fn corners(&self) -> [Vector3; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Vector3::new(a.x, a.y, a.z), Vector3::new(b.x, a.y, a.z),
+            Vector3::new(a.x, b.y, a.z), Vector3::new(b.x, b.y, a.z),
+            Vector3::new(a.x, a.y, b.z), Vector3::new(b.x, a.y, b.z),
+            Vector3::new(a.x, b.y, b.z), Vector3::new(b.x, b.y, b.z),
+        ]
+    }
+    //Слэб-тест для всех лучей пакета на отрезке [t_min, t_max]
+    pub fn packet_hit(&self, p: &RayPacket, t_max: &Lanes) -> [bool; LANES] {
+        let slab = |o: &Lanes, d: &Lanes, lo: Float, hi: Float| -> (Lanes, Lanes) {
+            let t0 = lanes(|i| (lo - o[i]) / d[i]);
+            let t1 = lanes(|i| (hi - o[i]) / d[i]);
+            (lanes(|i| t0[i].min(t1[i])), lanes(|i| t0[i].max(t1[i])))
+        };
+        let (x0, x1) = slab(&p.pos.x, &p.dir.x, self.min.x, self.max.x);
+        let (y0, y1) = slab(&p.pos.y, &p.dir.y, self.min.y, self.max.y);
+        let (z0, z1) = slab(&p.pos.z, &p.dir.z, self.min.z, self.max.z);
+        std::array::from_fn(|i| {
+            let near = x0[i].max(y0[i]).max(z0[i]).max(p.t_min[i]);
+            let far = x1[i].min(y1[i]).min(z1[i]).min(t_max[i]);
+            p.active[i] && near <= far
+        })
+    }
+}
+
+impl FigureKind {
+    pub fn bounds(&self) -> Aabb {
+        match self {
+            FigureKind::Side { pos, .. } => Aabb::from_points(&[pos[0], pos[1], pos[2], pos[1] + pos[2] - pos[0]]),
+            FigureKind::Cube { pos, .. } => Aabb::from_points(pos),
+            FigureKind::Sphere { r, pos, .. } => Aabb::around(&[*pos], *r),
+            FigureKind::Plane { .. } => Aabb::INFINITE,
+            FigureKind::Disk { pos, r, .. } => Aabb::around(&[*pos], *r),
+            FigureKind::Cylinder { pos, axis, r, h, .. } | FigureKind::Cone { pos, axis, r, h, .. } => {
+                Aabb::around(&[*pos, pos + &axis.mult(*h)], *r)
+            }
+            FigureKind::Torus { pos, big_r, r, .. } => Aabb::around(&[*pos], big_r + r),
+            FigureKind::Sdf { sdf, .. } => sdf.bounds().map_or(Aabb::INFINITE, |(c, r)| Aabb::around(&[c], r)),
+            FigureKind::Instance { figure, transform } => {
+                let b = figure.bounds();
+                if !(b.min.len_sq() + b.max.len_sq()).is_finite() {
+                    return Aabb::INFINITE;
+                }
+                let c = b.corners().map(|p| transform.transform_point(&p));
+                Aabb::from_points(&c)
+            }
+            FigureKind::Csg { op, a, b } => match op {
+                CsgOp::Union => a.bounds().union(&b.bounds()),
+                CsgOp::Intersection => a.bounds().intersection(&b.bounds()),
+                CsgOp::Difference => a.bounds(),
+            },
+        }
+    }
+    //Те же формулы, что и в sphere_hit, для всех лучей сразу. Промах - INFINITY.
+    pub fn sphere_packet_hit(p: &RayPacket, radius: Float, pos: &Vector3, t_max: &Lanes) -> Lanes {
+        let l = VectorPacket::splat(pos) - p.pos;
+        let r2 = radius * radius;
+        let tca = l.scalar_product(&p.dir);
+        let l2 = l.scalar_product(&l);
+        lanes(|i| {
+            if tca[i] < 0.0 && l2[i] > r2 { return Float::INFINITY; }
+            let d2 = l2[i] - tca[i] * tca[i];
+            if d2 > r2 { return Float::INFINITY; }
+            let thc = (r2 - d2).sqrt();
+            let mut t0 = tca[i] - thc;
+            if t0 < p.t_min[i] { t0 = tca[i] + thc; }
+            if t0 < p.t_min[i] || t0 > t_max[i] { Float::INFINITY } else { t0 }
+        })
+    }
+    //Те же формулы, что и в rectangle_hit: t и UV. Промах - t = INFINITY.
+    pub fn rectangle_packet_hit(
+        p: &RayPacket,
+        top_left: &Vector3,
+        top_right: &Vector3,
+        down_left: &Vector3,
+        normal: &Vector3,
+        t_max: &Lanes,
+    ) -> (Lanes, Lanes, Lanes) {
+        let c_b = top_right - top_left;
+        let e_b = down_left - top_left;
+        let denom = VectorPacket::splat(normal).scalar_product(&p.dir);
+        let p010 = VectorPacket::splat(top_left) - p.pos;
+        let num = p010.scalar_product(&VectorPacket::splat(normal));
+        let t = lanes(|i| num[i] / denom[i]);
+        let a = p.point_from_t(&t) - VectorPacket::splat(top_left);
+        let u = a.scalar_product(&VectorPacket::splat(&c_b));
+        let v = a.scalar_product(&VectorPacket::splat(&e_b));
+        let (cl, el) = (c_b.len_sq(), e_b.len_sq());
+        let (u, v) = (lanes(|i| u[i] / cl), lanes(|i| v[i] / el));
+        let t = lanes(|i| {
+            let inside = (-EPSILON..=1.0 + EPSILON).contains(&u[i]) && (-EPSILON..=1.0 + EPSILON).contains(&v[i]);
+            if denom[i].abs() > EPSILON && t[i] >= p.t_min[i] && t[i] <= t_max[i] && inside { t[i] } else { Float::INFINITY }
+        });
+        (t, u, v)
+    }
+    //Ближайшие пересечения с фигурой не дальше t_max, те же, что дал бы hit_in.
+    //Остальные фигуры считаются по одному лучу.
+    pub fn packet_hit(&self, p: &RayPacket, t_max: &Lanes) -> PacketHits<'_> {
+        let record = |i: usize, t: Float, normal: Vector3, uv: (Float, Float)| {
+            t.is_finite().then(|| HitRecord::new(&p.ray(i), t, normal, uv, self))
+        };
+        match self {
+            FigureKind::Side { pos, normal, .. } => {
+                let (t, u, v) = Self::rectangle_packet_hit(p, &pos[0], &pos[1], &pos[2], normal, t_max);
+                std::array::from_fn(|i| record(i, t[i], *normal, (u[i], v[i])))
+            }
+            FigureKind::Cube { pos, normals, .. } => {
+                //Как в cube_hit: при равных t остаётся первая грань
+                let mut best = *t_max;
+                let mut face = [None; LANES];
+                let mut uv = [(0.0, 0.0); LANES];
+                for (f, [a, b, c]) in Self::CUBE_FACES.iter().enumerate() {
+                    let (t, u, v) = Self::rectangle_packet_hit(p, &pos[*a], &pos[*b], &pos[*c], &normals[f], &best);
+                    for l in 0..LANES {
+                        if t[l].is_finite() && (face[l].is_none() || t[l] < best[l]) {
+                            best[l] = t[l];
+                            face[l] = Some(f);
+                            uv[l] = (u[l], v[l]);
+                        }
+                    }
+                }
+                std::array::from_fn(|i| face[i].and_then(|f| record(i, best[i], normals[f], uv[i])))
+            }
+            FigureKind::Sphere { r, pos, .. } => {
+                let t = Self::sphere_packet_hit(p, *r, pos, t_max);
+                std::array::from_fn(|i| {
+                    if !t[i].is_finite() {
+                        return None;
+                    }
+                    let n = (p.ray(i).point_from_t(t[i]) - *pos).normalize();
+                    record(i, t[i], n, Self::sphere_uv(&n))
+                })
+            }
+            _ => std::array::from_fn(|i| self.hit_in(&p.ray(i), p.t_min[i], t_max[i])),
+        }
+    }
+}
+
+//Ближайшая фигура и попадание для каждого луча пакета, как closest_hit для отдельных лучей
+pub fn closest_hits<'a>(scene: &'a Scene, bounds: &[Aabb], p: &RayPacket, stats: &mut RayCounters) -> [Option<(usize, HitRecord<'a>)>; LANES] {
+    let mut best = [Float::INFINITY; LANES];
+    let mut res = [None; LANES];
+    for (i, (f, b)) in scene.figures.iter().zip(bounds).enumerate() {
+        let mask = b.packet_hit(p, &best);
+        if !mask.iter().any(|x| *x) {
+            continue;
+        }
+        for _ in mask.iter().filter(|x| **x) {
+            stats.intersection_test(f);
+        }
+        let hits = f.packet_hit(p, &best);
+        for l in 0..LANES {
+            if let (true, Some(h)) = (mask[l], hits[l]) {
+                if h.t <= best[l] {
+                    best[l] = h.t;
+                    res[l] = Some((i, h));
+                }
+            }
+        }
+    }
+    res
+}
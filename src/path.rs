@@ -0,0 +1,150 @@
+use crate::{
+    background::Background,
+    figure::HitRecord,
+    lights::{LightRef, LightSampler},
+    material::AIR_REFRACTION,
+    math::{consts, Float, Ray, Vector3, EPSILON},
+    random::Rng,
+    raytracer::{closest_hit, facing_normal, incoming_light, pbr_light, unoccluded_along, unoccluded_point},
+    scene::Scene,
+    stats::RayCounters,
+};
+
+pub const MAX_DEPTH: u32 = 16;
+//С этой глубины путь обрывается русской рулеткой
+pub(crate) const ROULETTE_DEPTH: u32 = 3;
+
+//Трассировка пути: в каждой точке прямой свет от источников, выбранных lights, затем направление
+//выбирается по BRDF материала. Прозрачные материалы преломляют или отражают по Френелю.
+//Светящиеся фигуры и карта окружения учитываются и при попадании, и при выборе
+//точки или направления на них, с весами MIS. Их прямой свет прозрачные фигуры перекрывают:
+//сквозь стекло он приходит по преломлённым путям, иначе учитывался бы дважды. Точечный
+//источник преломлённый путь найти не может, поэтому его свет проходит сквозь стекло ослабленным.
+pub fn path_trace(scene: &Scene, r: &Ray, lights: &LightSampler, rng: &mut Rng, stats: &mut RayCounters) -> Vector3 {
+    path_trace_at(scene, r, lights, None, rng, stats).0
+}
+
+//Путь на длине волны wavelength в нм (spectrum.rs): от неё зависит преломление в материалах
+//с дисперсией. Второе значение - путь прошёл через такой материал, и яркость зависит от длины волны.
+pub fn path_trace_at(scene: &Scene, r: &Ray, lights: &LightSampler, wavelength: Option<Float>, rng: &mut Rng, stats: &mut RayCounters) -> (Vector3, bool) {
+    let mut dispersed = false;
+    let mut radiance = Vector3::new(0.0, 0.0, 0.0);
+    let mut throughput = Vector3::new(1.0, 1.0, 1.0);
+    let mut ray = *r;
+    //Плотность выбора последнего направления по BRDF, None для камеры и преломления
+    let mut brdf_pdf = None;
+    for depth in 0..MAX_DEPTH {
+        stats.traced_at_depth(depth);
+        let Some((index, hit)) = closest_hit(scene, &ray, stats) else {
+            let w = match (&scene.background, brdf_pdf) {
+                (Background::Map(env), Some(pdf)) => power_heuristic(pdf, env.pdf(&ray.dir)),
+                _ => 1.0,
+            };
+            radiance += throughput.mult_per_element(&scene.background.radiance(&ray.dir)).mult(w);
+            break;
+        };
+        let m = hit.material();
+        if m.is_emissive() {
+            //Источник, найденный по BRDF, взвешивается с его выбором в прямом освещении
+            let w = match (brdf_pdf, hit.figure.area(), lights.figure_weight(index)) {
+                (Some(pdf), Some(area), k) if k > 0.0 => power_heuristic(pdf, k * light_pdf(hit.t, hit.normal.scalar_product(&ray.dir), area)),
+                _ => 1.0,
+            };
+            radiance += throughput.mult_per_element(&m.emission).mult(w);
+        }
+        if m.transparency > EPSILON && (rng.next_f32() as Float) < m.transparency {
+            dispersed |= wavelength.is_some() && m.dispersion != 0.0;
+            let (next, weight) = dielectric_bounce(&ray, &hit, rng, false, wavelength, stats);
+            ray = next;
+            throughput = throughput.mult_per_element(&weight);
+            brdf_pdf = None;
+        } else {
+            let p = m.as_pbr();
+            let n = facing_normal(&hit);
+            let v = -ray.dir;
+            lights.for_each_choice(rng, |l, weight, rng| match l {
+                LightRef::Point(l) => {
+                    if let Some((dir, light)) = incoming_light(scene, &hit, &n, l, stats) {
+                        radiance += throughput.mult_per_element(&pbr_light(&p, m, &n, &v, &dir, &light)).mult(weight);
+                    }
+                }
+                LightRef::Emitter(f) => {
+                    let u = (rng.next_f32() as Float, rng.next_f32() as Float);
+                    let (Some((q, q_normal)), Some(area)) = (f.sample_surface(u), f.area()) else { return };
+                    let d = q - hit.point;
+                    let emission = f.get_material().emission;
+                    let pdf = light_pdf(d.len(), q_normal.scalar_product(&d.normalize()), area) / weight;
+                    if pdf <= 0.0 {
+                        return;
+                    }
+                    //pbr_light умножает на π, как для точечных источников
+                    let Some((dir, light)) = unoccluded_point(scene, &hit, &n, &q, emission.div(pdf * consts::PI), stats) else { return };
+                    let w = power_heuristic(pdf, p.pdf(&n, &v, &dir));
+                    radiance += throughput.mult_per_element(&pbr_light(&p, m, &n, &v, &dir, &light)).mult(w);
+                }
+            });
+            //Освещение картой окружения: направление выбирается по яркости карты
+            if let Background::Map(env) = &scene.background {
+                let u = (rng.next_f32() as Float, rng.next_f32() as Float);
+                if let Some(e) = env.sample(u) {
+                    if let Some(light) = unoccluded_along(scene, &hit, &n, &e.dir, Float::INFINITY, e.radiance.div(e.pdf * consts::PI), stats) {
+                        let w = power_heuristic(e.pdf, p.pdf(&n, &v, &e.dir));
+                        radiance += throughput.mult_per_element(&pbr_light(&p, m, &n, &v, &e.dir, &light)).mult(w);
+                    }
+                }
+            }
+            let u = [rng.next_f32() as Float, rng.next_f32() as Float, rng.next_f32() as Float];
+            let Some(s) = p.sample(&m.color, &n, &v, u) else { break };
+            throughput = throughput.mult_per_element(&s.weight);
+            ray = Ray::spawn(&hit.point, &hit.normal, s.dir);
+            brdf_pdf = Some(s.pdf);
+            stats.reflection += 1;
+        }
+        if depth >= ROULETTE_DEPTH {
+            let q = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+            if (rng.next_f32() as Float) >= q {
+                break;
+            }
+            throughput = throughput.div(q);
+        }
+    }
+    (radiance, dispersed)
+}
+
+//Плотность точки светящейся поверхности по телесному углу при выборе равномерно по площади
+fn light_pdf(dist: Float, cos: Float, area: Float) -> Float {
+    let cos = cos.abs();
+    if cos < EPSILON {
+        return 0.0;
+    }
+    dist * dist / (cos * area)
+}
+
+//Степенная эвристика MIS (Veach) для двух стратегий по одному сэмплу
+fn power_heuristic(a: Float, b: Float) -> Float {
+    a * a / (a * a + b * b)
+}
+
+//Следующий луч на границе прозрачного материала и его вес. Доля отражения по Шлику
+//выбирается случайно, прошедший наружу свет окрашивается, как в refraction_part.
+//Для путей от источника (adjoint) свет идёт навстречу лучу и окрашивается при входе.
+pub(crate) fn dielectric_bounce(r: &Ray, hit: &HitRecord, rng: &mut Rng, adjoint: bool, wavelength: Option<Float>, stats: &mut RayCounters) -> (Ray, Vector3) {
+    let m = hit.material();
+    let n = facing_normal(hit);
+    let ior = m.ior(wavelength);
+    let eta = if hit.front_face { AIR_REFRACTION / ior } else { ior / AIR_REFRACTION };
+    let cos_i = -r.dir.scalar_product(&n);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+    let f0 = ((1.0 - eta) / (1.0 + eta)).powi(2);
+    let fresnel = if sin2_t >= 1.0 { 1.0 } else { f0 + (1.0 - f0) * (1.0 - cos_i).powi(5) };
+    if (rng.next_f32() as Float) < fresnel {
+        stats.reflection += 1;
+        let dir = r.dir + n.mult(2.0 * cos_i);
+        return (Ray::spawn(&hit.point, &hit.normal, dir), Vector3::new(1.0, 1.0, 1.0));
+    }
+    stats.refraction += 1;
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let dir = (r.dir.mult(eta) + n.mult(eta * cos_i - cos_t)).normalize();
+    let weight = if hit.front_face != adjoint { Vector3::new(1.0, 1.0, 1.0) } else { m.color };
+    (Ray::spawn(&hit.point, &hit.normal, dir), weight)
+}
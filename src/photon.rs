@@ -0,0 +1,240 @@
+use std::cmp::Ordering;
+
+use crate::{
+    color::luminance,
+    figure::{FigureKind, HitRecord},
+    material::Material,
+    math::{consts, Float, Ray, Vector3, EPSILON},
+    path::dielectric_bounce,
+    random::Rng,
+    raytracer::{closest_hit, facing_normal, lit_normal, reflected_light},
+    scene::{Falloff, Scene},
+    stats::RayCounters,
+};
+
+//Фотонов в оценке освещённости и наибольшее расстояние до них
+pub const GATHER_PHOTONS: usize = 50;
+pub const GATHER_RADIUS: Float = 0.1;
+//Зеркальных отражений и преломлений на пути фотона
+const MAX_BOUNCES: u32 = 10;
+//Старые материалы с такой долей отражения считаются зеркалами
+const MIRROR_REFL: Float = 0.5;
+
+//Фотон, пришедший на незеркальную поверхность после зеркальных отражений или преломлений.
+//power в единицах света точечного источника: плотность фотонов на площади даёт light * cos.
+#[derive(Debug, Clone, Copy)]
+pub struct Photon {
+    pub pos: Vector3,
+    //Направление полёта
+    pub dir: Vector3,
+    pub power: Vector3,
+    //Ось разбиения в kd-дереве
+    axis: u8,
+}
+impl Photon {
+    pub fn new(pos: Vector3, dir: Vector3, power: Vector3) -> Self {
+        Photon { pos, dir, power, axis: 0 }
+    }
+}
+
+//Карта каустик: фотоны в сбалансированном kd-дереве. Узел поддерева - средний элемент
+//своего отрезка массива, левее - фотоны с меньшей координатой по его оси.
+#[derive(Debug, Clone)]
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+}
+
+fn coord(v: &Vector3, axis: u8) -> Float {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+fn build_tree(p: &mut [Photon]) {
+    if p.len() <= 1 {
+        return;
+    }
+    let (mut min, mut max) = (p[0].pos, p[0].pos);
+    for f in p.iter() {
+        min = Vector3::new(min.x.min(f.pos.x), min.y.min(f.pos.y), min.z.min(f.pos.z));
+        max = Vector3::new(max.x.max(f.pos.x), max.y.max(f.pos.y), max.z.max(f.pos.z));
+    }
+    let size = max - min;
+    let axis = if size.x >= size.y && size.x >= size.z { 0 } else if size.y >= size.z { 1 } else { 2 };
+    let mid = p.len() / 2;
+    p.select_nth_unstable_by(mid, |a, b| coord(&a.pos, axis).partial_cmp(&coord(&b.pos, axis)).unwrap_or(Ordering::Equal));
+    p[mid].axis = axis;
+    let (left, right) = p.split_at_mut(mid);
+    build_tree(left);
+    build_tree(&mut right[1..]);
+}
+
+//Добавляет фотоны отрезка ближе max_sq к found, отсортированному по расстоянию.
+//Когда набрано k, max_sq сужается до самого дальнего из них.
+fn gather<'a>(photons: &'a [Photon], p: &Vector3, k: usize, found: &mut Vec<(Float, &'a Photon)>, max_sq: &mut Float) {
+    if photons.is_empty() {
+        return;
+    }
+    let mid = photons.len() / 2;
+    let node = &photons[mid];
+    let d = coord(p, node.axis) - coord(&node.pos, node.axis);
+    let (near, far) = if d < 0.0 { (&photons[..mid], &photons[mid + 1..]) } else { (&photons[mid + 1..], &photons[..mid]) };
+    gather(near, p, k, found, max_sq);
+    let dist_sq = (node.pos - *p).len_sq();
+    if dist_sq < *max_sq {
+        let i = found.partition_point(|(d, _)| *d <= dist_sq);
+        found.insert(i, (dist_sq, node));
+        if found.len() > k {
+            found.pop();
+        }
+        if found.len() == k {
+            *max_sq = found[k - 1].0;
+        }
+    }
+    if d * d < *max_sq {
+        gather(far, p, k, found, max_sq);
+    }
+}
+
+//Фигуры, на которые направляются фотоны: прозрачные и зеркальные
+fn casts_caustics(m: &Material) -> bool {
+    m.transparency > EPSILON || m.refl >= MIRROR_REFL || m.pbr.is_some_and(|p| p.roughness < 1.0 - MIRROR_REFL.sqrt())
+}
+
+//Конус направлений из точки на ограничивающую сферу фигуры: ось, косинус раствора, телесный угол.
+//Если точка внутри сферы - вся сфера направлений.
+fn cone_to(from: &Vector3, f: &FigureKind) -> Option<(Vector3, Float, Float)> {
+    let b = f.bounds();
+    let center = (b.min + b.max).mult(0.5);
+    let r = (b.max - b.min).len() * 0.5;
+    if !r.is_finite() {
+        return None;
+    }
+    let d = center - *from;
+    let dist = d.len();
+    let cos_max = if dist > r { (1.0 - r * r / (dist * dist)).sqrt() } else { -1.0 };
+    Some((d.div(dist.max(EPSILON)), cos_max, 2.0 * consts::PI * (1.0 - cos_max)))
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> Self {
+        build_tree(&mut photons);
+        PhotonMap { photons }
+    }
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+    //До k ближайших к p фотонов не дальше max_dist и квадраты расстояний до них, по возрастанию
+    pub fn nearest(&self, p: &Vector3, k: usize, max_dist: Float) -> Vec<(Float, &Photon)> {
+        let mut found = Vec::with_capacity(k + 1);
+        let mut max_sq = max_dist * max_dist;
+        if k > 0 {
+            gather(&self.photons, p, k, &mut found, &mut max_sq);
+        }
+        found
+    }
+
+    //Выпускает count фотонов от точечных источников, поровну на единицу мощности.
+    //Фотоны направляются в конусы на прозрачные и зеркальные фигуры, их мощность
+    //делится на плотность выбора направления, так что оценка не зависит от прицеливания.
+    pub fn build(scene: &Scene, count: usize, seed: u64) -> Self {
+        let mut photons = vec![];
+        let mut stats = RayCounters::default();
+        let total: Float = scene.lights.iter().map(|l| l.intencity * luminance(&l.color)).sum();
+        if total <= 0.0 {
+            return Self::new(photons);
+        }
+        let targets: Vec<&FigureKind> = scene.figures.iter().filter(|f| casts_caustics(f.get_material())).collect();
+        for (i, l) in scene.lights.iter().enumerate() {
+            let n = (count as Float * l.intencity * luminance(&l.color) / total).ceil() as usize;
+            let cones: Vec<_> = targets.iter().filter_map(|f| cone_to(&l.pos, f)).collect();
+            let solid_angle: Float = cones.iter().map(|c| c.2).sum();
+            if solid_angle <= 0.0 {
+                continue;
+            }
+            let mut rng = Rng::new(seed, i as u64);
+            for _ in 0..n {
+                //Конус выбирается пропорционально телесному углу, поэтому плотность направления -
+                //число накрывающих его конусов, делённое на их общий телесный угол
+                let mut u = rng.next_f32() as Float * solid_angle;
+                let (axis, cos_max, _) = *cones.iter().find(|c| { u -= c.2; u <= 0.0 }).unwrap_or(&cones[cones.len() - 1]);
+                let cos = 1.0 - rng.next_f32() as Float * (1.0 - cos_max);
+                let sin = (1.0 - cos * cos).max(0.0).sqrt();
+                let phi = 2.0 * consts::PI * rng.next_f32() as Float;
+                let (a, b) = axis.orthonormal_basis();
+                let dir = a.mult(sin * phi.cos()) + b.mult(sin * phi.sin()) + axis.mult(cos);
+                let covering = cones.iter().filter(|c| c.0.scalar_product(&dir) >= c.1).count().max(1);
+                let pdf = covering as Float / solid_angle;
+                trace_photon(scene, Ray { pos: l.pos, dir }, l.color.mult(l.intencity / (n as Float * pdf)), l.falloff, &mut rng, &mut photons, &mut stats);
+            }
+        }
+        Self::new(photons)
+    }
+
+    //Свет каустик в точке попадания, отражённый к камере
+    pub fn caustic_part(&self, t: &Ray, hit: &HitRecord) -> Vector3 {
+        let mut color = Vector3::new(0.0, 0.0, 0.0);
+        let found = self.nearest(&hit.point, GATHER_PHOTONS, GATHER_RADIUS);
+        if found.is_empty() {
+            return color;
+        }
+        let r_sq = if found.len() == GATHER_PHOTONS { found[found.len() - 1].0 } else { GATHER_RADIUS * GATHER_RADIUS };
+        let n = lit_normal(hit);
+        for (_, p) in found {
+            let d = -p.dir;
+            let cos = d.scalar_product(&n);
+            if cos > EPSILON {
+                //Плотность фотонов уже содержит косинус падения
+                color += reflected_light(t, hit, &n, &d, &p.power.div(cos));
+            }
+        }
+        color.div(consts::PI * r_sq)
+    }
+}
+
+//Ведёт фотон по зеркальным отражениям и преломлениям до первой непрозрачной незеркальной поверхности.
+//Первой должна встретиться фигура, на которую направляются фотоны: прямой свет считает shadow_part.
+//Доли преломления, отражения и рассеяния берутся как в shade, где они не обязаны давать в сумме 1,
+//поэтому вес фотона делится на вероятность выбранной ветви.
+fn trace_photon(scene: &Scene, mut ray: Ray, mut power: Vector3, falloff: Falloff, rng: &mut Rng, photons: &mut Vec<Photon>, stats: &mut RayCounters) {
+    for bounce in 0..MAX_BOUNCES {
+        let Some((_, hit)) = closest_hit(scene, &ray, stats) else { return };
+        //Свет точечного источника ослабевает по его Falloff вместо 1 / d²
+        if bounce == 0 {
+            power = power.mult(falloff.apply(hit.t * hit.t, hit.t));
+        }
+        let m = hit.material();
+        //Направления вне конусов не выбираются, поэтому свет через другие фигуры не собирается
+        if bounce == 0 && !casts_caustics(m) {
+            return;
+        }
+        let transparency = if m.transparency > EPSILON { m.transparency } else { 0.0 };
+        if (rng.next_f32() as Float) < transparency {
+            let (next, weight) = dielectric_bounce(&ray, &hit, rng, true, None, stats);
+            ray = next;
+            power = power.mult_per_element(&weight);
+            continue;
+        }
+        let n = facing_normal(&hit);
+        let mirror = match m.pbr {
+            Some(p) => p.mirror_reflectance(&m.color, -ray.dir.scalar_product(&n)),
+            None => Vector3::new(m.refl, m.refl, m.refl),
+        };
+        let k = mirror.x.max(mirror.y).max(mirror.z).min(1.0);
+        if k > EPSILON && (rng.next_f32() as Float) < k {
+            let dir = ray.dir - n.mult(2.0 * ray.dir.scalar_product(&n));
+            ray = Ray::spawn(&hit.point, &hit.normal, dir);
+            power = power.mult_per_element(&mirror.div(k * (1.0 - transparency)));
+            continue;
+        }
+        if bounce > 0 && transparency == 0.0 && k < 1.0 {
+            photons.push(Photon::new(hit.point, ray.dir, power.div(1.0 - k)));
+        }
+        return;
+    }
+}
@@ -1,26 +1,170 @@
-use std::sync::Mutex;
+use std::{sync::Mutex, time::Instant};
 
 use image::RgbImage;
 use rayon::prelude::*;
 
-use crate::{color::Color, scene::{Scene, LightSource}, math::{Ray, Vector3, EPSILON}, material::{Material, AIR_REFRACTION}, figure::FigureKind};
+use crate::{aov::{AovBuffers, PixelAov, primary_aov}, color::Color, scene::{Scene, LightSource}, math::{consts, Float, Ray, Vector3, EPSILON}, material::{Material, AIR_REFRACTION}, brdf::Pbr, path::{path_trace, path_trace_at}, bdpt::bidirectional, spectrum::Spectrum, lights::{LightRef, LightSampler}, figure::{FigureKind, HitRecord}, stats::{RenderStats, RayCounters}, random::Rng, packet::{closest_hits, RayPacket, LANES}};
 
-pub fn render(scene: &Scene, x: usize, y: usize) -> Vec<Color> {
+//Точек на стороне сетки при освещении светящимися фигурами без случайных чисел
+pub const AREA_LIGHT_SAMPLES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Integrator {
+    //Рекурсивная трассировка: точечный свет, зеркальное отражение и преломление
+    Whitted,
+    //Трассировка путей со случайным выбором направления по BRDF (path.rs)
+    Path,
+    //Двунаправленная трассировка путей (bdpt.rs)
+    Bidirectional,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderSettings {
+    pub samples: u32,
+    pub seed: u64,
+    pub integrator: Integrator,
+    //Источников прямого освещения на точку, выбранных по мощности, 0 - все (lights.rs).
+    //Для Integrator::Bidirectional не используется: там всегда один источник
+    pub light_samples: u32,
+    //Трассировка путей на случайных длинах волн для дисперсии в стекле (spectrum.rs)
+    pub spectral: bool,
+}
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self { samples: 1, seed: 0, integrator: Integrator::Whitted, light_samples: 0, spectral: false }
+    }
+}
+
+pub fn render(scene: &Scene, x: usize, y: usize, settings: &RenderSettings, stats: &mut RenderStats) -> Vec<Color> {
+    render_radiance(scene, x, y, settings, stats).iter().map(Color::from_vector3).collect()
+}
+
+//Линейная яркость пикселей без обрезки до 8 бит, например для шумоподавления.
+//При одном сэмпле луч идёт через центр пикселя, при нескольких - через случайные точки внутри него.
+//Трассировка путей случайна и при одном сэмпле. Спектральный режим - только для Integrator::Path.
+pub fn render_radiance(scene: &Scene, x: usize, y: usize, settings: &RenderSettings, stats: &mut RenderStats) -> Vec<Vector3> {
+    if settings.integrator != Integrator::Whitted {
+        //Подпуть источника начинается с одного источника, выбранного по мощности,
+        //поэтому Bidirectional не использует settings.light_samples
+        let light_samples = if settings.integrator == Integrator::Bidirectional { 1 } else { settings.light_samples };
+        let lights = LightSampler::new(scene, light_samples);
+        let spectrum = Spectrum::new();
+        return render_pixels(scene, x, y, stats, Vector3::new(0.0, 0.0, 0.0), |i, r, local| {
+            let mut c = Vector3::new(0.0, 0.0, 0.0);
+            for sample in 0..settings.samples.max(1) {
+                let mut rng = Rng::for_sample(settings.seed, i, sample);
+                let jittered;
+                let r = if settings.samples <= 1 {
+                    r
+                } else {
+                    jittered = scene.image.get_ray(x, y, i % x, i / x, rng.next_f32() as Float, rng.next_f32() as Float);
+                    &jittered
+                };
+                local.primary += 1;
+                c += match settings.integrator {
+                    Integrator::Bidirectional => bidirectional(scene, r, &lights, &mut rng, local),
+                    _ if settings.spectral => {
+                        let (w, pdf) = spectrum.sample(rng.next_f32() as Float);
+                        match path_trace_at(scene, r, &lights, Some(w), &mut rng, local) {
+                            (radiance, true) => spectrum.to_rgb(w, pdf, &radiance),
+                            (radiance, false) => radiance,
+                        }
+                    }
+                    _ => path_trace(scene, r, &lights, &mut rng, local),
+                };
+            }
+            c.div(settings.samples.max(1) as Float)
+        });
+    }
+    let lights = LightSampler::new(scene, settings.light_samples);
+    if cfg!(feature = "packets") && settings.samples <= 1 {
+        return trace_packets(scene, x, y, &lights, settings.seed, stats);
+    }
+    render_pixels(scene, x, y, stats, Vector3::new(0.0, 0.0, 0.0), |i, r, local| {
+        if settings.samples <= 1 {
+            local.primary += 1;
+            return raytrace(0, scene, r, 1.0, &lights, &mut Rng::for_sample(settings.seed, i, 0), local);
+        }
+        let mut c = Vector3::new(0.0, 0.0, 0.0);
+        for sample in 0..settings.samples {
+            let mut rng = Rng::for_sample(settings.seed, i, sample);
+            let r = scene.image.get_ray(x, y, i % x, i / x, rng.next_f32() as Float, rng.next_f32() as Float);
+            local.primary += 1;
+            c += raytrace(0, scene, &r, 1.0, &lights, &mut rng, local);
+        }
+        c.div(settings.samples as Float)
+    })
+}
+
+//Вспомогательные буферы: глубина, нормаль, альбедо и номер фигуры
+pub fn render_aovs(scene: &Scene, x: usize, y: usize, stats: &mut RenderStats) -> AovBuffers {
+    let pixels = render_pixels(scene, x, y, stats, PixelAov::MISS, |_, r, local| {
+        local.primary += 1;
+        primary_aov(scene, r, local)
+    });
+    AovBuffers::new(x, y, pixels)
+}
+
+//Первичные лучи пересекаются со сценой пакетами по LANES соседних пикселей,
+//дальше каждый луч обрабатывается как обычно
+pub fn render_packets(scene: &Scene, x: usize, y: usize, settings: &RenderSettings, stats: &mut RenderStats) -> Vec<Color> {
+    let lights = LightSampler::new(scene, settings.light_samples);
+    trace_packets(scene, x, y, &lights, settings.seed, stats).iter().map(Color::from_vector3).collect()
+}
+fn trace_packets(scene: &Scene, x: usize, y: usize, lights: &LightSampler, seed: u64, stats: &mut RenderStats) -> Vec<Vector3> {
+    let bounds: Vec<_> = scene.figures.iter().map(|f| f.bounds()).collect();
+    render_chunks(scene, x, y, stats, Vector3::new(0.0, 0.0, 0.0), |chunk, local| {
+        let mut res = Vec::with_capacity(chunk.len());
+        for c in chunk.chunks(LANES) {
+            let rays: Vec<Ray> = c.iter().map(|(_, r)| *r).collect();
+            let hits = closest_hits(scene, &bounds, &RayPacket::new(&rays), local);
+            for ((i, r), h) in c.iter().zip(hits) {
+                local.primary += 1;
+                local.traced_at_depth(0);
+                let mut rng = Rng::for_sample(seed, *i, 0);
+                let c = h.map_or_else(|| scene.background.radiance(&r.dir), |(_, hit)| shade(0, scene, r, &hit, 1.0, lights, &mut rng, local));
+                res.push(c);
+            }
+        }
+        res
+    })
+}
+
+fn render_pixels<T, F>(scene: &Scene, x: usize, y: usize, stats: &mut RenderStats, empty: T, f: F) -> Vec<T>
+where
+    T: Clone + Send,
+    F: Fn(usize, &Ray, &mut RayCounters) -> T + Sync,
+{
+    render_chunks(scene, x, y, stats, empty, |chunk, local| chunk.iter().map(|(i, r)| f(*i, r, local)).collect())
+}
+
+fn render_chunks<T, F>(scene: &Scene, x: usize, y: usize, stats: &mut RenderStats, empty: T, f: F) -> Vec<T>
+where
+    T: Clone + Send,
+    F: Fn(&[(usize, Ray)], &mut RayCounters) -> Vec<T> + Sync,
+{
+    let begin = Instant::now();
     let p: Vec<_> = scene.image.get_rays(x, y).into_iter().enumerate().collect();
+    stats.ray_generation += begin.elapsed();
 
-    let b = Mutex::new(vec![Color::BLACK; x * y]);
+    let begin = Instant::now();
+    let b = Mutex::new(vec![empty; x * y]);
+    let counters = Mutex::new(RayCounters::default());
     let chunk_size = 1000;
     p.par_chunks(chunk_size)
         .for_each(|x|{
+            let mut local = RayCounters::default();
             let temp_buffer : Vec<_> = 
-            x.iter()
-            .map(|(i, y)|(i, Color::from_vector3(&raytrace(0, scene, y, 1.0)))).collect();
+            x.iter().map(|(i, _)| i).zip(f(x, &mut local)).collect();
             let mut t =  b.lock().unwrap();
             for (i, c) in temp_buffer {
                 t[*i] = c;
             }
-            //drop(t)
+            drop(t);
+            counters.lock().unwrap().merge(&local);
         });
+    stats.tracing += begin.elapsed();
+    stats.counters.merge(&counters.into_inner().unwrap());
     b.into_inner().unwrap()
 }
 
@@ -28,107 +172,228 @@ pub fn save_to_image(i: &[Color], x:usize, y: usize) -> RgbImage {
     let t = i.iter().flat_map(|c| [c.r, c.g, c.b]).collect();
     RgbImage::from_vec(x as u32, y as u32, t).unwrap()
 }
-#[inline(always)]
-pub fn intencity_distance(int: f32, dist: f32) -> f32 {
-    int / (dist.powi(2) * 0.3 + dist * 0.5 )
+
+//Ближайшее пересечение луча со сценой и номер фигуры
+pub fn closest_hit<'a>(scene: &'a Scene, r: &Ray, stats: &mut RayCounters) -> Option<(usize, HitRecord<'a>)> {
+    closest_hit_in(scene, r, r.t_min(), Float::INFINITY, stats)
+}
+pub fn closest_hit_in<'a>(scene: &'a Scene, r: &Ray, t_min: Float, t_max: Float, stats: &mut RayCounters) -> Option<(usize, HitRecord<'a>)> {
+    let mut res: Option<(usize, HitRecord)> = None;
+    let mut t_max = t_max;
+    for (i, f) in scene.figures.iter().enumerate() {
+        stats.intersection_test(f);
+        if let Some(h) = f.hit_in(r, t_min, t_max) {
+            t_max = h.t;
+            res = Some((i, h));
+        }
+    }
+    res
+}
+//Есть ли что-нибудь на отрезке луча, останавливается на первом найденном препятствии
+pub fn occluded(scene: &Scene, r: &Ray, t_min: Float, t_max: Float, stats: &mut RayCounters) -> bool {
+    scene.figures.iter().any(|f| {
+        stats.intersection_test(f);
+        f.occludes(r, t_min, t_max)
+    })
+}
+//Доля и цвет света, прошедшего через прозрачные фигуры на отрезке луча.
+//None, как только свет полностью перекрыт.
+pub fn transmittance(scene: &Scene, r: &Ray, t_min: Float, t_max: Float, stats: &mut RayCounters) -> Option<(Float, Vector3)> {
+    let mut intensity = 1.0;
+    let mut color = Vector3::new(1.0, 1.0, 1.0);
+    for f in &scene.figures {
+        stats.intersection_test(f);
+        if f.occludes(r, t_min, t_max) {
+            let m = f.get_material();
+            intensity *= m.transparency;
+            if intensity < EPSILON {return None};
+            color = color.mult_per_element(&m.color);
+        }
+    }
+    Some((intensity, color))
 }
 
-//Цвет пикселя
+//Цвет пикселя. Прямой свет в каждой точке дают источники, выбранные lights
 
-pub fn raytrace(iter: u32, scene: &Scene, r: &Ray, portion: f32) -> Vector3 {
+pub fn raytrace(iter: u32, scene: &Scene, r: &Ray, portion: Float, lights: &LightSampler, rng: &mut Rng, stats: &mut RayCounters) -> Vector3 {
     if iter > 10 {return Vector3::new(0.0, 0.0, 0.0);}
-    let mut d = f32::MAX;
-    let mut f_i = usize::MAX;
-    for (i, f) in scene.figures.iter().enumerate() {
-        if let Some(p) = f.intersect(r) {
-            let d_new = (p - r.pos).len_sq();
-            if d_new < d {
-                d = d_new;
-                f_i = i;
-            }
-        }
+    stats.traced_at_depth(iter);
+    match closest_hit(scene, r, stats) {
+        Some((_, hit)) => shade(iter, scene, r, &hit, portion, lights, rng, stats),
+        None => scene.background.radiance(&r.dir).mult(portion),
     }
-    if f_i != usize::MAX {
-        let f = &scene.figures[f_i];
-        let (t, normal) = f.intersect_with_normal(r).unwrap();
-        let m = f.get_material();
-        let int = m.base_illumination;
-        let mut color = m.color.mult(int);
-        for l in &scene.lights {
-            if let Some(c) = shadow_part(scene, r,&t, &normal, l, m) {
-                let c_res = c.mult_per_element(&m.color);
-                color += c_res;
+}
+//Цвет найденного пересечения: освещение, отражение и преломление
+#[allow(clippy::too_many_arguments)]
+pub fn shade(iter: u32, scene: &Scene, r: &Ray, hit: &HitRecord, portion: Float, lights: &LightSampler, rng: &mut Rng, stats: &mut RayCounters) -> Vector3 {
+    let m = hit.material();
+    let int = m.base_illumination;
+    let mut color = m.color.mult(int);
+    let mut emitted = Vector3::new(0.0, 0.0, 0.0);
+    lights.for_each_choice(rng, |l, weight, _| match l {
+        LightRef::Point(l) => {
+            if let Some(c) = shadow_part(scene, r, hit, l, stats) {
+                color += c.mult(weight);
             }
         }
-        if m.refl > EPSILON {
-            let c = mirror_part(iter, scene, &t, r, &normal, portion * m.refl);
-            color += c;
-        }
-        if m.transparency > EPSILON {
-            let c = refraction_part(iter, scene, &t, r, &normal, f, portion * m.transparency, m);
-            color += c;
+        LightRef::Emitter(f) => emitted += emitter_light(scene, r, hit, f, stats).mult(weight),
+    });
+    color += emitted + m.emission;
+    if let Some(map) = &scene.caustics {
+        color += map.caustic_part(r, hit);
+    }
+    if let Some(p) = m.pbr {
+        //Отражение окрашено у металлов, поэтому доля передаётся по наибольшему каналу
+        let f = p.mirror_reflectance(&m.color, -r.dir.scalar_product(&facing_normal(hit)));
+        let k = f.x.max(f.y).max(f.z);
+        if k > EPSILON {
+            color += mirror_part(iter, scene, r, hit, portion * k, lights, rng, stats).mult_per_element(&f.div(k));
         }
-        color.mult(portion)
+    } else if m.refl > EPSILON {
+        let c = mirror_part(iter, scene, r, hit, portion * m.refl, lights, rng, stats);
+        color += c;
     }
-    else {
-        Vector3{x:0.0, y:0.0, z:0.0}
+    if m.transparency > EPSILON {
+        let c = refraction_part(iter, scene, r, hit, portion * m.transparency, lights, rng, stats);
+        color += c;
     }
+    color.mult(portion)
 }
-//Цвет как вектор
-pub fn shadow_part( scene: &Scene, t: &Ray, point: &Vector3, side_normal: &Vector3, l: &LightSource, m: &Material) -> Option<Vector3> {
-    let d = &l.pos - point;
-    let d_len = d.len();
-    let d_norm = d.div(d_len);
-    //println!("Point of collision: {point}, vector to light: {d_norm}");
-    let diff = d_norm.scalar_product(side_normal);
-    if diff > 0.0 {
-        let light_ray = &Ray { pos: *point, dir: d_norm };
-        let mut intensity = l.intencity;
-        let mut color = l.color;
-        for i in &scene.figures {
-            if let Some(p) = i.intersect(light_ray) {
-                if (&p - point).len() < d_len {
-                    let m = i.get_material();
-                    intensity *= m.transparency;
-                    if intensity < EPSILON {return None};
-                    color = color.mult_per_element(&m.color);
-                }
-            }
-        }
-        let refl = light_ray.reflect(point, side_normal);
+//Нормаль со стороны, с которой пришёл луч
+pub fn facing_normal(hit: &HitRecord) -> Vector3 {
+    if hit.front_face { hit.normal } else { -hit.normal }
+}
+//Направление на источник и дошедший от него свет с учётом расстояния.
+//None, если источник за поверхностью или перекрыт.
+pub fn incoming_light(scene: &Scene, hit: &HitRecord, normal: &Vector3, l: &LightSource, stats: &mut RayCounters) -> Option<(Vector3, Vector3)> {
+    let local = l.intencity_at((l.pos - hit.point).len());
+    visible_light(scene, hit, normal, &l.pos, l.color.mult(local), stats)
+}
+//Свет источника, если на пути к нему нет никаких фигур, в том числе прозрачных.
+//Свет сквозь прозрачные фигуры тогда приносят только преломлённые пути или фотоны каустик.
+pub fn unoccluded_light(scene: &Scene, hit: &HitRecord, normal: &Vector3, l: &LightSource, stats: &mut RayCounters) -> Option<(Vector3, Vector3)> {
+    let local = l.intencity_at((l.pos - hit.point).len());
+    unoccluded_point(scene, hit, normal, &l.pos, l.color.mult(local), stats)
+}
+//Как visible_light, но любая фигура на пути перекрывает свет
+pub fn unoccluded_point(scene: &Scene, hit: &HitRecord, normal: &Vector3, target: &Vector3, light: Vector3, stats: &mut RayCounters) -> Option<(Vector3, Vector3)> {
+    let d_norm = (target - &hit.point).normalize();
+    let dist = (target - &hit.point).len() - Ray { pos: *target, dir: d_norm }.t_min();
+    let light = unoccluded_along(scene, hit, normal, &d_norm, dist, light, stats)?;
+    Some((d_norm, light))
+}
+//Как visible_along, но любая фигура на пути перекрывает свет
+pub fn unoccluded_along(scene: &Scene, hit: &HitRecord, normal: &Vector3, dir: &Vector3, dist: Float, light: Vector3, stats: &mut RayCounters) -> Option<Vector3> {
+    if dir.scalar_product(normal) <= 0.0 {
+        return None;
+    }
+    let light_ray = Ray::spawn(&hit.point, &hit.normal, *dir);
+    stats.shadow += 1;
+    let t_max = dist - (light_ray.pos - hit.point).len();
+    (!occluded(scene, &light_ray, light_ray.t_min(), t_max, stats)).then_some(light)
+}
+//Свет из точки target, окрашенный прозрачными фигурами на пути, и направление на неё
+pub fn visible_light(scene: &Scene, hit: &HitRecord, normal: &Vector3, target: &Vector3, light: Vector3, stats: &mut RayCounters) -> Option<(Vector3, Vector3)> {
+    let d_norm = (target - &hit.point).normalize();
+    //Точка на светящейся поверхности не должна перекрываться самой поверхностью
+    let dist = (target - &hit.point).len() - Ray { pos: *target, dir: d_norm }.t_min();
+    let light = visible_along(scene, hit, normal, &d_norm, dist, light, stats)?;
+    Some((d_norm, light))
+}
+//Свет, пришедший по направлению dir с расстояния dist (бесконечность для фона)
+pub fn visible_along(scene: &Scene, hit: &HitRecord, normal: &Vector3, dir: &Vector3, dist: Float, light: Vector3, stats: &mut RayCounters) -> Option<Vector3> {
+    if dir.scalar_product(normal) <= 0.0 {
+        return None;
+    }
+    let light_ray = &Ray::spawn(&hit.point, &hit.normal, *dir);
+    stats.shadow += 1;
+    let t_max = dist - (light_ray.pos - hit.point).len();
+    let (k, tint) = transmittance(scene, light_ray, light_ray.t_min(), t_max, stats)?;
+    Some(light.mult_per_element(&tint).mult(k))
+}
+//Нормаль, с которой материал принимает свет: у старых материалов освещена только внешняя сторона
+pub fn lit_normal(hit: &HitRecord) -> Vector3 {
+    if hit.material().pbr.is_some() { facing_normal(hit) } else { hit.normal }
+}
+//Вклад источника в цвет точки
+pub fn shadow_part(scene: &Scene, t: &Ray, hit: &HitRecord, l: &LightSource, stats: &mut RayCounters) -> Option<Vector3> {
+    let n = lit_normal(hit);
+    let (d_norm, light) = match scene.caustics {
+        Some(_) => unoccluded_light(scene, hit, &n, l, stats)?,
+        None => incoming_light(scene, hit, &n, l, stats)?,
+    };
+    Some(reflected_light(t, hit, &n, &d_norm, &light))
+}
+//Свет, пришедший по направлению d_norm и отражённый материалом в сторону камеры
+pub fn reflected_light(t: &Ray, hit: &HitRecord, n: &Vector3, d_norm: &Vector3, light: &Vector3) -> Vector3 {
+    let m = hit.material();
+    if let Some(p) = m.pbr {
+        return pbr_light(&p, m, n, &-t.dir, d_norm, light);
+    }
+    let diff = d_norm.scalar_product(n);
+    let refl = *d_norm - n.mult(2. * diff);
 
-        let diff_part = m.diff * diff;
-        let spec_part = m.specular * refl.dir.scalar_product(&t.dir).max(0.0).powf(m.shininess);
-        //let spec_part = 0.0;
-        let local = intencity_distance(intensity, d_len);
-        Some(color.mult((diff_part + spec_part) * local))
+    let diff_part = m.diff * diff;
+    let spec_part = m.specular * refl.scalar_product(&t.dir).max(0.0).powf(m.shininess);
+    light.mult(diff_part + spec_part).mult_per_element(&m.color)
+}
+//Свет источника, отражённый по BRDF. Интенсивность источника задана так, что белая
+//ламбертова поверхность отражает light * cos, поэтому BRDF умножается на π.
+pub fn pbr_light(p: &Pbr, m: &Material, n: &Vector3, v: &Vector3, l: &Vector3, light: &Vector3) -> Vector3 {
+    light.mult_per_element(&p.eval(&m.color, n, v, l)).mult(consts::PI)
+}
+//Освещение светящейся фигурой f по AREA_LIGHT_SAMPLES^2 точкам на равномерной сетке.
+//Свет точки приводится к единицам точечного источника: освещённость, делённая на π.
+pub fn emitter_light(scene: &Scene, t: &Ray, hit: &HitRecord, f: &FigureKind, stats: &mut RayCounters) -> Vector3 {
+    let mut color = Vector3::new(0.0, 0.0, 0.0);
+    let n = lit_normal(hit);
+    let k = AREA_LIGHT_SAMPLES;
+    let (emission, area) = (f.get_material().emission, f.area().unwrap_or(0.0));
+    for i in 0..k * k {
+        let u = (((i % k) as Float + 0.5) / k as Float, ((i / k) as Float + 0.5) / k as Float);
+        let Some((q, q_normal)) = f.sample_surface(u) else { break };
+        let d = q - hit.point;
+        let cos = q_normal.scalar_product(&d).abs() / d.len();
+        let scale = cos * area / (d.len_sq() * (k * k) as Float * consts::PI);
+        if let Some((d_norm, light)) = visible_light(scene, hit, &n, &q, emission.mult(scale), stats) {
+            color += reflected_light(t, hit, &n, &d_norm, &light);
+        }
     }
-    else {None}
+    color
 }
-pub fn mirror_part(iter: u32, scene: &Scene, point: &Vector3, r: &Ray, side_normal: &Vector3, portion: f32) -> Vector3 {
+#[allow(clippy::too_many_arguments)]
+pub fn mirror_part(iter: u32, scene: &Scene, r: &Ray, hit: &HitRecord, portion: Float, lights: &LightSampler, rng: &mut Rng, stats: &mut RayCounters) -> Vector3 {
     if portion < EPSILON { return Vector3::new(0.0, 0.0, 0.0); }
-    let t = r.reflect(point, side_normal);
-    raytrace(iter + 1, scene, &t, portion)
+    let t = r.reflect(&hit.point, &hit.normal);
+    let t = Ray::spawn(&hit.point, &hit.normal, t.dir);
+    stats.reflection += 1;
+    raytrace(iter + 1, scene, &t, portion, lights, rng, stats)
 }
-pub fn refraction_part(iter: u32 ,scene: &Scene, point: &Vector3, r: &Ray, side_normal: &Vector3, f: &FigureKind, portion: f32, m: &Material) -> Vector3 {
+#[allow(clippy::too_many_arguments)]
+pub fn refraction_part(iter: u32, scene: &Scene, r: &Ray, hit: &HitRecord, portion: Float, lights: &LightSampler, rng: &mut Rng, stats: &mut RayCounters) -> Vector3 {
     //if portion < EPSILON { return Vector3::new(0.0, 0.0, 0.0); }
     if iter > 10 {return  Vector3::new(0.0, 0.0, 0.0);}
-    let normal_product = r.dir.scalar_product(side_normal);
-    let (n1, n2, norm, normal_vec) =  if normal_product < 0.0 {
+    let point = &hit.point;
+    let m = hit.material();
+    let normal_product = r.dir.scalar_product(&hit.normal);
+    let (n1, n2, norm, normal_vec) =  if hit.front_face {
         //Входим в материал
-        (AIR_REFRACTION, m.refraction, normal_product, *side_normal)
+        (AIR_REFRACTION, m.refraction, normal_product, hit.normal)
     } else {
-        (m.refraction, AIR_REFRACTION, -normal_product, -side_normal)
+        (m.refraction, AIR_REFRACTION, -normal_product, -hit.normal)
     };
 
     let n1n2 = n1 / n2;
-    let _under_root_expr = 1.0 - (n1n2).powi(2)*(1.0 - norm.powi(2));
-    let cos_fita = f32::sqrt(1.0 - (n1n2).powi(2)*(1.0 - norm.powi(2)));
+    let cos_fita = Float::sqrt(1.0 - (n1n2).powi(2)*(1.0 - norm.powi(2)));
     //if cos_fita.is_nan() {cos_fita = 0.0}
     if cos_fita.is_nan() {
         let t = r.reflect(point, &normal_vec);
-        let internal_reflect = raytrace(iter + 1, scene, &t, portion).mult(m.refl);
-        let outside = raytrace(iter + 1, scene, &Ray::new_normalize(*point, &r.dir), portion).mult(m.transparency);
+        let t = Ray::spawn(point, &hit.normal, t.dir);
+        stats.reflection += 1;
+        let internal_reflect = raytrace(iter + 1, scene, &t, portion, lights, rng, stats).mult(m.refl);
+        stats.refraction += 1;
+        let outside = Ray::spawn(point, &hit.normal, r.dir);
+        let outside = raytrace(iter + 1, scene, &outside, portion, lights, rng, stats).mult(m.transparency);
         return internal_reflect + outside; 
         //return Vector3::new(0.0, 0.0, 0.0);
         //println!("HI");
@@ -136,9 +401,11 @@ pub fn refraction_part(iter: u32 ,scene: &Scene, point: &Vector3, r: &Ray, side_
     
     let t = (r.dir.mult(n1n2) - normal_vec.mult(cos_fita + n1n2 * norm)).normalize();
     //println!("n1n2:{n1n2:.4}, E: {:.4} , Cos: {:.4}, Incoming vector: {:30}, Normal vector: {:30}, outcoming vector: {:30}",under_root_expr, cos_fita , r.dir, normal_vec, t);
-    let new_r = Ray{pos: *point, dir: t}.move_forward(0.001);
-    if let Some((p, n)) = f.intersect_with_normal(&new_r) {
-        return refraction_part(iter + 1 , scene, &p, &new_r, &n, f, portion, m);
+    let new_r = Ray::spawn(point, &hit.normal, t);
+    stats.refraction += 1;
+    stats.intersection_test(hit.figure);
+    if let Some(h) = hit.figure.hit(&new_r) {
+        return refraction_part(iter + 1 , scene, &new_r, &h, portion, lights, rng, stats);
     }
-    raytrace(iter + 1, scene, &new_r, portion).mult_per_element(&m.color)
-}
\ No newline at end of file
+    raytrace(iter + 1, scene, &new_r, portion, lights, rng, stats).mult_per_element(&m.color)
+}
@@ -1,6 +1,13 @@
+use std::sync::Arc;
+
 use crate::{
-    figure::FigureKind,
-    math::{Ray, Vector3}, color::Color, material::Material,
+    background::Background,
+    figure::{CsgOp, FigureKind},
+    brdf::Pbr,
+    math::{consts, Float, Ray, Transform, Transformable, Vector3}, color::Color, material::Material,
+    library::{LibraryError, MaterialLibrary},
+    photon::PhotonMap,
+    sdf::Sdf,
 };
 
 #[derive(Debug, Clone)]
@@ -8,11 +15,22 @@ pub struct Scene {
     pub figures: Vec<FigureKind>,
     pub image: RenderSurface,
     pub lights: Vec<LightSource>,
+    pub background: Background,
+    //Карта каустик от точечных источников для shade, None - свет сквозь прозрачные фигуры
+    //приходит ослабленными тенями
+    pub caustics: Option<PhotonMap>,
 }
 
 impl Scene {
+    //Светящиеся фигуры, на которых можно выбирать точки для прямого освещения
+    pub fn emitters(&self) -> impl Iterator<Item = &FigureKind> {
+        self.figures.iter().filter(|f| f.get_material().is_emissive() && f.area().is_some())
+    }
     //2, 2 
-    pub fn get_room() -> Self{
+    pub fn get_room() -> Self {
+        Self::get_room_in(&MaterialLibrary::default()).expect("built-in materials")
+    }
+    pub fn get_room_in(lib: &MaterialLibrary) -> Result<Self, LibraryError> {
         let r = RenderSurface {
             top_left: Vector3::new(-1.5, -1.5, -1.95),
             top_right: Vector3::new(1.5, -1.5, -1.95),
@@ -24,43 +42,46 @@ impl Scene {
             &Vector3::new(-2.0, -2.0, -2.0),
             &Vector3::new(-2.0, -2.0, 2.0),
             &Vector3::new(2.0, -2.0, -2.0), 
-            Material::FRONTWALLS);
+            lib.material("frontwalls")?);
         let front = FigureKind::new_side(
             &Vector3::new(-2.0, -2.0, 2.0),
             &Vector3::new(-2.0, 2.0, 2.0),
             &Vector3::new(2.0, -2.0, 2.0), 
-            Material::FRONTWALLS);
+            lib.material("frontwalls")?);
         let down = FigureKind::new_side(
           &Vector3::new(-2.0, 2.0, -2.0),
           &Vector3::new(2.0, 2.0, -2.0),
           &Vector3::new(-2.0, 2.0, 2.0),
-          Material::FRONTWALLS);
+          lib.material("frontwalls")?);
         let left = FigureKind::new_side(
             &Vector3::new(-2.0, -2.0, 2.0),
             &Vector3::new(-2.0, -2.0, -2.0),
             &Vector3::new(-2.0, 2.0, 2.0),
-            Material::LEFTWALL);
+            lib.material("leftwall")?);
         let right = FigureKind::new_side(
           &Vector3::new(2.0, 2.0, 2.0),
           &Vector3::new(2.0, 2.0, -2.0),
           &Vector3::new(2.0, -2.0, 2.0),
-          Material::RIGHTWALL);
+          lib.material("rightwall")?);
         let back = FigureKind::new_side(
             &Vector3::new(-2.0, -2.0, -2.0),
             &Vector3::new(2.0, -2.0, -2.0),
             &Vector3::new(-2.0, 2.0, -2.0),
-            Material::BACKWALLS);
+            lib.material("backwalls")?);
         let cube = FigureKind::new_cube(
             &Vector3::new(-1.5, 1.0, 1.5), 
             &Vector3::new(-0.5, 1.0, 1.5), 
             &Vector3::new(-1.5, 2.0, 1.5), 
-            &Vector3::new(-1.5, 1.0, 0.5), Material::CUBE);
+            &Vector3::new(-1.5, 1.0, 0.5), lib.material("cube")?);
         let cube2 = FigureKind::new_cube_from_d(
-            &Vector3::new(0.5, 0.5, 1.0), 
-            &Vector3::new(1.0, 0.0, 0.0).rotate_y_axis(std::f32::consts::PI / 6.0 ), 
-            &Vector3::new(0.0, 1.499, 0.0).rotate_y_axis(std::f32::consts::PI / 6.0 ), 
-            &Vector3::new(0.0, 0.0, -1.0).rotate_y_axis(std::f32::consts::PI / 6.0 ), Material::CUBEMETALIC);
-        let sphere = FigureKind::Sphere { r: 0.2, pos: Vector3 { x: -1.0, y: 1.5, z: -0.5 }, m: Material::CUBETRANSPARENT };
+            &Vector3::new(0.0, 0.0, 0.0), 
+            &Vector3::new(1.0, 0.0, 0.0), 
+            &Vector3::new(0.0, 1.499, 0.0), 
+            &Vector3::new(0.0, 0.0, -1.0), lib.material("cubemetalic")?);
+        let cube2 = FigureKind::new_instance(&Arc::new(cube2),
+            Transform::rotate_y(consts::PI / 6.0)
+                .then(&Transform::translate(&Vector3::new(0.5, 0.5, 1.0))));
+        let sphere = FigureKind::Sphere { r: 0.45, pos: Vector3 { x: -1.0, y: 1.5, z: -0.5 }, m: lib.material("cubetransparent")? };
         
         let v = vec![
             top, 
@@ -74,10 +95,182 @@ impl Scene {
             sphere
         ];
 
-        let l1 = LightSource { pos: Vector3::new(1.6, -1.6, -0.1), color: Color::WHITE.to_vector3(), intencity: 1.5 };
-        let l2 = LightSource { pos: Vector3::new(-1.6, -1.6, -0.1), color: Color::WHITE.to_vector3(), intencity: 1.5 };
+        let l1 = LightSource { pos: Vector3::new(1.6, -1.6, -0.1), color: Color::WHITE.to_vector3(), intencity: 1.5, falloff: Falloff::default() };
+        let l2 = LightSource { pos: Vector3::new(-1.6, -1.6, -0.1), color: Color::WHITE.to_vector3(), intencity: 1.5, falloff: Falloff::default() };
+
+        Ok(Scene { figures: v, image: r, lights: vec![l1, l2], background: Background::BLACK, caustics: None })
+    }
+    //Комната, освещённая только светящейся панелью под потолком
+    pub fn get_cornell() -> Self {
+        Self::get_cornell_in(&MaterialLibrary::default()).expect("built-in materials")
+    }
+    pub fn get_cornell_in(lib: &MaterialLibrary) -> Result<Self, LibraryError> {
+        let mut s = Self::get_room_in(lib)?;
+        let panel = FigureKind::new_side(
+            &Vector3::new(-0.6, -1.99, -0.1),
+            &Vector3::new(0.6, -1.99, -0.1),
+            &Vector3::new(-0.6, -1.99, 1.1),
+            lib.material("panel_light")?);
+        s.figures.push(panel);
+        s.lights = vec![];
+        Ok(s)
+    }
+    //Двести слабых разноцветных источников над полом и несколько светящихся шаров
+    pub fn get_many_lights() -> Self {
+        Self::get_many_lights_in(&MaterialLibrary::default()).expect("built-in materials")
+    }
+    pub fn get_many_lights_in(lib: &MaterialLibrary) -> Result<Self, LibraryError> {
+        let mut s = Self::get_pbr_in(lib)?;
+        s.lights = (0..200)
+            .map(|i| {
+                let (x, z) = ((i % 20) as Float, (i / 20) as Float);
+                let hue = i as Float * 0.37;
+                let color = Vector3::new(hue.sin(), (hue + 2.1).sin(), (hue + 4.2).sin()).mult(0.5) + Vector3::new(0.5, 0.5, 0.5);
+                LightSource { pos: Vector3::new(-3.8 + 0.4 * x, -0.5 - 0.1 * (i % 3) as Float, 0.5 + 0.5 * z), color, intencity: 0.05, falloff: Falloff::default() }
+            })
+            .collect();
+        for (i, name) in ["orange_lamp", "amber_lamp", "yellow_lamp"].into_iter().enumerate() {
+            let pos = Vector3::new(-1.2 + 1.2 * i as Float, 1.45, 1.2);
+            s.figures.push(FigureKind::Sphere { r: 0.15, pos, m: lib.material(name)? });
+        }
+        Ok(s)
+    }
+    //Пол и задняя стена уходят до горизонта, на полу стоят тела вращения
+    pub fn get_primitives() -> Self {
+        Self::get_primitives_in(&MaterialLibrary::default()).expect("built-in materials")
+    }
+    pub fn get_primitives_in(lib: &MaterialLibrary) -> Result<Self, LibraryError> {
+        let r = RenderSurface {
+            top_left: Vector3::new(-1.5, -1.5, -1.95),
+            top_right: Vector3::new(1.5, -1.5, -1.95),
+            down_left: Vector3::new(-1.5, 1.5, -1.95),
+            foci_point: Vector3::new(0.0, 0.0, -4.95),
+        };
+        let floor = FigureKind::new_plane(&Vector3::new(0.0, 1.6, 0.0), &Vector3::new(0.0, -1.0, 0.0), lib.material("frontwalls")?);
+        let back = FigureKind::new_plane(&Vector3::new(0.0, 0.0, 8.0), &Vector3::new(0.0, 0.0, -1.0), lib.material("backwalls")?);
+        let disk = FigureKind::new_disk(&Vector3::new(1.3, 0.2, 4.0), &Vector3::new(-0.3, 0.0, -1.0), 0.9, lib.material("leftwall")?);
+        let cylinder = FigureKind::new_cylinder(&Vector3::new(-1.7, 1.6, 2.5), &Vector3::new(-1.7, 0.4, 2.5), 0.45, lib.material("cube")?);
+        let cone = FigureKind::new_cone(&Vector3::new(-0.2, 1.6, 3.2), &Vector3::new(-0.2, 0.1, 3.2), 0.6, lib.material("rightwall")?);
+        let torus = FigureKind::new_torus(&Vector3::new(1.2, 1.15, 1.6), &Vector3::new(0.3, -1.0, -0.6), 0.5, 0.17, lib.material("cubemetalic")?);
+        let sphere = FigureKind::Sphere { r: 0.35, pos: Vector3::new(-0.6, 1.25, 1.0), m: lib.material("cubetransparent")? };
+
+        let l1 = LightSource { pos: Vector3::new(0.0, -3.0, 0.0), color: Color::WHITE.to_vector3(), intencity: 2.5, falloff: Falloff::default() };
+        let l2 = LightSource { pos: Vector3::new(2.0, -2.0, -2.0), color: Color::WHITE.to_vector3(), intencity: 1.5, falloff: Falloff::default() };
+
+        Ok(Scene { figures: vec![floor, back, disk, cylinder, cone, torus, sphere], image: r, lights: vec![l1, l2], background: Background::BLACK, caustics: None })
+    }
+    //Тела, заданные функциями расстояния, рядом с аналитическим полом
+    pub fn get_sdf() -> Self {
+        Self::get_sdf_in(&MaterialLibrary::default()).expect("built-in materials")
+    }
+    pub fn get_sdf_in(lib: &MaterialLibrary) -> Result<Self, LibraryError> {
+        let mut s = Self::get_primitives_in(lib)?;
+        let bulb = FigureKind::Sdf { sdf: Sdf::Mandelbulb { power: 8.0, iterations: 8 }, m: lib.material("cube")? };
+        let bulb = FigureKind::new_instance(&Arc::new(bulb),
+            Transform::rotate_x(-consts::FRAC_PI_2)
+                .then(&Transform::scale(&Vector3::new(0.6, 0.6, 0.6)))
+                .then(&Transform::translate(&Vector3::new(0.0, 0.9, 2.2))));
+        let blob = Sdf::RoundBox { half: Vector3::new(0.3, 0.3, 0.3), r: 0.08 }
+            .smooth_union(Sdf::Sphere { r: 0.25 }.translate(&Vector3::new(0.25, -0.3, 0.0)), 0.2)
+            .translate(&Vector3::new(-1.5, 1.3, 1.8));
+        let twisted = Sdf::Box { half: Vector3::new(0.2, 0.6, 0.2) }
+            .twist(1.5)
+            .translate(&Vector3::new(1.5, 1.0, 2.2));
+        let row = Sdf::Sphere { r: 0.2 }
+            .repeat(&Vector3::new(0.8, 0.0, 0.0))
+            .translate(&Vector3::new(0.0, 1.4, 4.5));
+        s.figures = vec![
+            s.figures[0].clone(),
+            s.figures[1].clone(),
+            bulb,
+            FigureKind::Sdf { sdf: blob, m: lib.material("rightwall")? },
+            FigureKind::Sdf { sdf: twisted, m: lib.material("cubemetalic")? },
+            FigureKind::Sdf { sdf: row, m: lib.material("leftwall")? },
+        ];
+        Ok(s)
+    }
+    //Шары с материалами metallic/roughness: верхний ряд - золото, нижний - красный пластик,
+    //шероховатость растёт слева направо
+    pub fn get_pbr() -> Self {
+        Self::get_pbr_in(&MaterialLibrary::default()).expect("built-in materials")
+    }
+    pub fn get_pbr_in(lib: &MaterialLibrary) -> Result<Self, LibraryError> {
+        let mut s = Self::get_primitives_in(lib)?;
+        let floor = FigureKind::new_plane(&Vector3::new(0.0, 1.6, 0.0), &Vector3::new(0.0, -1.0, 0.0), lib.material("pbr_floor")?);
+        let mut figures = vec![floor, s.figures[1].clone()];
+        //Шероховатость ряда задаётся здесь, остальное берётся у именованного материала
+        let (gold, plastic) = (lib.material("gold")?, lib.material("red_plastic")?);
+        let rough = |m: Material, roughness| Material { pbr: Some(Pbr { roughness, ..m.as_pbr() }), ..m };
+        for i in 0..5 {
+            let roughness = 0.05 + 0.2 * i as Float;
+            let x = -1.6 + 0.8 * i as Float;
+            figures.push(FigureKind::Sphere { r: 0.32, pos: Vector3::new(x, 0.2, 2.6), m: rough(gold, roughness) });
+            figures.push(FigureKind::Sphere { r: 0.32, pos: Vector3::new(x, 1.28, 2.0), m: rough(plastic, roughness) });
+        }
+        s.figures = figures;
+        Ok(s)
+    }
+    //Клин из тяжёлого флинта с углом 20° перед рядом узких светящихся щелей.
+    //Щели, видимые сквозь клин, смещены и разложены в спектр (RenderSettings::spectral).
+    pub fn get_prism() -> Self {
+        Self::get_prism_in(&MaterialLibrary::default()).expect("built-in materials")
+    }
+    pub fn get_prism_in(lib: &MaterialLibrary) -> Result<Self, LibraryError> {
+        let mut s = Self::get_primitives_in(lib)?;
+        let (sin, cos) = (10.0 as Float).to_radians().sin_cos();
+        let glass = lib.material("flintglass")?;
+        let half_space = |pos: Vector3, normal: Vector3| Arc::new(FigureKind::new_plane(&pos, &normal, glass));
+        let c = Vector3::new(0.0, 0.5, 1.0);
+        let faces = FigureKind::new_csg(CsgOp::Intersection,
+            &half_space(c + Vector3::new(0.0, 0.0, -0.3), Vector3::new(-sin, 0.0, -cos)),
+            &half_space(c + Vector3::new(0.0, 0.0, 0.3), Vector3::new(-sin, 0.0, cos)));
+        let caps = FigureKind::new_csg(CsgOp::Intersection,
+            &half_space(Vector3::new(0.0, -0.5, 0.0), Vector3::new(0.0, -1.0, 0.0)),
+            &half_space(Vector3::new(0.0, 1.4, 0.0), Vector3::new(0.0, 1.0, 0.0)));
+        let prism = FigureKind::new_csg(CsgOp::Intersection, &Arc::new(faces), &Arc::new(FigureKind::new_csg(CsgOp::Intersection,
+            &Arc::new(caps), &half_space(Vector3::new(1.3, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0)))));
+        let mut figures = vec![s.figures[0].clone(), s.figures[1].clone(), prism];
+        for i in 0..13 {
+            let x = -3.0 + 0.5 * i as Float;
+            figures.push(FigureKind::new_side(
+                &Vector3::new(x, -1.5, 6.0),
+                &Vector3::new(x + 0.05, -1.5, 6.0),
+                &Vector3::new(x, 1.6, 6.0),
+                lib.material("slit_light")?));
+        }
+        s.figures = figures;
+        Ok(s)
+    }
+    //Тела, собранные булевыми операциями: куб с полостью, скруглённый кубик,
+    //срезанная плоскостью сфера и крест из цилиндров
+    pub fn get_csg() -> Self {
+        Self::get_csg_in(&MaterialLibrary::default()).expect("built-in materials")
+    }
+    pub fn get_csg_in(lib: &MaterialLibrary) -> Result<Self, LibraryError> {
+        let mut s = Self::get_primitives_in(lib)?;
+        let cube = |c: Vector3, a: Float, m: Material| Arc::new(FigureKind::new_cube_from_d(
+            &(c + Vector3::new(-a, -a, a)),
+            &Vector3::new(2.0 * a, 0.0, 0.0),
+            &Vector3::new(0.0, 2.0 * a, 0.0),
+            &Vector3::new(0.0, 0.0, -2.0 * a),
+            m));
+        let sphere = |c: Vector3, r: Float, m: Material| Arc::new(FigureKind::Sphere { r, pos: c, m });
+
+        let c = Vector3::new(-1.1, 1.1, 1.8);
+        let hollow = FigureKind::new_csg(CsgOp::Difference, &cube(c, 0.5, lib.material("cubetransparent")?), &sphere(c, 0.62, lib.material("cube")?));
+        let c = Vector3::new(0.4, 1.2, 1.2);
+        let die = FigureKind::new_csg(CsgOp::Intersection, &cube(c, 0.4, lib.material("rightwall")?), &sphere(c, 0.55, lib.material("rightwall")?));
+        let c = Vector3::new(1.5, 1.0, 2.6);
+        let dome = FigureKind::new_csg(CsgOp::Intersection,
+            &sphere(c, 0.6, lib.material("cubemetalic")?),
+            &Arc::new(FigureKind::new_plane(&c, &Vector3::new(0.4, -0.3, -1.0), lib.material("cubemetalic")?)));
+        let c = Vector3::new(-0.2, 0.6, 3.5);
+        let rod = lib.material("cube")?;
+        let bar = |d: Vector3| Arc::new(FigureKind::new_cylinder(&(c - d), &(c + d), 0.2, rod));
+        let cross = FigureKind::new_csg(CsgOp::Union, &bar(Vector3::new(0.0, 0.9, 0.0)), &bar(Vector3::new(0.7, 0.0, 0.0)));
 
-        Scene { figures: v, image: r, lights: vec![l1, l2] }
+        s.figures = vec![s.figures[0].clone(), s.figures[1].clone(), hollow, die, dome, cross];
+        Ok(s)
     }
 }
 
@@ -91,25 +284,80 @@ pub struct RenderSurface {
 impl RenderSurface {
     pub fn get_rays(&self, x: usize, y: usize) -> Vec<Ray> {
         let mut r = vec![];
-        let delta_y = (self.down_left - self.top_left).div(x as f32);
-        let delta_x = (self.top_right - self.top_left).div(y as f32);
-        let begin = self.top_left + delta_x.mult(0.5) + delta_y.mult(0.5);
-        let origin = self.foci_point;
-
         for j in 0..y {
             for i in 0..x {
-                let pos = begin + delta_x.mult(i as f32) + delta_y.mult(j as f32);
-                let dir = (pos - origin).normalize();
-                let ray = Ray { pos, dir };
-                r.push(ray)
+                r.push(self.get_ray(x, y, i, j, 0.5, 0.5))
             }
         }
         r
     }
+    //Луч через точку (dx, dy) внутри пикселя (i, j), dx и dy из [0, 1)
+    pub fn get_ray(&self, x: usize, y: usize, i: usize, j: usize, dx: Float, dy: Float) -> Ray {
+        let delta_x = (self.top_right - self.top_left).div(x as Float);
+        let delta_y = (self.down_left - self.top_left).div(y as Float);
+        let pos = self.top_left + delta_x.mult(i as Float + dx) + delta_y.mult(j as Float + dy);
+        let dir = (pos - self.foci_point).normalize();
+        Ray { pos, dir }
+    }
+}
+//Камера двигается как единое целое, например при анимации
+impl Transformable for RenderSurface {
+    fn transformed(&self, t: &Transform) -> Self {
+        RenderSurface {
+            top_left: t.transform_point(&self.top_left),
+            top_right: t.transform_point(&self.top_right),
+            down_left: t.transform_point(&self.down_left),
+            foci_point: t.transform_point(&self.foci_point),
+        }
+    }
 }
 #[derive(Debug, Clone)]
 pub struct LightSource {
     pub pos: Vector3,
     pub color: Vector3,
-    pub intencity: f32,
+    pub intencity: Float,
+    pub falloff: Falloff,
+}
+impl LightSource {
+    //Сила света, дошедшая до расстояния dist
+    #[inline(always)]
+    pub fn intencity_at(&self, dist: Float) -> Float {
+        self.falloff.apply(self.intencity, dist)
+    }
+}
+
+//Ослабление света точечного источника с расстоянием
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Falloff {
+    //Одинаковая яркость на любом расстоянии
+    None,
+    //1 / d
+    Linear,
+    //1 / d², физически верное
+    InverseSquare,
+    //1 / (constant + linear·d + quadratic·d²)
+    Custom { constant: Float, linear: Float, quadratic: Float },
+    //1 / d², плавно гаснущее до нуля к radius: дальше источник ничего не освещает
+    Cutoff { radius: Float },
+}
+//Мягкая кривая, с которой настроены все сцены
+impl Default for Falloff {
+    fn default() -> Self {
+        Falloff::Custom { constant: 0.0, linear: 0.5, quadratic: 0.3 }
+    }
+}
+impl Falloff {
+    //Сила света int на расстоянии dist
+    pub fn apply(&self, int: Float, dist: Float) -> Float {
+        match *self {
+            Falloff::None => int,
+            Falloff::Linear => int / dist,
+            Falloff::InverseSquare => int / (dist * dist),
+            Falloff::Custom { constant, linear, quadratic } => int / (dist * dist * quadratic + dist * linear + constant),
+            Falloff::Cutoff { radius } => {
+                let window = (1.0 - (dist / radius).powi(4)).max(0.0);
+                int * window * window / (dist * dist)
+            }
+        }
+    }
 }
@@ -0,0 +1,189 @@
+use crate::math::{Float, Ray, Vector3};
+
+//Точность попадания при сфере-трассировке, относительно пройденного пути
+pub const HIT_EPSILON: Float = 1e-4;
+pub const MAX_STEPS: usize = 512;
+//Предел пути для полей без ограничивающей сферы (повторение)
+pub const MAX_DISTANCE: Float = 100.0;
+
+//Функция расстояния со знаком: отрицательна внутри тела
+#[derive(Debug, Clone)]
+pub enum Sdf {
+    Sphere { r: Float },
+    //Полуразмеры вдоль осей
+    Box { half: Vector3 },
+    RoundBox { half: Vector3, r: Float },
+    //Фрактал, power = 8 даёт классический вид
+    Mandelbulb { power: Float, iterations: u32 },
+    Translate { offset: Vector3, sdf: Box<Sdf> },
+    Union { a: Box<Sdf>, b: Box<Sdf> },
+    //Объединение со скруглённым швом ширины k
+    SmoothUnion { a: Box<Sdf>, b: Box<Sdf>, k: Float },
+    //Закрутка вокруг оси y на k радиан на единицу высоты
+    Twist { k: Float, sdf: Box<Sdf> },
+    //Бесконечное повторение с периодом по каждой оси, 0 - без повторения
+    Repeat { period: Vector3, sdf: Box<Sdf> },
+}
+impl Sdf {
+    pub fn translate(self, offset: &Vector3) -> Self {
+        Sdf::Translate { offset: *offset, sdf: Box::new(self) }
+    }
+    pub fn union(self, other: Sdf) -> Self {
+        Sdf::Union { a: Box::new(self), b: Box::new(other) }
+    }
+    pub fn smooth_union(self, other: Sdf, k: Float) -> Self {
+        Sdf::SmoothUnion { a: Box::new(self), b: Box::new(other), k }
+    }
+    pub fn twist(self, k: Float) -> Self {
+        Sdf::Twist { k, sdf: Box::new(self) }
+    }
+    pub fn repeat(self, period: &Vector3) -> Self {
+        Sdf::Repeat { period: *period, sdf: Box::new(self) }
+    }
+
+    pub fn distance(&self, p: &Vector3) -> Float {
+        match self {
+            Sdf::Sphere { r } => p.len() - r,
+            Sdf::Box { half } => box_distance(p, half),
+            Sdf::RoundBox { half, r } => box_distance(p, &(*half - Vector3::new(*r, *r, *r))) - r,
+            Sdf::Mandelbulb { power, iterations } => mandelbulb(p, *power, *iterations),
+            Sdf::Translate { offset, sdf } => sdf.distance(&(*p - *offset)),
+            Sdf::Union { a, b } => a.distance(p).min(b.distance(p)),
+            Sdf::SmoothUnion { a, b, k } => {
+                let (d1, d2) = (a.distance(p), b.distance(p));
+                let h = (0.5 + 0.5 * (d2 - d1) / k).clamp(0.0, 1.0);
+                d2 + (d1 - d2) * h - k * h * (1.0 - h)
+            }
+            Sdf::Twist { k, sdf } => {
+                let (s, c) = (k * p.y).sin_cos();
+                sdf.distance(&Vector3::new(c * p.x - s * p.z, p.y, s * p.x + c * p.z))
+            }
+            Sdf::Repeat { period, sdf } => {
+                let rep = |x: Float, t: Float| if t > 0.0 { x - t * (x / t).round() } else { x };
+                sdf.distance(&Vector3::new(rep(p.x, period.x), rep(p.y, period.y), rep(p.z, period.z)))
+            }
+        }
+    }
+    //Закрутка растягивает поле, и расстояние до поверхности переоценивается.
+    //Шаг умножается на этот коэффициент, чтобы не перескочить её.
+    pub fn step_scale(&self) -> Float {
+        match self {
+            Sdf::Sphere { .. } | Sdf::Box { .. } | Sdf::RoundBox { .. } | Sdf::Mandelbulb { .. } => 1.0,
+            Sdf::Translate { sdf, .. } | Sdf::Repeat { sdf, .. } => sdf.step_scale(),
+            Sdf::Union { a, b } | Sdf::SmoothUnion { a, b, .. } => a.step_scale().min(b.step_scale()),
+            Sdf::Twist { sdf, .. } => 0.5 * sdf.step_scale(),
+        }
+    }
+    //Центр и радиус сферы, содержащей тело. None для бесконечного повторения.
+    pub fn bounds(&self) -> Option<(Vector3, Float)> {
+        let zero = Vector3::new(0.0, 0.0, 0.0);
+        match self {
+            Sdf::Sphere { r } => Some((zero, *r)),
+            Sdf::Box { half } | Sdf::RoundBox { half, .. } => Some((zero, half.len())),
+            //Поверхность фрактала лежит внутри радиуса выхода итераций
+            Sdf::Mandelbulb { .. } => Some((zero, 1.5)),
+            Sdf::Translate { offset, sdf } => sdf.bounds().map(|(c, r)| (c + *offset, r)),
+            Sdf::Union { a, b } | Sdf::SmoothUnion { a, b, .. } => {
+                let ((ca, ra), (cb, rb)) = (a.bounds()?, b.bounds()?);
+                let c = ca.lerp(&cb, 0.5);
+                let r = ((ca - c).len() + ra).max((cb - c).len() + rb);
+                match self {
+                    Sdf::SmoothUnion { k, .. } => Some((c, r + k)),
+                    _ => Some((c, r)),
+                }
+            }
+            //Расстояние до оси y при закрутке не меняется
+            Sdf::Twist { sdf, .. } => sdf.bounds().map(|(c, r)| (zero, c.len() + r)),
+            Sdf::Repeat { .. } => None,
+        }
+    }
+    //Градиент по четырём точкам тетраэдра
+    pub fn normal(&self, p: &Vector3, h: Float) -> Vector3 {
+        let k = [
+            Vector3::new(1.0, -1.0, -1.0),
+            Vector3::new(-1.0, -1.0, 1.0),
+            Vector3::new(-1.0, 1.0, -1.0),
+            Vector3::new(1.0, 1.0, 1.0),
+        ];
+        k.iter()
+            .fold(Vector3::new(0.0, 0.0, 0.0), |n, k| n + k.mult(self.distance(&(*p + k.mult(h)))))
+            .normalize()
+    }
+    //Сфера-трассировка на [t_min, t_max]. Если луч начинается на поверхности (вторичные лучи),
+    //сторона определяется по направлению, и попадание засчитывается только после того,
+    //как луч отошёл от неё.
+    pub fn march(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Float> {
+        let (mut t, t_end) = match self.bounds() {
+            Some((c, radius)) => {
+                //С запасом, чтобы вход в сферу не совпадал с поверхностью тела
+                let radius = radius * 1.01 + HIT_EPSILON;
+                let l = c - r.pos;
+                let tca = l.scalar_product(&r.dir);
+                let d2 = l.len_sq() - tca * tca;
+                if d2 > radius * radius {
+                    return None;
+                }
+                let thc = (radius * radius - d2).sqrt();
+                ((tca - thc).max(t_min), (tca + thc).min(t_max))
+            }
+            None => (t_min, t_max.min(MAX_DISTANCE)),
+        };
+        let eps = |t: Float| HIT_EPSILON * t.max(1.0);
+        let d0 = self.distance(&r.point_from_t(t));
+        let inside = if t > t_min {
+            false
+        } else if d0.abs() < eps(t) {
+            r.dir.scalar_product(&self.normal(&r.point_from_t(t), eps(t))) < 0.0
+        } else {
+            d0 < 0.0
+        };
+        let sign = if inside { -1.0 } else { 1.0 };
+        let mut armed = sign * d0 >= eps(t);
+        let scale = self.step_scale();
+        for _ in 0..MAX_STEPS {
+            if t > t_end {
+                return None;
+            }
+            let d = sign * self.distance(&r.point_from_t(t));
+            if armed && d < eps(t) {
+                //Ещё несколько шагов (в том числе назад при перелёте) приближают точку к поверхности,
+                //чтобы вторичные лучи начинались в пределах допуска от неё
+                for _ in 0..4 {
+                    t += sign * self.distance(&r.point_from_t(t)) * scale;
+                }
+                return Some(t.max(t_min));
+            }
+            armed = armed || d >= eps(t);
+            t += d.max(eps(t)) * scale;
+        }
+        None
+    }
+}
+
+fn box_distance(p: &Vector3, half: &Vector3) -> Float {
+    let q = Vector3::new(p.x.abs() - half.x, p.y.abs() - half.y, p.z.abs() - half.z);
+    let outside = Vector3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).len();
+    outside + q.x.max(q.y).max(q.z).min(0.0)
+}
+
+//Оценка расстояния через производную итерации z -> z^power + c в сферических координатах
+fn mandelbulb(p: &Vector3, power: Float, iterations: u32) -> Float {
+    let mut z = *p;
+    let mut dr = 1.0;
+    let mut r = z.len();
+    if r == 0.0 {
+        return -1.0;
+    }
+    for _ in 0..iterations {
+        if r > 2.0 {
+            break;
+        }
+        let theta = (z.z / r).clamp(-1.0, 1.0).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+        let zr = r.powf(power);
+        z = Vector3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()).mult(zr) + *p;
+        r = z.len();
+    }
+    0.5 * r.ln() * r / dr
+}
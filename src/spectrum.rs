@@ -0,0 +1,89 @@
+use crate::math::{Float, Matrix3, Vector3};
+
+//Видимый диапазон длин волн, нм
+pub const MIN_WAVELENGTH: Float = 380.0;
+pub const MAX_WAVELENGTH: Float = 780.0;
+
+//Кусочно-гауссова ступенька с разной шириной слева и справа от mu
+fn lobe(w: Float, mu: Float, left: Float, right: Float) -> Float {
+    let t = (w - mu) / if w < mu { left } else { right };
+    (-0.5 * t * t).exp()
+}
+
+//Функции сложения цветов CIE 1931 для наблюдателя 2°, приближение Wyman, Sloan, Shirley (2013)
+pub fn cie_xyz(w: Float) -> Vector3 {
+    Vector3::new(
+        1.056 * lobe(w, 599.8, 37.9, 31.0) + 0.362 * lobe(w, 442.0, 16.0, 26.7) - 0.065 * lobe(w, 501.1, 20.4, 26.2),
+        0.821 * lobe(w, 568.8, 46.9, 40.5) + 0.286 * lobe(w, 530.9, 16.3, 31.1),
+        1.217 * lobe(w, 437.0, 11.8, 36.0) + 0.681 * lobe(w, 459.0, 26.0, 13.8),
+    )
+}
+
+//Линейный sRGB (Rec. 709) из XYZ
+pub const XYZ_TO_RGB: Matrix3 = Matrix3::new([
+    [3.2406, -1.5372, -0.4986],
+    [-0.9689, 1.8758, 0.0415],
+    [0.0557, -0.2040, 1.0570],
+]);
+
+//Веса красного, зелёного и синего канала на длине волны: RGB-цвет становится гладким
+//спектром. В сумме веса дают 1, белый цвет - ровный спектр.
+pub fn rgb_basis(w: Float) -> Vector3 {
+    const BLUE: Float = 450.0;
+    const GREEN: Float = 540.0;
+    const RED: Float = 610.0;
+    if w <= BLUE {
+        Vector3::new(0.0, 0.0, 1.0)
+    } else if w <= GREEN {
+        let t = (w - BLUE) / (GREEN - BLUE);
+        Vector3::new(0.0, t, 1.0 - t)
+    } else if w <= RED {
+        let t = (w - GREEN) / (RED - GREEN);
+        Vector3::new(t, 1.0 - t, 0.0)
+    } else {
+        Vector3::new(1.0, 0.0, 0.0)
+    }
+}
+
+//Перевод яркости, посчитанной на одной длине волны, в RGB. Поправочная матрица выбрана так,
+//что для яркости, не зависящей от длины волны, среднее по длинам волн равно ей самой:
+//без дисперсии спектральный режим даёт то же изображение.
+#[derive(Debug, Clone, Copy)]
+pub struct Spectrum {
+    correction: Matrix3,
+}
+impl Default for Spectrum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Spectrum {
+    pub fn new() -> Self {
+        //Интеграл RGB-откликов по базисным кривым, по средним точкам шагов в 1 нм
+        let steps = (MAX_WAVELENGTH - MIN_WAVELENGTH) as usize;
+        let mut m = [[0.0; 3]; 3];
+        for i in 0..steps {
+            let w = MIN_WAVELENGTH + i as Float + 0.5;
+            let rgb = XYZ_TO_RGB * cie_xyz(w);
+            let b = rgb_basis(w);
+            let (rgb, b) = ([rgb.x, rgb.y, rgb.z], [b.x, b.y, b.z]);
+            for (row, r) in m.iter_mut().zip(rgb) {
+                for (x, b) in row.iter_mut().zip(b) {
+                    *x += r * b;
+                }
+            }
+        }
+        let correction = Matrix3::new(m).inverse().expect("RGB responses are independent");
+        Spectrum { correction }
+    }
+    //Длина волны, равномерная по видимому диапазону, и её плотность
+    pub fn sample(&self, u: Float) -> (Float, Float) {
+        let range = MAX_WAVELENGTH - MIN_WAVELENGTH;
+        (MIN_WAVELENGTH + u * range, 1.0 / range)
+    }
+    //Вклад в RGB яркости radiance, посчитанной на длине волны w с плотностью pdf
+    pub fn to_rgb(&self, w: Float, pdf: Float, radiance: &Vector3) -> Vector3 {
+        let value = radiance.scalar_product(&rgb_basis(w));
+        self.correction * (XYZ_TO_RGB * cie_xyz(w)).mult(value / pdf)
+    }
+}
@@ -0,0 +1,93 @@
+use std::{fmt::Display, time::Duration};
+
+use crate::figure::FigureKind;
+
+//Глубина рекурсии, начиная с которой всё складывается в последнюю корзину гистограммы
+pub const DEPTH_BUCKETS: usize = 12;
+
+//Счётчики одного потока, сливаются в RenderStats после обработки чанка
+#[derive(Debug, Clone, Default)]
+pub struct RayCounters {
+    pub primary: u64,
+    pub reflection: u64,
+    pub refraction: u64,
+    pub shadow: u64,
+    pub intersection_tests: [u64; FigureKind::KIND_NAMES.len()],
+    pub depth: [u64; DEPTH_BUCKETS],
+}
+impl RayCounters {
+    #[inline(always)]
+    pub fn intersection_test(&mut self, f: &FigureKind) {
+        self.intersection_tests[f.kind_index()] += 1;
+    }
+    #[inline(always)]
+    pub fn traced_at_depth(&mut self, iter: u32) {
+        self.depth[(iter as usize).min(DEPTH_BUCKETS - 1)] += 1;
+    }
+    pub fn merge(&mut self, other: &Self) {
+        self.primary += other.primary;
+        self.reflection += other.reflection;
+        self.refraction += other.refraction;
+        self.shadow += other.shadow;
+        for (a, b) in self.intersection_tests.iter_mut().zip(other.intersection_tests) {
+            *a += b;
+        }
+        for (a, b) in self.depth.iter_mut().zip(other.depth) {
+            *a += b;
+        }
+    }
+    pub fn total_rays(&self) -> u64 {
+        self.primary + self.reflection + self.refraction + self.shadow
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RenderStats {
+    pub counters: RayCounters,
+    pub ray_generation: Duration,
+    pub tracing: Duration,
+    pub output: Duration,
+}
+impl RenderStats {
+    pub fn total_time(&self) -> Duration {
+        self.ray_generation + self.tracing + self.output
+    }
+    pub fn to_json(&self) -> String {
+        let c = &self.counters;
+        let tests = FigureKind::KIND_NAMES.iter().zip(c.intersection_tests)
+            .map(|(n, v)| format!("\"{n}\": {v}"))
+            .collect::<Vec<_>>().join(", ");
+        let depth = c.depth.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ");
+        format!(
+            "{{\n  \"rays\": {{\"primary\": {}, \"reflection\": {}, \"refraction\": {}, \"shadow\": {}, \"total\": {}}},\n  \"intersection_tests\": {{{tests}}},\n  \"depth_histogram\": [{depth}],\n  \"timing_ms\": {{\"ray_generation\": {:.3}, \"tracing\": {:.3}, \"output\": {:.3}, \"total\": {:.3}}}\n}}\n",
+            c.primary, c.reflection, c.refraction, c.shadow, c.total_rays(),
+            self.ray_generation.as_secs_f64() * 1000.0,
+            self.tracing.as_secs_f64() * 1000.0,
+            self.output.as_secs_f64() * 1000.0,
+            self.total_time().as_secs_f64() * 1000.0,
+        )
+    }
+}
+impl Display for RenderStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let c = &self.counters;
+        writeln!(f, "Rays: {} total", c.total_rays())?;
+        writeln!(f, "  primary:    {}", c.primary)?;
+        writeln!(f, "  reflection: {}", c.reflection)?;
+        writeln!(f, "  refraction: {}", c.refraction)?;
+        writeln!(f, "  shadow:     {}", c.shadow)?;
+        writeln!(f, "Intersection tests:")?;
+        for (n, v) in FigureKind::KIND_NAMES.iter().zip(c.intersection_tests) {
+            writeln!(f, "  {n:<10}{v}")?;
+        }
+        writeln!(f, "Recursion depth:")?;
+        for (i, v) in c.depth.iter().enumerate().filter(|(_, v)| **v > 0) {
+            let plus = if i == DEPTH_BUCKETS - 1 { "+" } else { "" };
+            writeln!(f, "  {i:>2}{plus:<8}{v}")?;
+        }
+        writeln!(f, "Timing:")?;
+        writeln!(f, "  ray generation: {:?}", self.ray_generation)?;
+        writeln!(f, "  tracing:        {:?}", self.tracing)?;
+        write!(f, "  output:         {:?}", self.output)
+    }
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use common::v;
+use raytracer::{
+    background::Background,
+    color::Color,
+    figure::FigureKind,
+    material::Material,
+    math::{consts, Float, Ray, Vector3},
+    raytracer::{closest_hit, shadow_part},
+    scene::{Falloff, LightSource, RenderSurface, Scene},
+    stats::RayCounters,
+};
+
+const GRID: usize = 64;
+#[cfg(not(feature = "f64"))]
+const SCALES: [Float; 3] = [1e-3, 1.0, 1e3];
+//В двойной точности проверяем и сцены географического масштаба
+#[cfg(feature = "f64")]
+const SCALES: [Float; 5] = [1e-6, 1e-3, 1.0, 1e3, 1e6];
+
+fn scene(f: FigureKind, light: Vector3) -> Scene {
+    let l = LightSource { pos: light, color: Color::WHITE.to_vector3(), intencity: 1.0, falloff: Falloff::default() };
+    let image = RenderSurface { top_left: light, top_right: light, down_left: light, foci_point: light };
+    Scene { figures: vec![f], image, lights: vec![l], background: Background::BLACK, caustics: None }
+}
+
+//Сцены с одной выпуклой фигурой: освещённая сторона не может быть в собственной тени
+fn scenes(s: Float, o: Vector3) -> Vec<(&'static str, Scene, Vec<Vector3>)> {
+    let grid = |a: Vector3, b: Vector3| -> Vec<Vector3> {
+        (0..GRID * GRID).map(|i| {
+            let (u, w) = ((i % GRID) as Float / (GRID - 1) as Float * 2.0 - 1.0, (i / GRID) as Float / (GRID - 1) as Float * 2.0 - 1.0);
+            o + a.mult(u * s) + b.mult(w * s)
+        }).collect()
+    };
+    let light = o + v(2.0, -5.0, -3.0).mult(s);
+    let floor = FigureKind::new_side(
+        &(o + v(-10.0, 0.0, -10.0).mult(s)),
+        &(o + v(10.0, 0.0, -10.0).mult(s)),
+        &(o + v(-10.0, 0.0, 10.0).mult(s)),
+        Material::FRONTWALLS,
+    );
+    let sphere = FigureKind::Sphere { r: s, pos: o, m: Material::FRONTWALLS };
+    let a = consts::PI / 6.0;
+    let cube = FigureKind::new_cube_from_d(
+        &(o + v(-0.5, -0.5, 0.5).mult(s)),
+        &v(1.0, 0.0, 0.0).rotate_y_axis(a).mult(s),
+        &v(0.0, 1.0, 0.0).rotate_y_axis(a).mult(s),
+        &v(0.0, 0.0, -1.0).rotate_y_axis(a).mult(s),
+        Material::FRONTWALLS,
+    );
+    let axis = v(0.3, -1.0, 0.2);
+    let cylinder = FigureKind::new_cylinder(&(o + v(0.0, 0.5, 0.0).mult(s)), &(o + axis.mult(s)), 0.6 * s, Material::FRONTWALLS);
+    let cone = FigureKind::new_cone(&(o + v(0.0, 0.5, 0.0).mult(s)), &(o + axis.mult(s)), 0.8 * s, Material::FRONTWALLS);
+    vec![
+        ("floor", scene(floor, light), grid(v(3.0, 0.0, 0.0), v(0.0, 0.0, 3.0))),
+        ("sphere", scene(sphere, light), grid(v(1.2, 0.0, 0.0), v(0.0, 1.2, 0.0))),
+        ("cube", scene(cube, light), grid(v(1.0, 0.0, 0.0), v(0.0, 1.0, 0.0))),
+        ("cylinder", scene(cylinder, light), grid(v(1.0, 0.0, 0.0), v(0.0, 1.2, 0.0))),
+        ("cone", scene(cone, light), grid(v(1.0, 0.0, 0.0), v(0.0, 1.2, 0.0))),
+    ]
+}
+
+fn for_each_case(check: impl Fn(&str, Float, &Scene, &Ray)) {
+    for s in SCALES {
+        for o in [v(0.0, 0.0, 0.0), v(100.0, -37.0, 250.0).mult(s)] {
+            let eye = o + v(0.3, -2.0, -6.0).mult(s);
+            for (name, sc, targets) in scenes(s, o) {
+                for t in targets {
+                    check(name, s, &sc, &Ray::new_normalize(eye, &(t - eye)));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn lit_side_is_never_self_shadowed() {
+    for_each_case(|name, s, sc, r| {
+        let mut stats = RayCounters::default();
+        if let Some((_, h)) = closest_hit(sc, r, &mut stats) {
+            let l = &sc.lights[0];
+            if (l.pos - h.point).scalar_product(&h.normal) > 0.0 {
+                assert!(shadow_part(sc, r, &h, l, &mut stats).is_some(), "{name} at scale {s}: acne at {}", h.point);
+            }
+        }
+    });
+}
+
+#[test]
+fn reflected_ray_leaves_convex_surface() {
+    for_each_case(|name, s, sc, r| {
+        let mut stats = RayCounters::default();
+        if let Some((_, h)) = closest_hit(sc, r, &mut stats) {
+            let refl = r.reflect(&h.point, &h.normal);
+            let spawned = Ray::spawn(&h.point, &h.normal, refl.dir);
+            assert!(h.figure.hit(&spawned).is_none(), "{name} at scale {s}: reflected ray re-hit at {}", h.point);
+        }
+    });
+}
+
+#[test]
+fn transmitted_ray_reaches_far_side() {
+    for_each_case(|name, s, sc, r| {
+        if name == "floor" { return; }
+        let mut stats = RayCounters::default();
+        if let Some((_, h)) = closest_hit(sc, r, &mut stats) {
+            //У рёбер и на касательных хорда сама по себе меньше ошибки округления
+            let near_edge = [h.uv.0, h.uv.1].iter().any(|x| !(0.01..=0.99).contains(x));
+            if name != "sphere" && near_edge || h.normal.scalar_product(&r.dir) > -0.1 { return; }
+            let spawned = Ray::spawn(&h.point, &h.normal, r.dir);
+            let exit = h.figure.hit(&spawned).unwrap_or_else(|| panic!("{name} at scale {s}: no exit from {}", h.point));
+            assert!(!exit.front_face, "{name} at scale {s}: exit hit is front-facing");
+            //Самопересечение дало бы t порядка ошибки округления, а не хорду
+            assert!(exit.t > 1e-4 * s || (exit.point - h.point).len() > 1e-4 * s, "{name} at scale {s}: self-hit at t = {}", exit.t);
+        }
+    });
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use common::v;
+use raytracer::{
+    background::{Background, EnvMap},
+    figure::FigureKind,
+    lights::LightSampler,
+    material::Material,
+    math::{consts, Float, Ray, Vector3},
+    path::path_trace,
+    random::Rng,
+    raytracer::raytrace,
+    scene::Scene,
+    stats::RayCounters,
+};
+
+//Пустая сцена с одним шаром в начале координат
+fn sphere_scene(m: Material, background: Background) -> Scene {
+    let mut s = Scene::get_room();
+    s.lights = vec![];
+    s.figures = vec![FigureKind::Sphere { r: 1.0, pos: v(0.0, 0.0, 0.0), m }];
+    s.background = background;
+    s
+}
+
+fn uniform_map(c: Vector3) -> EnvMap {
+    EnvMap::new(16, 8, vec![c; 16 * 8])
+}
+
+//Тёмная карта с одним ярким пикселем
+fn sun_map() -> EnvMap {
+    let mut px = vec![v(0.1, 0.1, 0.1); 32 * 16];
+    px[4 * 32 + 20] = v(500.0, 500.0, 500.0);
+    EnvMap::new(32, 16, px)
+}
+
+#[test]
+fn missed_rays_see_background() {
+    let mut stats = RayCounters::default();
+    let miss = Ray::new_normalize(v(0.0, 0.0, -5.0), &v(0.0, -1.0, 0.2));
+    for b in [Background::Color(v(0.2, 0.3, 0.4)), Background::SKY] {
+        let s = sphere_scene(Material::CUBE, b.clone());
+        let expected = b.radiance(&miss.dir);
+        assert_eq!(raytrace(0, &s, &miss, 1.0, &LightSampler::new(&s, 0), &mut Rng::new(0, 0), &mut stats), expected);
+        assert_eq!(path_trace(&s, &miss, &LightSampler::new(&s, 0), &mut Rng::new(0, 0), &mut stats), expected);
+    }
+    //К зениту небо синее
+    let sky = Background::SKY;
+    assert!(sky.radiance(&v(0.0, -1.0, 0.0)).x < sky.radiance(&v(0.0, 0.0, 1.0)).x);
+    //Зеркало и стекло показывают фон
+    let hit = Ray::new_normalize(v(0.0, 0.0, -5.0), &v(0.0, 0.0, 1.0));
+    for m in [Material::MIRRORMATERIAL, Material::CUBETRANSPARENT] {
+        let s = sphere_scene(m, Background::Color(v(1.0, 1.0, 1.0)));
+        assert!(raytrace(0, &s, &hit, 1.0, &LightSampler::new(&s, 0), &mut Rng::new(0, 0), &mut stats).x > 0.2);
+    }
+}
+
+#[test]
+fn map_orientation() {
+    let mut px = vec![v(0.0, 0.0, 0.0); 4 * 2];
+    //Верхняя строка - небо, в центре нижней - +z
+    px[..4].fill(v(1.0, 1.0, 1.0));
+    px[4 + 2] = v(0.0, 0.0, 1.0);
+    let m = EnvMap::new(4, 2, px);
+    assert_eq!(m.lookup(&v(0.0, -1.0, 0.0)), v(1.0, 1.0, 1.0));
+    assert_eq!(m.lookup(&v(0.1, 0.5, 1.0).normalize()), v(0.0, 0.0, 1.0));
+    assert_eq!(m.lookup(&v(0.0, 0.5, -1.0).normalize()), v(0.0, 0.0, 0.0));
+}
+
+#[test]
+fn map_sampling_is_consistent() {
+    let m = sun_map();
+    let mut rng = Rng::new(1, 0);
+    let n = 200_000;
+    //Плотность по всей сфере интегрируется в единицу
+    let mut integral = 0.0;
+    for _ in 0..n {
+        let z = 1.0 - 2.0 * rng.next_f32() as Float;
+        let phi = 2.0 * consts::PI * rng.next_f32() as Float;
+        let s = (1.0 - z * z).sqrt();
+        integral += m.pdf(&v(s * phi.cos(), s * phi.sin(), z)) * 4.0 * consts::PI;
+    }
+    let integral = integral / n as Float;
+    assert!((integral - 1.0).abs() < 0.05, "{integral}");
+    //Большая часть направлений попадает в яркий пиксель, плотность совпадает с pdf()
+    let mut sun = 0;
+    for _ in 0..1000 {
+        let e = m.sample((rng.next_f32() as Float, rng.next_f32() as Float)).unwrap();
+        assert!((e.pdf - m.pdf(&e.dir)).abs() <= 1e-3 * e.pdf);
+        assert_eq!(e.radiance, m.lookup(&e.dir));
+        sun += (e.radiance.x > 1.0) as usize;
+    }
+    assert!(sun > 900, "{sun}");
+    assert!(EnvMap::new(2, 2, vec![v(0.0, 0.0, 0.0); 4]).sample((0.5, 0.5)).is_none());
+}
+
+//Среднее по n путям из луча, попадающего в шар
+fn mean_radiance(s: &Scene, n: usize) -> Vector3 {
+    let r = Ray::new_normalize(v(0.3, -0.2, -5.0), &v(0.0, 0.0, 1.0));
+    let mut rng = Rng::new(5, 0);
+    let lights = LightSampler::new(s, 0);
+    let mut stats = RayCounters::default();
+    let mut sum = v(0.0, 0.0, 0.0);
+    for _ in 0..n {
+        sum += path_trace(s, &r, &lights, &mut rng, &mut stats);
+    }
+    sum.div(n as Float)
+}
+
+#[test]
+fn image_based_lighting_converges() {
+    let m = Material::pbr(v(0.8, 0.8, 0.8), 0.0, 0.7);
+    //Равномерная карта с выбором направлений даёт то же, что постоянный фон без него
+    let c = v(0.5, 0.5, 0.5);
+    let map = mean_radiance(&sphere_scene(m, Background::Map(uniform_map(c))), 20_000);
+    let color = mean_radiance(&sphere_scene(m, Background::Color(c)), 20_000);
+    assert!((map.x - color.x).abs() < 0.02 * color.x, "map {map}, color {color}");
+    //Маленькое яркое солнце: без выбора по карте оценка очень шумная, с ним - устойчивая
+    let s = sphere_scene(m, Background::Map(sun_map()));
+    let (a, b) = (mean_radiance(&s, 2_000), mean_radiance(&s, 20_000));
+    assert!((a.x - b.x).abs() < 0.05 * b.x, "{a} vs {b}");
+}
@@ -0,0 +1,58 @@
+use raytracer::{
+    bdpt::bidirectional,
+    lights::LightSampler,
+    math::{Float, Ray, Vector3},
+    path::path_trace,
+    random::Rng,
+    raytracer::{render, Integrator, RenderSettings},
+    scene::Scene,
+    stats::{RayCounters, RenderStats},
+};
+
+//Прозрачные фигуры path_trace освещает через ослабленные тени, а bidirectional - только
+//через преломление, поэтому общие сцены для сравнения без них
+fn opaque(mut s: Scene) -> Scene {
+    s.figures.retain(|f| f.get_material().transparency <= 0.0);
+    s
+}
+
+//Лучи камеры через сетку точек кадра
+fn camera_rays(s: &Scene) -> Vec<Ray> {
+    let (w, h) = (8, 8);
+    (1..w).step_by(2).flat_map(|i| (1..h).step_by(2).map(move |j| (i, j))).map(|(i, j)| s.image.get_ray(w, h, i, j, 0.5, 0.5)).collect()
+}
+
+fn mean(n: usize, mut f: impl FnMut(&mut Rng) -> Vector3) -> Vector3 {
+    let mut rng = Rng::new(4, 0);
+    let mut sum = Vector3::new(0.0, 0.0, 0.0);
+    for _ in 0..n {
+        sum += f(&mut rng);
+    }
+    sum.div(n as Float)
+}
+
+#[test]
+fn converges_to_path_tracing() {
+    for s in [opaque(Scene::get_pbr()), opaque(Scene::get_cornell())] {
+        let lights = LightSampler::new(&s, 1);
+        let mut stats = RayCounters::default();
+        let (mut total_path, mut total_bdpt) = (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
+        for r in camera_rays(&s) {
+            let path = mean(32_000, |rng| path_trace(&s, &r, &lights, rng, &mut stats));
+            let bdpt = mean(32_000, |rng| bidirectional(&s, &r, &lights, rng, &mut stats));
+            assert!((path - bdpt).len() < 0.04 * path.len() + 0.01, "{path} vs {bdpt}");
+            total_path += path;
+            total_bdpt += bdpt;
+        }
+        assert!((total_path - total_bdpt).len() < 0.02 * total_path.len(), "{total_path} vs {total_bdpt}");
+    }
+}
+
+#[test]
+fn renders_with_bidirectional_integrator() {
+    let s = Scene::get_cornell();
+    let settings = RenderSettings { samples: 2, integrator: Integrator::Bidirectional, ..Default::default() };
+    let img = render(&s, 16, 16, &settings, &mut RenderStats::default());
+    assert_eq!(img.len(), 256);
+    assert!(img.iter().any(|c| c.r > 0 && c.r < 255));
+}
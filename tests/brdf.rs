@@ -0,0 +1,138 @@
+mod common;
+
+use common::v;
+use raytracer::{
+    brdf::{ggx_d, Pbr},
+    math::{consts, Float, Vector3},
+    random::Rng,
+};
+
+const N: usize = 200_000;
+
+fn white() -> Vector3 {
+    v(1.0, 1.0, 1.0)
+}
+
+fn normal() -> Vector3 {
+    v(0.0, 0.0, 1.0)
+}
+
+//Направление наблюдения под углом theta к нормали
+fn view(theta: Float) -> Vector3 {
+    v(theta.sin(), 0.0, theta.cos())
+}
+
+fn u3(rng: &mut Rng) -> [Float; 3] {
+    [rng.next_f32() as Float, rng.next_f32() as Float, rng.next_f32() as Float]
+}
+
+//Равномерно по полусфере, плотность 1 / 2π
+fn uniform_hemisphere(rng: &mut Rng) -> Vector3 {
+    let z = rng.next_f32() as Float;
+    let phi = 2.0 * consts::PI * rng.next_f32() as Float;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    v(r * phi.cos(), r * phi.sin(), z)
+}
+
+//Доля отражённого света: интеграл f * cos по полусфере, оценка по сэмплам BRDF
+fn albedo(p: &Pbr, base: &Vector3, wo: &Vector3, seed: u64) -> Vector3 {
+    let mut rng = Rng::new(seed, 0);
+    let mut sum = v(0.0, 0.0, 0.0);
+    for _ in 0..N {
+        if let Some(s) = p.sample(base, &normal(), wo, u3(&mut rng)) {
+            sum += s.weight;
+        }
+    }
+    sum.div(N as Float)
+}
+
+#[test]
+fn ggx_distribution_is_normalized() {
+    //Проекция площади микрограней на плоскость равна единице
+    for alpha in [0.05, 0.2, 0.5, 1.0] {
+        let steps = 200_000;
+        let d_theta = consts::FRAC_PI_2 / steps as Float;
+        let integral: Float = (0..steps)
+            .map(|i| {
+                let theta = (i as Float + 0.5) * d_theta;
+                ggx_d(theta.cos(), alpha) * theta.cos() * theta.sin() * d_theta * 2.0 * consts::PI
+            })
+            .sum();
+        assert!((integral - 1.0).abs() < 1e-2, "alpha {alpha}: {integral}");
+    }
+}
+
+#[test]
+fn energy_is_conserved() {
+    for roughness in [0.05, 0.3, 0.7, 1.0] {
+        for metallic in [0.0, 1.0] {
+            let p = Pbr { metallic, roughness };
+            for theta in [0.0, 0.8, 1.4] {
+                let a = albedo(&p, &white(), &view(theta), 1);
+                assert!(a.x <= 1.02, "{p:?} at {theta}: albedo {a}");
+                //Белый металл теряет только на затенении микрограней. Однократное рассеяние
+                //не учитывает переотражения между ними, и на шероховатых потери заметны.
+                if metallic == 1.0 && theta < 1.0 && roughness <= 0.3 {
+                    assert!(a.x > 0.75, "{p:?} at {theta}: albedo {a}");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn brdf_is_reciprocal() {
+    let p = Pbr { metallic: 0.3, roughness: 0.4 };
+    let base = v(0.9, 0.5, 0.2);
+    let n = normal();
+    let mut rng = Rng::new(7, 0);
+    for _ in 0..100 {
+        let (a, b) = (uniform_hemisphere(&mut rng), uniform_hemisphere(&mut rng));
+        //eval содержит косинус с направлением на свет
+        let f_ab = p.eval(&base, &n, &a, &b).div(n.scalar_product(&b));
+        let f_ba = p.eval(&base, &n, &b, &a).div(n.scalar_product(&a));
+        assert!((f_ab - f_ba).len() < 1e-3 * (1.0 + f_ab.len()), "{f_ab} vs {f_ba}");
+    }
+    assert_eq!(p.eval(&base, &n, &view(0.3), &v(0.0, 0.0, -1.0)).len(), 0.0);
+}
+
+#[test]
+fn sampling_matches_uniform_estimate() {
+    let base = v(0.9, 0.5, 0.2);
+    for (metallic, roughness) in [(0.0, 0.5), (1.0, 0.4), (0.5, 0.8)] {
+        let p = Pbr { metallic, roughness };
+        let wo = view(0.6);
+        let mut rng = Rng::new(3, 1);
+        let mut uniform = v(0.0, 0.0, 0.0);
+        let mut pdf_integral = 0.0;
+        for _ in 0..N {
+            let l = uniform_hemisphere(&mut rng);
+            uniform += p.eval(&base, &normal(), &wo, &l).mult(2.0 * consts::PI);
+            pdf_integral += p.pdf(&normal(), &wo, &l) * 2.0 * consts::PI;
+        }
+        let uniform = uniform.div(N as Float);
+        let sampled = albedo(&p, &base, &wo, 5);
+        assert!((uniform - sampled).len() < 0.02, "{p:?}: {uniform} vs {sampled}");
+        //Часть GGX-сэмплов уходит под поверхность, поэтому плотность на полусфере меньше единицы
+        let pdf_integral = pdf_integral / N as Float;
+        assert!(pdf_integral <= 1.01 && pdf_integral > 0.7, "{p:?}: pdf integral {pdf_integral}");
+    }
+}
+
+#[test]
+fn metals_tint_reflection_and_dielectrics_do_not() {
+    let base = v(1.0, 0.77, 0.34);
+    let wo = view(0.2);
+    let mirror = |metallic| {
+        let p = Pbr { metallic, roughness: 0.05 };
+        p.eval(&base, &normal(), &wo, &v(-wo.x, -wo.y, wo.z))
+    };
+    let gold = mirror(1.0);
+    assert!(gold.x > gold.y && gold.y > gold.z);
+    //Блик пластика белый: у чёрного пластика остаётся только он
+    let p = Pbr { metallic: 0.0, roughness: 0.05 };
+    let highlight = p.eval(&v(0.0, 0.0, 0.0), &normal(), &wo, &v(-wo.x, -wo.y, wo.z));
+    assert!(highlight.x > 1.0 && highlight.x == highlight.z, "{highlight}");
+    let plastic = p.eval(&base, &normal(), &wo, &v(-wo.x, -wo.y, wo.z));
+    assert!(plastic.x > highlight.x && plastic.z > highlight.z);
+}
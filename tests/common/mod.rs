@@ -0,0 +1,91 @@
+//Общее для интеграционных тестов. Каждый тест подключает модуль целиком,
+//а использует только часть
+#![allow(dead_code)]
+
+use std::{env, fs, path::{Path, PathBuf}};
+
+use image::{Rgb, RgbImage};
+use raytracer::{
+    color::Color,
+    math::{Float, Vector3},
+    raytracer::{render, save_to_image, RenderSettings},
+    scene::Scene,
+    stats::RenderStats,
+};
+
+pub fn v(x: Float, y: Float, z: Float) -> Vector3 {
+    Vector3::new(x, y, z)
+}
+
+//Допуск сравнения с эталоном
+pub struct Tolerance {
+    //Минимальный PSNR, дБ
+    pub min_psnr: f64,
+    //Разница канала, начиная с которой пиксель считается отличающимся
+    pub pixel_error: u8,
+    //Допустимая доля отличающихся пикселей
+    pub max_bad_fraction: f64,
+}
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self { min_psnr: 40.0, pixel_error: 8, max_bad_fraction: 0.005 }
+    }
+}
+
+pub fn render_scene(scene: &Scene, size: usize, settings: &RenderSettings) -> RgbImage {
+    let c: Vec<Color> = render(scene, size, size, settings, &mut RenderStats::default());
+    save_to_image(&c, size, size)
+}
+
+pub fn psnr(a: &RgbImage, b: &RgbImage) -> f64 {
+    let mse = a.as_raw().iter().zip(b.as_raw())
+        .map(|(x, y)| (*x as f64 - *y as f64).powi(2))
+        .sum::<f64>() / a.as_raw().len() as f64;
+    if mse == 0.0 { f64::INFINITY } else { 10.0 * (255.0f64.powi(2) / mse).log10() }
+}
+
+//Отличия усилены в 4 раза, чтобы были заметны на глаз
+pub fn diff_image(a: &RgbImage, b: &RgbImage) -> RgbImage {
+    RgbImage::from_fn(a.width(), a.height(), |x, y| {
+        let (p, q) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        Rgb([0, 1, 2].map(|i| p[i].abs_diff(q[i]).saturating_mul(4)))
+    })
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{name}.png"))
+}
+
+fn failure_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden-failures")
+}
+
+//Сравнивает изображение с tests/golden/<name>.png.
+//UPDATE_GOLDEN=1 перезаписывает эталон вместо сравнения.
+pub fn assert_golden(name: &str, actual: &RgbImage, tol: &Tolerance) {
+    let path = golden_path(name);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        actual.save(&path).unwrap();
+        return;
+    }
+    let expected = image::open(&path)
+        .unwrap_or_else(|e| panic!("cannot open {}: {e}; run with UPDATE_GOLDEN=1 to create it", path.display()))
+        .to_rgb8();
+    assert_eq!(expected.dimensions(), actual.dimensions(), "{name}: size mismatch");
+
+    let p = psnr(&expected, actual);
+    let bad = expected.pixels().zip(actual.pixels())
+        .filter(|(e, a)| (0..3).any(|i| e[i].abs_diff(a[i]) > tol.pixel_error))
+        .count();
+    let bad_fraction = bad as f64 / (expected.width() * expected.height()) as f64;
+    if p < tol.min_psnr || bad_fraction > tol.max_bad_fraction {
+        let dir = failure_dir();
+        fs::create_dir_all(&dir).unwrap();
+        actual.save(dir.join(format!("{name}_actual.png"))).unwrap();
+        diff_image(&expected, actual).save(dir.join(format!("{name}_diff.png"))).unwrap();
+        panic!(
+            "{name}: PSNR {p:.2} dB (min {:.2}), {:.3}% pixels off by more than {} (max {:.3}%); see {}",
+            tol.min_psnr, bad_fraction * 100.0, tol.pixel_error, tol.max_bad_fraction * 100.0, dir.display()
+        );
+    }
+}
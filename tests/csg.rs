@@ -0,0 +1,158 @@
+mod common;
+
+use common::v;
+use std::sync::Arc;
+
+use raytracer::{
+    figure::{CsgOp, FigureKind},
+    material::Material,
+    math::{Float, Ray, Transform, Vector3},
+};
+
+const TOL: Float = 1e-4;
+
+fn ray(pos: Vector3, dir: Vector3) -> Ray {
+    Ray::new_normalize(pos, &dir)
+}
+
+#[track_caller]
+fn assert_close(a: Vector3, b: Vector3) {
+    assert!((a - b).len() < TOL, "expected {b}, got {a}");
+}
+
+//Куб [-1, 1]^3
+fn cube(m: Material) -> Arc<FigureKind> {
+    Arc::new(FigureKind::new_cube_from_d(&v(-1.0, -1.0, 1.0), &v(2.0, 0.0, 0.0), &v(0.0, 2.0, 0.0), &v(0.0, 0.0, -2.0), m))
+}
+
+fn sphere(pos: Vector3, r: Float, m: Material) -> Arc<FigureKind> {
+    Arc::new(FigureKind::Sphere { r, pos, m })
+}
+
+fn hollow_cube() -> FigureKind {
+    FigureKind::new_csg(CsgOp::Difference, &cube(Material::CUBETRANSPARENT), &sphere(v(0.0, 0.0, 0.0), 0.5, Material::CUBE))
+}
+
+//Значения t входа и выхода, None - бесконечность
+fn span_ts(f: &FigureKind, r: &Ray) -> Vec<(Option<Float>, Option<Float>)> {
+    f.spans(r, r.t_min()).iter().map(|s| (s.enter.map(|h| h.t), s.exit.map(|h| h.t))).collect()
+}
+
+#[track_caller]
+fn assert_spans(f: &FigureKind, r: &Ray, expected: &[(Float, Float)]) {
+    let s = span_ts(f, r);
+    assert_eq!(s.len(), expected.len(), "{s:?}");
+    for ((a, b), (ea, eb)) in s.iter().zip(expected) {
+        assert!((a.unwrap() - ea).abs() < TOL && (b.unwrap() - eb).abs() < TOL, "{s:?}");
+    }
+}
+
+#[test]
+fn primitive_spans_have_entry_and_exit() {
+    let r = ray(v(0.0, 0.0, -5.0), v(0.0, 0.0, 1.0));
+    assert_spans(&cube(Material::CUBE), &r, &[(4.0, 6.0)]);
+    assert_spans(&sphere(v(0.0, 0.0, 0.0), 0.5, Material::CUBE), &r, &[(4.5, 5.5)]);
+    let torus = FigureKind::new_torus(&v(0.0, 0.0, 0.0), &v(0.0, 1.0, 0.0), 1.0, 0.25, Material::CUBE);
+    assert_spans(&torus, &ray(v(-5.0, 0.0, 0.0), v(1.0, 0.0, 0.0)), &[(3.75, 4.25), (5.75, 6.25)]);
+    //Начало луча внутри: вход отсутствует
+    let inside = span_ts(&cube(Material::CUBE), &ray(v(0.0, 0.0, 0.0), v(0.0, 0.0, 1.0)));
+    assert!(inside.len() == 1 && inside[0].0.is_none() && (inside[0].1.unwrap() - 1.0).abs() < TOL);
+}
+
+#[test]
+fn difference_carves_cavity() {
+    let f = hollow_cube();
+    let r = ray(v(0.0, 0.0, -5.0), v(0.0, 0.0, 1.0));
+    assert_spans(&f, &r, &[(4.0, 4.5), (5.5, 6.0)]);
+    //Мимо полости - одна сплошная хорда
+    assert_spans(&f, &ray(v(0.7, 0.0, -5.0), v(0.0, 0.0, 1.0)), &[(4.0, 6.0)]);
+    //Стенка полости смотрит внутрь неё и сделана из материала куба
+    let wall = f.hit_in(&r, 4.2, Float::INFINITY).unwrap();
+    assert_close(wall.point, v(0.0, 0.0, -0.5));
+    assert_close(wall.normal, v(0.0, 0.0, 1.0));
+    assert!(!wall.front_face);
+    assert_eq!(wall.material().refraction, Material::CUBETRANSPARENT.refraction);
+}
+
+#[test]
+fn refraction_inside_csg_sees_cavity() {
+    let f = hollow_cube();
+    let r = ray(v(0.0, 0.0, -5.0), v(0.0, 0.0, 1.0));
+    let h = f.hit(&r).unwrap();
+    assert!(h.front_face);
+    //Луч продолжается внутри той же фигуры, как в refraction_part
+    let inner = Ray::spawn(&h.point, &h.normal, r.dir);
+    let exit = h.figure.hit(&inner).unwrap();
+    assert_close(exit.point, v(0.0, 0.0, -0.5));
+    assert!(!exit.front_face);
+    let across = Ray::spawn(&exit.point, &exit.normal, r.dir);
+    let back_in = exit.figure.hit(&across).unwrap();
+    assert_close(back_in.point, v(0.0, 0.0, 0.5));
+    assert!(back_in.front_face);
+}
+
+#[test]
+fn intersection_keeps_common_part() {
+    let f = FigureKind::new_csg(CsgOp::Intersection, &cube(Material::CUBE), &sphere(v(0.0, 0.0, 0.0), 1.2, Material::CUBE));
+    assert_spans(&f, &ray(v(0.0, 0.0, -5.0), v(0.0, 0.0, 1.0)), &[(4.0, 6.0)]);
+    //Вдоль диагонали куб длиннее, граница - сфера
+    let d = v(1.0, 1.0, 1.0).normalize();
+    let h = f.hit(&ray(d.mult(-5.0), d)).unwrap();
+    assert_close(h.point, d.mult(-1.2));
+    assert_close(h.normal, d.mult(-1.0));
+    assert!(f.hit(&ray(v(-5.0, 1.1, 0.0), v(1.0, 0.0, 0.0))).is_none());
+}
+
+#[test]
+fn union_merges_overlapping_spans() {
+    let a = sphere(v(-0.5, 0.0, 0.0), 1.0, Material::LEFTWALL);
+    let b = sphere(v(0.5, 0.0, 0.0), 1.0, Material::RIGHTWALL);
+    let f = FigureKind::new_csg(CsgOp::Union, &a, &b);
+    let r = ray(v(-5.0, 0.0, 0.0), v(1.0, 0.0, 0.0));
+    assert_spans(&f, &r, &[(3.5, 6.5)]);
+    //Каждая часть объединения сохраняет свой материал
+    let s = f.spans(&r, r.t_min());
+    assert_eq!(s[0].enter.unwrap().material().color, Material::LEFTWALL.color);
+    assert_eq!(s[0].exit.unwrap().material().color, Material::RIGHTWALL.color);
+    //В экземпляре тоже
+    let moved = FigureKind::new_instance(&Arc::new(f), Transform::translate(&v(0.0, 3.0, 0.0)));
+    let h = moved.hit(&ray(v(5.0, 3.0, 0.0), v(-1.0, 0.0, 0.0))).unwrap();
+    assert_close(h.point, v(1.5, 3.0, 0.0));
+    assert_eq!(h.material().color, Material::RIGHTWALL.color);
+}
+
+#[test]
+fn plane_acts_as_half_space() {
+    let cut = Arc::new(FigureKind::new_plane(&v(0.0, 0.0, 0.0), &v(0.0, 0.0, -1.0), Material::CUBE));
+    let f = FigureKind::new_csg(CsgOp::Intersection, &sphere(v(0.0, 0.0, 0.0), 1.0, Material::CUBE), &cut);
+    //Срез по плоскости z = 0, остаётся половина за нормалью
+    let h = f.hit(&ray(v(0.3, 0.2, -5.0), v(0.0, 0.0, 1.0))).unwrap();
+    assert_close(h.point, v(0.3, 0.2, 0.0));
+    assert_close(h.normal, v(0.0, 0.0, -1.0));
+    let h = f.hit(&ray(v(0.0, 0.0, 5.0), v(0.0, 0.0, -1.0))).unwrap();
+    assert_close(h.point, v(0.0, 0.0, 1.0));
+    let d = FigureKind::new_csg(CsgOp::Difference, &sphere(v(0.0, 0.0, 0.0), 1.0, Material::CUBE), &cut);
+    assert_spans(&d, &ray(v(0.0, 0.0, -5.0), v(0.0, 0.0, 1.0)), &[(4.0, 5.0)]);
+}
+
+#[test]
+fn nested_operations() {
+    let hollow = Arc::new(hollow_cube());
+    let drill = Arc::new(FigureKind::new_cylinder(&v(0.0, 0.0, -2.0), &v(0.0, 0.0, 2.0), 0.2, Material::CUBE));
+    let f = FigureKind::new_csg(CsgOp::Difference, &hollow, &drill);
+    //По оси отверстия материала нет вовсе, рядом с ним - две стенки
+    assert!(f.hit(&ray(v(0.0, 0.0, -5.0), v(0.0, 0.0, 1.0))).is_none());
+    let s = span_ts(&f, &ray(v(0.3, 0.0, -5.0), v(0.0, 0.0, 1.0)));
+    assert_eq!(s.len(), 2, "{s:?}");
+}
+
+#[test]
+fn ray_inside_half_space_without_crossing() {
+    //Луч параллелен плоскости и целиком лежит за нормалью: пересечение - только со сферой
+    let cut = Arc::new(FigureKind::new_plane(&v(0.0, 0.0, 0.0), &v(0.0, 1.0, 0.0), Material::CUBE));
+    let f = FigureKind::new_csg(CsgOp::Intersection, &sphere(v(0.0, 0.0, 0.0), 1.0, Material::CUBE), &cut);
+    let h = (0.75 as Float).sqrt();
+    assert_spans(&f, &ray(v(-5.0, -0.5, 0.0), v(1.0, 0.0, 0.0)), &[(5.0 - h, 5.0 + h)]);
+    //По другую сторону плоскости тела нет
+    assert!(f.hit(&ray(v(-5.0, 0.5, 0.0), v(1.0, 0.0, 0.0))).is_none());
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::v;
+use raytracer::{
+    aov::{AovBuffers, PixelAov},
+    denoise::{denoise, DenoiseSettings},
+    math::{Float, Vector3},
+    raytracer::{render_aovs, render_radiance, Integrator, RenderSettings},
+    scene::Scene,
+    stats::RenderStats,
+};
+
+const SIZE: usize = 40;
+
+fn mean_error(a: &[Vector3], b: &[Vector3], pixels: &[usize]) -> Float {
+    pixels.iter().map(|i| (a[*i] - b[*i]).len_sq()).sum::<Float>() / pixels.len() as Float
+}
+
+//Ошибка в видимом диапазоне [0, 1], как после сохранения в 8 бит
+fn display_error(a: &[Vector3], b: &[Vector3]) -> Float {
+    let clamp = |c: &Vector3| v(c.x.clamp(0.0, 1.0), c.y.clamp(0.0, 1.0), c.z.clamp(0.0, 1.0));
+    let (a, b): (Vec<_>, Vec<_>) = (a.iter().map(clamp).collect(), b.iter().map(clamp).collect());
+    mean_error(&a, &b, &(0..a.len()).collect::<Vec<_>>())
+}
+
+//Пиксели, соседние по горизонтали или вертикали с пикселем другой фигуры
+fn edge_pixels(aov: &AovBuffers) -> Vec<usize> {
+    let w = aov.width;
+    (0..aov.pixels.len())
+        .filter(|i| {
+            let f = aov.pixels[*i].figure;
+            (i % w + 1 < w && aov.pixels[i + 1].figure != f) || (i + w < aov.pixels.len() && aov.pixels[i + w].figure != f)
+        })
+        .collect()
+}
+
+#[test]
+fn keeps_wall_and_cube_edges() {
+    let s = Scene::get_room();
+    let mut stats = RenderStats::default();
+    let img = render_radiance(&s, SIZE, SIZE, &RenderSettings::default(), &mut stats);
+    let aov = render_aovs(&s, SIZE, SIZE, &mut stats);
+    let edges = edge_pixels(&aov);
+    assert!(edges.len() > 50, "{}", edges.len());
+    let kept = denoise(&img, &aov, &DenoiseSettings::default());
+    //Тот же фильтр без остановки на границах - обычное размытие
+    let blind = DenoiseSettings { sigma_color: 1e6, sigma_normal: 1e6, sigma_depth: 1e6, sigma_albedo: 1e6, ..DenoiseSettings::default() };
+    let blurred = denoise(&img, &aov, &blind);
+    let (kept, blurred) = (mean_error(&kept, &img, &edges), mean_error(&blurred, &img, &edges));
+    assert!(kept < 0.1 * blurred, "edges: denoised {kept}, blurred {blurred}");
+}
+
+#[test]
+fn reduces_path_tracing_noise() {
+    let s = Scene::get_room();
+    let mut stats = RenderStats::default();
+    let settings = |samples| RenderSettings { samples, integrator: Integrator::Path, ..RenderSettings::default() };
+    let noisy = render_radiance(&s, SIZE, SIZE, &settings(2), &mut stats);
+    let reference = render_radiance(&s, SIZE, SIZE, &RenderSettings { seed: 1, ..settings(256) }, &mut stats);
+    let aov = render_aovs(&s, SIZE, SIZE, &mut stats);
+    let denoised = denoise(&noisy, &aov, &DenoiseSettings::default());
+    let (before, after) = (display_error(&noisy, &reference), display_error(&denoised, &reference));
+    assert!(after < 0.6 * before, "error before {before}, after {after}");
+}
+
+#[test]
+fn background_and_zero_depth_stay_finite() {
+    let hit = |depth| PixelAov { depth, normal: v(0.0, 0.0, -1.0), albedo: v(0.5, 0.5, 0.5), figure: Some(0) };
+    let pixels = vec![hit(0.0), hit(1.0), PixelAov::MISS, hit(2.0), PixelAov::MISS, hit(0.0)];
+    let aov = AovBuffers::new(3, 2, pixels);
+    let img = vec![v(0.2, 0.4, 0.6); 6];
+    for c in denoise(&img, &aov, &DenoiseSettings::default()) {
+        assert!(c.x.is_finite() && c.y.is_finite() && c.z.is_finite(), "{c}");
+    }
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use common::v;
+use raytracer::{
+    figure::FigureKind,
+    lights::LightSampler,
+    material::Material,
+    math::{consts, Float, Ray},
+    path::path_trace,
+    random::Rng,
+    raytracer::{closest_hit, emitter_light, raytrace, render, RenderSettings},
+    scene::Scene,
+    stats::{RayCounters, RenderStats},
+};
+
+const EMISSION: Float = 12.0;
+
+//Пол y = 1 и светящаяся панель над ним, больше ничего
+fn panel_scene(floor: Material) -> Scene {
+    let mut s = Scene::get_room();
+    s.lights = vec![];
+    s.figures = vec![
+        FigureKind::new_side(&v(-3.0, 1.0, -3.0), &v(3.0, 1.0, -3.0), &v(-3.0, 1.0, 3.0), floor),
+        FigureKind::new_side(&v(-0.6, -1.0, -0.6), &v(0.6, -1.0, -0.6), &v(-0.6, -1.0, 0.6), Material::emissive(v(EMISSION, EMISSION, EMISSION))),
+    ];
+    s
+}
+
+fn lambert() -> Material {
+    Material { specular: 0.0, refl: 0.0, diff: 1.0, base_illumination: 0.0, ..Material::FRONTWALLS }
+}
+
+//Луч сверху в точку пола
+fn floor_ray(x: Float) -> Ray {
+    Ray::new_normalize(v(x, 0.0, -1.0), &v(0.0, 1.0, 1.0))
+}
+
+#[test]
+fn surface_samples_lie_on_figures() {
+    let figures = [
+        FigureKind::new_side(&v(0.0, 0.0, 0.0), &v(2.0, 0.0, 0.0), &v(0.0, 0.0, 3.0), Material::CUBE),
+        FigureKind::Sphere { r: 0.5, pos: v(1.0, 2.0, 3.0), m: Material::CUBE },
+        FigureKind::new_disk(&v(1.0, 1.0, 1.0), &v(0.0, 1.0, 1.0), 0.7, Material::CUBE),
+    ];
+    let areas = [6.0, consts::PI, consts::PI * 0.49];
+    let mut rng = Rng::new(1, 0);
+    for (f, area) in figures.iter().zip(areas) {
+        assert!((f.area().unwrap() - area).abs() < 1e-4);
+        for _ in 0..100 {
+            let (p, n) = f.sample_surface((rng.next_f32() as Float, rng.next_f32() as Float)).unwrap();
+            //Луч к точке снаружи по нормали возвращается в неё
+            let h = f.hit(&Ray::new_normalize(p + n.mult(0.1), &-n)).unwrap();
+            assert!((h.point - p).len() < 1e-3, "{p} vs {}", h.point);
+        }
+    }
+    assert!(FigureKind::new_plane(&v(0.0, 0.0, 0.0), &v(0.0, 1.0, 0.0), Material::CUBE).area().is_none());
+}
+
+#[test]
+fn emitter_is_visible_to_camera() {
+    let s = panel_scene(lambert());
+    let up = Ray::new_normalize(v(0.1, 0.5, 0.0), &v(0.0, -1.0, 0.0));
+    let mut stats = RayCounters::default();
+    let seen = raytrace(0, &s, &up, 1.0, &LightSampler::new(&s, 0), &mut Rng::new(0, 0), &mut stats);
+    assert_eq!(seen, v(EMISSION, EMISSION, EMISSION));
+    let traced = path_trace(&s, &up, &LightSampler::new(&s, 0), &mut Rng::new(0, 0), &mut stats);
+    assert_eq!(traced, seen);
+    //Точечных источников нет, но сцена освещена
+    let img = render(&s, 16, 16, &RenderSettings::default(), &mut RenderStats::default());
+    assert!(img.iter().any(|c| c.r > 0 && c.r < 255));
+}
+
+#[test]
+fn sampled_light_matches_seen_light() {
+    let s = panel_scene(lambert());
+    let mut stats = RayCounters::default();
+    let r = floor_ray(0.3);
+    let (_, hit) = closest_hit(&s, &r, &mut stats).unwrap();
+    //Свет панели, который shade прибавляет в точке пола
+    let sampled = emitter_light(&s, &r, &hit, &s.figures[1], &mut stats);
+    //Те же лучи, что видела бы камера, по косинусу из точки пола
+    let mut rng = Rng::new(2, 0);
+    let lights = LightSampler::new(&s, 0);
+    let n = 100_000;
+    let mut seen = v(0.0, 0.0, 0.0);
+    for _ in 0..n {
+        let (a, b) = hit.normal.orthonormal_basis();
+        let (u1, u2) = (rng.next_f32() as Float, rng.next_f32() as Float);
+        let phi = 2.0 * consts::PI * u2;
+        let d = a.mult(u1.sqrt() * phi.cos()) + b.mult(u1.sqrt() * phi.sin()) + hit.normal.mult((1.0 - u1).sqrt());
+        seen += raytrace(1, &s, &Ray::spawn(&hit.point, &hit.normal, d), 1.0, &lights, &mut rng, &mut stats);
+    }
+    let seen = seen.div(n as Float).mult_per_element(&hit.material().color);
+    assert!((sampled.x - seen.x).abs() < 0.03 * seen.x, "sampled {sampled}, seen {seen}");
+}
+
+#[test]
+fn path_tracer_weights_both_strategies() {
+    //Гладкий пол: на нём важен выбор направления по BRDF, на шероховатом - выбор точки источника
+    for roughness in [0.1, 0.8] {
+        let s = panel_scene(Material::pbr(v(0.8, 0.8, 0.8), 0.0, roughness));
+        let mut stats = RayCounters::default();
+        let r = floor_ray(0.3);
+        let (_, hit) = closest_hit(&s, &r, &mut stats).unwrap();
+        let expected = emitter_light(&s, &r, &hit, &s.figures[1], &mut stats);
+        let n = 40_000;
+        let mut rng = Rng::new(3, 0);
+        let lights = LightSampler::new(&s, 0);
+        let mut traced = v(0.0, 0.0, 0.0);
+        for _ in 0..n {
+            traced += path_trace(&s, &r, &lights, &mut rng, &mut stats);
+        }
+        let traced = traced.div(n as Float);
+        assert!((traced.x - expected.x).abs() < 0.05 * expected.x, "roughness {roughness}: traced {traced}, sampled {expected}");
+    }
+}
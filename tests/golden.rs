@@ -0,0 +1,71 @@
+mod common;
+
+use common::{assert_golden, render_scene, Tolerance};
+use raytracer::{raytracer::{Integrator, RenderSettings}, scene::Scene};
+
+const SIZE: usize = 64;
+
+#[test]
+fn room() {
+    let img = render_scene(&Scene::get_room(), SIZE, &RenderSettings::default());
+    assert_golden("room", &img, &Tolerance::default());
+}
+
+#[test]
+fn primitives() {
+    let img = render_scene(&Scene::get_primitives(), SIZE, &RenderSettings::default());
+    assert_golden("primitives", &img, &Tolerance::default());
+}
+
+#[test]
+fn csg() {
+    let img = render_scene(&Scene::get_csg(), SIZE, &RenderSettings::default());
+    assert_golden("csg", &img, &Tolerance::default());
+}
+
+#[test]
+fn sdf() {
+    let img = render_scene(&Scene::get_sdf(), SIZE, &RenderSettings::default());
+    assert_golden("sdf", &img, &Tolerance::default());
+}
+
+#[test]
+fn pbr() {
+    let img = render_scene(&Scene::get_pbr(), SIZE, &RenderSettings::default());
+    assert_golden("pbr", &img, &Tolerance::default());
+}
+
+#[test]
+fn pbr_path_traced() {
+    let settings = RenderSettings { samples: 16, seed: 1, integrator: Integrator::Path, ..Default::default() };
+    let img = render_scene(&Scene::get_pbr(), SIZE, &settings);
+    assert_golden("pbr_path", &img, &Tolerance::default());
+}
+
+#[test]
+fn cornell() {
+    let img = render_scene(&Scene::get_cornell(), SIZE, &RenderSettings::default());
+    assert_golden("cornell", &img, &Tolerance::default());
+}
+
+#[test]
+fn room_supersampled() {
+    let settings = RenderSettings { samples: 4, seed: 1, ..Default::default() };
+    let img = render_scene(&Scene::get_room(), SIZE, &settings);
+    assert_golden("room_4spp", &img, &Tolerance::default());
+}
+
+#[test]
+fn same_seed_is_bit_identical() {
+    let settings = RenderSettings { samples: 4, seed: 42, ..Default::default() };
+    let a = render_scene(&Scene::get_room(), SIZE, &settings);
+    let b = render_scene(&Scene::get_room(), SIZE, &settings);
+    assert_eq!(a.as_raw(), b.as_raw());
+}
+
+#[test]
+fn different_seed_changes_image() {
+    let a = render_scene(&Scene::get_room(), SIZE, &RenderSettings { samples: 4, seed: 1, ..Default::default() });
+    let b = render_scene(&Scene::get_room(), SIZE, &RenderSettings { samples: 4, seed: 2, ..Default::default() });
+    assert_ne!(a.as_raw(), b.as_raw());
+}
@@ -0,0 +1,272 @@
+mod common;
+
+use common::v;
+use raytracer::{
+    figure::FigureKind,
+    material::Material,
+    math::{Float, Ray, Vector3},
+};
+
+const TOL: Float = 1e-4;
+
+fn ray(pos: Vector3, dir: Vector3) -> Ray {
+    Ray::new_normalize(pos, &dir)
+}
+
+#[track_caller]
+fn assert_close(a: Vector3, b: Vector3) {
+    assert!((a - b).len() < TOL, "expected {b}, got {a}");
+}
+
+//Единичный куб [0, 1]^3, передняя грань z = 0, верх y = 0
+fn unit_cube() -> FigureKind {
+    FigureKind::new_cube(&v(0.0, 0.0, 1.0), &v(1.0, 0.0, 1.0), &v(0.0, 1.0, 1.0), &v(0.0, 0.0, 0.0), Material::CUBE)
+}
+
+fn cube_hit(r: &Ray) -> Option<(Vector3, usize)> {
+    match unit_cube() {
+        FigureKind::Cube { pos, normals, .. } => FigureKind::cube_intersect(r, &pos, &normals),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn sphere_head_on() {
+    let r = ray(v(0.0, 0.0, -5.0), v(0.0, 0.0, 1.0));
+    let p = FigureKind::sphere_intersect(&r, 2.0, &v(0.0, 0.0, 0.0)).unwrap();
+    assert_close(p, v(0.0, 0.0, -2.0));
+}
+
+#[test]
+fn sphere_radius_is_not_squared() {
+    let centre = v(0.0, 0.0, 0.0);
+    //Для r = 0.2 луч на расстоянии 0.3 от центра проходит мимо (при ошибке радиус был бы √0.2 ≈ 0.447)
+    let r = ray(v(0.3, 0.0, -5.0), v(0.0, 0.0, 1.0));
+    assert!(FigureKind::sphere_intersect(&r, 0.2, &centre).is_none());
+    //Для r = 4 луч на расстоянии 3 попадает в точку z = -√(16 - 9)
+    let r = ray(v(3.0, 0.0, -10.0), v(0.0, 0.0, 1.0));
+    let p = FigureKind::sphere_intersect(&r, 4.0, &centre).unwrap();
+    assert_close(p, v(3.0, 0.0, -(7.0 as Float).sqrt()));
+}
+
+#[test]
+fn sphere_off_axis_hit_lies_on_surface() {
+    let centre = v(1.0, -2.0, 3.0);
+    let r = ray(v(-4.0, 1.0, -2.0), centre - v(-4.0, 1.0, -2.0) + v(0.1, 0.2, -0.1));
+    let p = FigureKind::sphere_intersect(&r, 1.5, &centre).unwrap();
+    assert!(((p - centre).len() - 1.5).abs() < TOL);
+}
+
+#[test]
+fn sphere_miss() {
+    let r = ray(v(0.0, 2.01, -5.0), v(0.0, 0.0, 1.0));
+    assert!(FigureKind::sphere_intersect(&r, 2.0, &v(0.0, 0.0, 0.0)).is_none());
+}
+
+#[test]
+fn sphere_behind_ray() {
+    let r = ray(v(0.0, 0.0, 5.0), v(0.0, 0.0, 1.0));
+    assert!(FigureKind::sphere_intersect(&r, 2.0, &v(0.0, 0.0, 0.0)).is_none());
+}
+
+#[test]
+fn sphere_from_inside_hits_exit_point() {
+    let centre = v(0.0, 0.0, 0.0);
+    let r = ray(v(0.0, 0.0, -0.5), v(0.0, 0.0, 1.0));
+    assert_close(FigureKind::sphere_intersect(&r, 1.0, &centre).unwrap(), v(0.0, 0.0, 1.0));
+    //Центр позади начала луча, но начало всё ещё внутри сферы
+    let r = ray(v(0.0, 0.0, 0.5), v(0.0, 0.0, 1.0));
+    assert_close(FigureKind::sphere_intersect(&r, 1.0, &centre).unwrap(), v(0.0, 0.0, 1.0));
+}
+
+#[test]
+fn sphere_normal_is_radial() {
+    let s = FigureKind::Sphere { r: 2.0, pos: v(1.0, 1.0, 1.0), m: Material::CUBE };
+    let r = ray(v(1.0, 1.0, -5.0), v(0.0, 0.0, 1.0));
+    let (p, n) = s.intersect_with_normal(&r).unwrap();
+    assert_close(p, v(1.0, 1.0, -1.0));
+    assert_close(n, v(0.0, 0.0, -1.0));
+}
+
+#[test]
+fn plane_hit_at_known_distance() {
+    let r = ray(v(1.0, 2.0, 0.0), v(0.0, 1.0, 0.0));
+    let p = FigureKind::plane_intersect(&r, &v(0.0, 5.0, 0.0), &v(0.0, -1.0, 0.0)).unwrap();
+    assert_close(p, v(1.0, 5.0, 0.0));
+}
+
+#[test]
+fn plane_oblique_hit() {
+    let r = ray(v(0.0, 0.0, 0.0), v(1.0, 1.0, 0.0));
+    let p = FigureKind::plane_intersect(&r, &v(3.0, 0.0, 0.0), &v(1.0, 0.0, 0.0)).unwrap();
+    assert_close(p, v(3.0, 3.0, 0.0));
+}
+
+#[test]
+fn plane_parallel_and_behind() {
+    let n = v(0.0, 1.0, 0.0);
+    let r = ray(v(0.0, 0.0, 0.0), v(1.0, 0.0, 0.0));
+    assert!(FigureKind::plane_intersect(&r, &v(0.0, 1.0, 0.0), &n).is_none());
+    let r = ray(v(0.0, 0.0, 0.0), v(0.0, -1.0, 0.0));
+    assert!(FigureKind::plane_intersect(&r, &v(0.0, 1.0, 0.0), &n).is_none());
+}
+
+#[test]
+fn plane_hit_from_either_side() {
+    let n = v(0.0, 0.0, 1.0);
+    let p = v(0.0, 0.0, 2.0);
+    let a = FigureKind::plane_intersect(&ray(v(0.0, 0.0, 0.0), v(0.0, 0.0, 1.0)), &p, &n).unwrap();
+    let b = FigureKind::plane_intersect(&ray(v(0.0, 0.0, 4.0), v(0.0, 0.0, -1.0)), &p, &n).unwrap();
+    assert_close(a, p);
+    assert_close(b, p);
+}
+
+fn square() -> (Vector3, Vector3, Vector3, Vector3) {
+    //Квадрат 2x1 в плоскости z = 3
+    let tl = v(-1.0, -0.5, 3.0);
+    let tr = v(1.0, -0.5, 3.0);
+    let dl = v(-1.0, 0.5, 3.0);
+    (tl, tr, dl, FigureKind::plane_normal(&tl, &tr, &dl))
+}
+
+#[test]
+fn rectangle_inside_hit() {
+    let (tl, tr, dl, n) = square();
+    let r = ray(v(0.5, 0.25, 0.0), v(0.0, 0.0, 1.0));
+    let p = FigureKind::rectangle_intersect(&r, &tl, &tr, &dl, &n).unwrap();
+    assert_close(p, v(0.5, 0.25, 3.0));
+}
+
+#[test]
+fn rectangle_outside_misses() {
+    let (tl, tr, dl, n) = square();
+    for (x, y) in [(1.1, 0.0), (-1.1, 0.0), (0.0, 0.6), (0.0, -0.6), (1.1, 0.6)] {
+        let r = ray(v(x, y, 0.0), v(0.0, 0.0, 1.0));
+        assert!(FigureKind::rectangle_intersect(&r, &tl, &tr, &dl, &n).is_none(), "({x}, {y})");
+    }
+}
+
+#[test]
+fn rectangle_corners_and_edges_hit() {
+    let (tl, tr, dl, n) = square();
+    for (x, y) in [(-1.0, -0.5), (1.0, -0.5), (-1.0, 0.5), (1.0, 0.5), (0.0, 0.5), (1.0, 0.0)] {
+        let r = ray(v(x, y, 0.0), v(0.0, 0.0, 1.0));
+        let p = FigureKind::rectangle_intersect(&r, &tl, &tr, &dl, &n).unwrap();
+        assert_close(p, v(x, y, 3.0));
+    }
+}
+
+#[test]
+fn rectangle_oblique_hit() {
+    let (tl, tr, dl, n) = square();
+    let r = ray(v(-3.0, 0.0, 0.0), v(3.5, 0.25, 3.0));
+    let p = FigureKind::rectangle_intersect(&r, &tl, &tr, &dl, &n).unwrap();
+    assert_close(p, v(0.5, 0.25, 3.0));
+}
+
+#[test]
+fn cube_hits_each_face() {
+    //(начало луча, направление, ожидаемая точка, номер грани)
+    let cases = [
+        (v(0.5, -2.0, 0.5), v(0.0, 1.0, 0.0), v(0.5, 0.0, 0.5), 0),
+        (v(0.5, 0.5, 3.0), v(0.0, 0.0, -1.0), v(0.5, 0.5, 1.0), 1),
+        (v(3.0, 0.5, 0.5), v(-1.0, 0.0, 0.0), v(1.0, 0.5, 0.5), 2),
+        (v(0.5, 0.5, -3.0), v(0.0, 0.0, 1.0), v(0.5, 0.5, 0.0), 3),
+        (v(-3.0, 0.5, 0.5), v(1.0, 0.0, 0.0), v(0.0, 0.5, 0.5), 4),
+        (v(0.5, 3.0, 0.5), v(0.0, -1.0, 0.0), v(0.5, 1.0, 0.5), 5),
+    ];
+    for (o, d, p, face) in cases {
+        let (hit, i) = cube_hit(&ray(o, d)).unwrap();
+        assert_eq!(i, face, "ray from {o}");
+        assert_close(hit, p);
+    }
+}
+
+#[test]
+fn cube_normals_point_outwards() {
+    let c = unit_cube();
+    let centre = v(0.5, 0.5, 0.5);
+    for d in [v(1.0, 0.0, 0.0), v(-1.0, 0.0, 0.0), v(0.0, 1.0, 0.0), v(0.0, -1.0, 0.0), v(0.0, 0.0, 1.0), v(0.0, 0.0, -1.0)] {
+        let (_, n) = c.intersect_with_normal(&ray(centre + d.mult(3.0), -d)).unwrap();
+        assert_close(n, d);
+    }
+}
+
+#[test]
+fn cube_picks_nearest_face() {
+    //Луч пересекает плоскости нескольких граней, ближайшая - нижняя (последняя в списке)
+    let (hit, i) = cube_hit(&ray(v(0.6, 3.0, 0.4), v(0.0, -1.0, 0.0))).unwrap();
+    assert_eq!(i, 5);
+    assert_close(hit, v(0.6, 1.0, 0.4));
+    let (hit, i) = cube_hit(&ray(v(0.5, 2.0, -0.5), v(0.0, -1.0, 1.0))).unwrap();
+    assert_eq!(i, 5);
+    assert_close(hit, v(0.5, 1.0, 0.5));
+}
+
+#[test]
+fn cube_from_inside_hits_exit_face() {
+    let (hit, i) = cube_hit(&ray(v(0.5, 0.5, 0.5), v(1.0, 0.0, 0.0))).unwrap();
+    assert_eq!(i, 2);
+    assert_close(hit, v(1.0, 0.5, 0.5));
+}
+
+#[test]
+fn cube_miss() {
+    assert!(cube_hit(&ray(v(1.5, 0.5, -3.0), v(0.0, 0.0, 1.0))).is_none());
+    assert!(cube_hit(&ray(v(0.5, 0.5, -3.0), v(0.0, 0.0, -1.0))).is_none());
+}
+
+#[test]
+fn cube_vertices_are_consistent() {
+    if let FigureKind::Cube { pos, .. } = unit_cube() {
+        //Передняя нижняя правая вершина
+        assert_close(pos[6], v(1.0, 1.0, 0.0));
+    }
+}
+
+#[test]
+fn sphere_interval_selects_root() {
+    let centre = v(0.0, 0.0, 0.0);
+    let r = ray(v(0.0, 0.0, -5.0), v(0.0, 0.0, 1.0));
+    assert_eq!(FigureKind::sphere_hit(&r, 1.0, &centre, 0.0, Float::INFINITY), Some(4.0));
+    //Ближний корень отсечён t_min, остаётся дальний
+    assert_eq!(FigureKind::sphere_hit(&r, 1.0, &centre, 4.5, Float::INFINITY), Some(6.0));
+    assert_eq!(FigureKind::sphere_hit(&r, 1.0, &centre, 0.0, 3.9), None);
+    assert_eq!(FigureKind::sphere_hit(&r, 1.0, &centre, 6.1, Float::INFINITY), None);
+}
+
+#[test]
+fn plane_interval() {
+    let r = ray(v(0.0, 0.0, 0.0), v(0.0, 0.0, 1.0));
+    let (p, n) = (v(0.0, 0.0, 2.0), v(0.0, 0.0, -1.0));
+    assert_eq!(FigureKind::plane_hit(&r, &p, &n, 0.0, 2.0), Some(2.0));
+    assert_eq!(FigureKind::plane_hit(&r, &p, &n, 0.0, 1.9), None);
+    assert_eq!(FigureKind::plane_hit(&r, &p, &n, 2.1, 5.0), None);
+}
+
+#[test]
+fn cube_interval_skips_entry_face() {
+    let c = unit_cube();
+    let r = ray(v(0.5, 0.5, -3.0), v(0.0, 0.0, 1.0));
+    let entry = c.hit_in(&r, 0.0, Float::INFINITY).unwrap();
+    assert!((entry.t - 3.0).abs() < TOL);
+    assert!(entry.front_face);
+    let exit = c.hit_in(&r, 3.5, Float::INFINITY).unwrap();
+    assert!((exit.t - 4.0).abs() < TOL);
+    assert!(!exit.front_face);
+    assert!(c.hit_in(&r, 0.0, 2.5).is_none());
+}
+
+#[test]
+fn occlusion_respects_interval() {
+    let figures = [
+        unit_cube(),
+        FigureKind::Sphere { r: 0.5, pos: v(0.5, 0.5, 5.0), m: Material::CUBE },
+        FigureKind::new_side(&v(-1.0, -1.0, 8.0), &v(2.0, -1.0, 8.0), &v(-1.0, 2.0, 8.0), Material::CUBE),
+    ];
+    let r = ray(v(0.5, 0.5, -3.0), v(0.0, 0.0, 1.0));
+    for (f, hit_t) in figures.iter().zip([3.0, 7.5, 11.0]) {
+        assert!(f.occludes(&r, 0.0, hit_t + 0.1));
+        assert!(!f.occludes(&r, 0.0, hit_t - 0.6));
+    }
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use common::v;
+use std::path::Path;
+
+use raytracer::{
+    figure::FigureKind,
+    library::{LibraryError, MaterialLibrary},
+    material::Material,
+    scene::Scene,
+};
+
+fn parse(text: &str) -> Result<MaterialLibrary, LibraryError> {
+    let mut lib = MaterialLibrary::default();
+    lib.parse(text)?;
+    Ok(lib)
+}
+
+#[track_caller]
+fn assert_error_at(text: &str, line: usize) {
+    match parse(text) {
+        Err(LibraryError::Parse { line: l, message }) => assert_eq!(l, line, "{message}"),
+        other => panic!("expected error at line {line}, got {other:?}"),
+    }
+}
+
+#[test]
+fn builtin_materials_are_named() {
+    let lib = MaterialLibrary::default();
+    let cube = lib.material("cube").unwrap();
+    assert_eq!(cube.color, Material::CUBE.color);
+    assert_eq!(cube.diff, Material::CUBE.diff);
+    assert_eq!(lib.material("flintglass").unwrap().dispersion, Material::FLINTGLASS.dispersion);
+    assert_eq!(lib.material("mirror").unwrap().refl, Material::MIRRORMATERIAL.refl);
+    assert_eq!(lib.material("gold").unwrap().pbr, Material::GOLD.pbr);
+    assert!(lib.get("glod").is_none());
+    //Опечатка в имени - ошибка с этим именем, а не падение
+    let err = lib.material("glod").unwrap_err();
+    assert!(matches!(&err, LibraryError::Unknown(name) if name == "glod"));
+    assert_eq!(err.to_string(), "unknown material 'glod'");
+}
+
+#[test]
+fn materials_inherit_and_override_fields() {
+    let lib = parse(
+        "# металлы
+        [gold : cubemetalic]
+        color = 1 0.77 0.34   # цвет золота
+        metallic = 1
+
+        [rough_gold : gold]
+        roughness = 0.8
+
+        [cube]
+        diff = 0.25
+        ",
+    )
+    .unwrap();
+    let base = Material::CUBEMETALIC;
+    let gold = lib.material("gold").unwrap();
+    assert_eq!(gold.color, v(1.0, 0.77, 0.34));
+    assert_eq!(gold.refl, base.refl);
+    assert_eq!(gold.shininess, base.shininess);
+    //Второй параметр pbr берётся у основы
+    let pbr = gold.pbr.unwrap();
+    assert_eq!((pbr.metallic, pbr.roughness), (1.0, base.as_pbr().roughness));
+    let rough = lib.material("rough_gold").unwrap();
+    assert_eq!(rough.color, gold.color);
+    assert_eq!(rough.pbr.unwrap().metallic, 1.0);
+    assert_eq!(rough.pbr.unwrap().roughness, 0.8);
+    //Правка встроенного материала сохраняет остальные поля
+    let cube = lib.material("cube").unwrap();
+    assert_eq!(cube.diff, 0.25);
+    assert_eq!(cube.color, Material::CUBE.color);
+}
+
+#[test]
+fn parse_errors_report_line() {
+    assert_error_at("[a : missing]", 1);
+    assert_error_at("color = 1 1 1", 1);
+    assert_error_at("[a : cube]\n\ncolor = 1 1", 3);
+    assert_error_at("[a : cube]\nrefl = x", 2);
+    assert_error_at("[a : cube]\nglow = 1", 2);
+    assert_error_at("[a : cube]\nrefl 1", 2);
+    assert_error_at("[a : cube", 1);
+    //Основа должна быть описана выше
+    assert_error_at("[a : b]\n[b : cube]", 1);
+    assert!(matches!(MaterialLibrary::load(Path::new("no/such/file.mat")), Err(LibraryError::Io(_))));
+}
+
+#[test]
+fn scenes_take_materials_by_name() {
+    let mut lib = MaterialLibrary::default();
+    lib.parse("[cubetransparent : mirror]\n[leftwall]\ncolor = 0 1 0").unwrap();
+    let plain = Scene::get_room();
+    let styled = Scene::get_room_in(&lib).unwrap();
+    assert_eq!(plain.figures.len(), styled.figures.len());
+    let colors = |s: &Scene| s.figures.iter().map(|f| f.get_material().color).collect::<Vec<_>>();
+    let changed: Vec<usize> = (0..plain.figures.len()).filter(|i| colors(&plain)[*i] != colors(&styled)[*i]).collect();
+    assert!(!changed.is_empty());
+    for i in changed {
+        let m = styled.figures[i].get_material();
+        assert!(m.color == v(0.0, 1.0, 0.0) || m.color == Material::MIRRORMATERIAL.color);
+    }
+    //Стеклянный шар комнаты стал зеркальным
+    let sphere = styled.figures.iter().find(|f| matches!(f, FigureKind::Sphere { .. })).unwrap().get_material();
+    assert_eq!((sphere.refl, sphere.transparency), (Material::MIRRORMATERIAL.refl, Material::MIRRORMATERIAL.transparency));
+}
+
+#[test]
+fn lit_and_pbr_scenes_take_materials_by_name() {
+    let mut lib = MaterialLibrary::default();
+    lib.parse("[panel_light]\nemission = 1 2 3\n[gold]\ncolor = 0 0 1\n[yellow_lamp : slit_light]").unwrap();
+    let cornell = Scene::get_cornell_in(&lib).unwrap();
+    let emissions: Vec<_> = cornell.emitters().map(|f| f.get_material().emission).collect();
+    assert_eq!(emissions, vec![v(1.0, 2.0, 3.0)]);
+    //Шероховатость ряда шаров задаёт сцена, цвет и metallic - библиотека
+    let pbr = Scene::get_pbr_in(&lib).unwrap();
+    let gold: Vec<_> = pbr.figures.iter().map(|f| f.get_material()).filter(|m| m.color == v(0.0, 0.0, 1.0)).collect();
+    assert_eq!(gold.len(), 5);
+    assert!(gold.iter().all(|m| m.pbr.unwrap().metallic == 1.0));
+    assert!(gold.windows(2).all(|w| w[0].pbr.unwrap().roughness < w[1].pbr.unwrap().roughness));
+    assert_eq!(Scene::get_pbr().figures[0].get_material().pbr, Material::PBR_FLOOR.pbr);
+    let lights = Scene::get_many_lights_in(&lib).unwrap();
+    assert!(lights.emitters().any(|f| f.get_material().emission == Material::SLIT_LIGHT.emission));
+    let prism = Scene::get_prism_in(&lib).unwrap();
+    assert_eq!(prism.emitters().count(), 13);
+}
+
+#[test]
+fn example_library_loads() {
+    let lib = MaterialLibrary::load(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/materials/example.mat"))).unwrap();
+    assert_eq!(lib.material("cubemetalic").unwrap().color, lib.material("gold").unwrap().color);
+    assert_eq!(lib.material("cubetransparent").unwrap().dispersion, Material::FLINTGLASS.dispersion);
+}
@@ -0,0 +1,199 @@
+mod common;
+
+use common::v;
+use raytracer::{
+    background::Background,
+    color::luminance,
+    figure::FigureKind,
+    lights::{LightRef, LightSampler},
+    material::Material,
+    math::{Float, Ray, Vector3},
+    path::path_trace,
+    raytracer::{closest_hit, raytrace, render_radiance, shadow_part, RenderSettings, AREA_LIGHT_SAMPLES},
+    random::Rng,
+    scene::{Falloff, LightSource, Scene},
+    stats::{RayCounters, RenderStats},
+};
+
+fn light(intencity: Float) -> LightSource {
+    LightSource { pos: v(0.0, -1.0, 0.0), color: v(1.0, 1.0, 1.0), intencity, falloff: Falloff::default() }
+}
+
+//Среднее по n путям через середину пола сцены
+fn mean_radiance(s: &Scene, light_samples: u32, n: usize, stats: &mut RayCounters) -> Vector3 {
+    let r = Ray::new_normalize(v(0.0, 0.0, -2.0), &v(0.1, 0.55, 1.0));
+    let lights = LightSampler::new(s, light_samples);
+    let mut rng = Rng::new(9, 0);
+    let mut sum = v(0.0, 0.0, 0.0);
+    for _ in 0..n {
+        sum += path_trace(s, &r, &lights, &mut rng, stats);
+    }
+    sum.div(n as Float)
+}
+
+//То же для рекурсивной трассировки
+fn whitted_mean(s: &Scene, light_samples: u32, n: usize, stats: &mut RayCounters) -> Vector3 {
+    let r = Ray::new_normalize(v(0.0, 0.0, -2.0), &v(0.1, 0.55, 1.0));
+    let lights = LightSampler::new(s, light_samples);
+    let mut rng = Rng::new(9, 0);
+    let mut sum = v(0.0, 0.0, 0.0);
+    for _ in 0..n {
+        sum += raytrace(0, s, &r, 1.0, &lights, &mut rng, stats);
+    }
+    sum.div(n as Float)
+}
+
+#[test]
+fn selection_follows_power() {
+    let mut s = Scene::get_pbr();
+    s.lights = vec![light(1.0), light(2.0), light(7.0)];
+    let sampler = LightSampler::new(&s, 2);
+    assert_eq!(sampler.lights.len(), 3);
+    let mut rng = Rng::new(1, 0);
+    let mut counts = [0.0; 3];
+    let n = 50_000;
+    for _ in 0..n {
+        sampler.for_each_choice(&mut rng, |l, weight, _| {
+            let LightRef::Point(l) = l else { panic!("no emitters in the scene") };
+            let i = [1.0, 2.0, 7.0].iter().position(|p| *p == l.intencity).unwrap();
+            //Вклад делится на ожидаемое число выборов
+            assert!((weight - 1.0 / (2.0 * l.intencity / 10.0)).abs() < 1e-4);
+            counts[i] += 1.0;
+        });
+    }
+    for (c, p) in counts.iter().zip([0.1, 0.2, 0.7]) {
+        assert!((c / (2 * n) as Float - p).abs() < 0.01, "{counts:?}");
+    }
+    //Без выбора каждый источник освещает с весом 1
+    let mut all = 0;
+    LightSampler::new(&s, 0).for_each_choice(&mut rng, |_, weight, _| {
+        assert_eq!(weight, 1.0);
+        all += 1;
+    });
+    assert_eq!(all, 3);
+}
+
+#[test]
+fn emitters_are_weighted_by_power() {
+    let s = Scene::get_many_lights();
+    let sampler = LightSampler::new(&s, 4);
+    assert_eq!(sampler.lights.len(), s.lights.len() + 3);
+    let emitters: Vec<_> = (0..s.figures.len()).filter(|i| sampler.figure_weight(*i) > 0.0).collect();
+    assert_eq!(emitters.len(), 3);
+    //Ожидаемое число выборов пропорционально мощности
+    let power = |i: usize| luminance(&s.figures[i].get_material().emission) * s.figures[i].area().unwrap();
+    let ratio = sampler.figure_weight(emitters[2]) / sampler.figure_weight(emitters[0]);
+    assert!((ratio - power(emitters[2]) / power(emitters[0])).abs() < 1e-3);
+}
+
+#[test]
+fn sampled_lights_converge_to_all_lights() {
+    let s = Scene::get_many_lights();
+    let mut stats = RayCounters::default();
+    let all = mean_radiance(&s, 0, 2_000, &mut stats);
+    let sampled = mean_radiance(&s, 1, 100_000, &mut stats);
+    assert!((luminance(&all) - luminance(&sampled)).abs() < 0.03 * luminance(&all), "all {all}, sampled {sampled}");
+}
+
+#[test]
+fn cost_does_not_grow_with_light_count() {
+    let shadow_rays = |count: usize| {
+        let mut s = Scene::get_many_lights();
+        s.lights.truncate(count);
+        let mut stats = RayCounters::default();
+        mean_radiance(&s, 1, 2_000, &mut stats);
+        stats.shadow
+    };
+    let (few, many) = (shadow_rays(10), shadow_rays(200));
+    assert!((many as Float) < 1.2 * few as Float, "{few} vs {many}");
+}
+
+#[test]
+fn whitted_samples_converge_to_all_lights() {
+    let s = Scene::get_many_lights();
+    let mut stats = RayCounters::default();
+    let all = whitted_mean(&s, 0, 1, &mut stats);
+    let sampled = whitted_mean(&s, 4, 20_000, &mut stats);
+    assert!((luminance(&all) - luminance(&sampled)).abs() < 0.03 * luminance(&all), "all {all}, sampled {sampled}");
+}
+
+#[test]
+fn whitted_shadow_rays_do_not_grow_with_light_count() {
+    let count = |lights: usize, settings: &RenderSettings| {
+        let mut s = Scene::get_many_lights();
+        s.lights.truncate(lights);
+        let mut stats = RenderStats::default();
+        render_radiance(&s, 16, 16, settings, &mut stats);
+        stats.counters
+    };
+    //В каждой точке освещают 4 источника, выбранных по мощности
+    let sampled = RenderSettings { light_samples: 4, ..RenderSettings::default() };
+    let (few, many) = (count(10, &sampled), count(200, &sampled));
+    let per_sample = |c: &RayCounters| c.shadow as Float / c.primary as Float;
+    assert!(per_sample(&many) < 1.2 * per_sample(&few), "{} vs {}", per_sample(&few), per_sample(&many));
+    //Не больше сетки светящейся фигуры на каждый выбор в каждой точке
+    let points = many.primary + many.reflection + many.refraction;
+    let bound = points * (4 * AREA_LIGHT_SAMPLES * AREA_LIGHT_SAMPLES) as u64;
+    assert!(many.shadow <= bound, "{} > {bound}", many.shadow);
+    //По умолчанию все источники сразу - в разы больше теневых лучей
+    let all = count(200, &RenderSettings::default());
+    assert!(all.shadow > 4 * many.shadow, "{} vs {}", all.shadow, many.shadow);
+}
+
+#[test]
+fn path_traced_point_light_passes_through_glass() {
+    //Только пол и источник: всё, что видно в точке пола, - прямой свет
+    let mut s = Scene::get_pbr();
+    s.figures.truncate(1);
+    s.lights = vec![light(1.0)];
+    s.background = Background::Color(v(0.0, 0.0, 0.0));
+    let mut stats = RayCounters::default();
+    let clear = mean_radiance(&s, 0, 64, &mut stats);
+    //Стеклянный шар между точкой пола и источником
+    let r = Ray::new_normalize(v(0.0, 0.0, -2.0), &v(0.1, 0.55, 1.0));
+    let (_, hit) = closest_hit(&s, &r, &mut stats).unwrap();
+    let pos = (hit.point + s.lights[0].pos).div(2.0);
+    s.figures.push(FigureKind::Sphere { r: 0.3, pos, m: Material::CUBETRANSPARENT });
+    let glass = mean_radiance(&s, 0, 64, &mut stats);
+    assert!(glass.x > 0.5 * clear.x && glass.x < clear.x, "glass {glass}, clear {clear}");
+}
+
+#[test]
+fn falloff_models() {
+    let int = 2.0;
+    //Кривая по умолчанию - прежняя мягкая int / (0.3·d² + 0.5·d)
+    for d in [0.5, 1.0, 3.0] {
+        assert_eq!(Falloff::default().apply(int, d), int / (d * d * 0.3 + d * 0.5));
+    }
+    assert_eq!(Falloff::None.apply(int, 10.0), int);
+    assert!((Falloff::Linear.apply(int, 4.0) - 0.5).abs() < 1e-6);
+    assert!((Falloff::InverseSquare.apply(int, 2.0) / Falloff::InverseSquare.apply(int, 1.0) - 0.25).abs() < 1e-6);
+    let custom = Falloff::Custom { constant: 1.0, linear: 0.0, quadratic: 0.0 };
+    assert_eq!(custom.apply(int, 7.0), int);
+    //Срез: вблизи почти обратные квадраты, к радиусу плавно до нуля, дальше - ноль
+    let cutoff = Falloff::Cutoff { radius: 4.0 };
+    assert!((cutoff.apply(int, 0.5) / Falloff::InverseSquare.apply(int, 0.5) - 1.0).abs() < 1e-3);
+    assert!(cutoff.apply(int, 3.99) < 1e-3 && cutoff.apply(int, 3.99) > 0.0);
+    assert_eq!(cutoff.apply(int, 4.0), 0.0);
+    assert_eq!(cutoff.apply(int, 9.0), 0.0);
+}
+
+#[test]
+fn shadow_part_honours_falloff() {
+    let mut s = Scene::get_pbr();
+    s.lights = vec![light(1.0)];
+    //Точка пола примерно в 2.6 от источника
+    let r = Ray::new_normalize(v(0.0, 0.0, -2.0), &v(0.1, 0.55, 1.0));
+    let mut stats = RayCounters::default();
+    let (_, hit) = closest_hit(&s, &r, &mut stats).unwrap();
+    let d = (s.lights[0].pos - hit.point).len();
+    let mut lit = |falloff: Falloff| {
+        let l = LightSource { falloff, ..s.lights[0].clone() };
+        shadow_part(&s, &r, &hit, &l, &mut stats).map_or(0.0, |c| luminance(&c))
+    };
+    let (none, square) = (lit(Falloff::None), lit(Falloff::InverseSquare));
+    assert!(none > 0.0);
+    assert!((square / none - 1.0 / (d * d)).abs() < 1e-4, "{none} {square} at {d}");
+    assert!(lit(Falloff::Cutoff { radius: 0.5 * d }) == 0.0);
+    assert!(lit(Falloff::Cutoff { radius: 2.0 * d }) > 0.0);
+}
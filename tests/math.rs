@@ -0,0 +1,104 @@
+mod common;
+
+use common::v;
+use raytracer::{
+    math::{consts::{FRAC_PI_2, PI}, Float, Matrix3, Normal3, Point3, Quaternion, Ray, Transform, Vector3},
+    scene::Scene,
+};
+
+const TOL: Float = 1e-4;
+
+#[track_caller]
+fn assert_close(a: Vector3, b: Vector3) {
+    assert!((a - b).len() < TOL, "expected {b}, got {a}");
+}
+
+#[test]
+fn matrix3_inverse_and_determinant() {
+    let m = Matrix3::new([[2.0, 0.0, 1.0], [1.0, 3.0, 0.0], [0.0, 1.0, 4.0]]);
+    assert!((m.determinant() - 25.0).abs() < TOL);
+    let id = m * m.inverse().unwrap();
+    for (a, b) in id.m.iter().flatten().zip(Matrix3::IDENTITY.m.iter().flatten()) {
+        assert!((a - b).abs() < TOL);
+    }
+    assert!(Matrix3::from_columns(&v(1.0, 2.0, 3.0), &v(2.0, 4.0, 6.0), &v(0.0, 0.0, 1.0)).inverse().is_none());
+}
+
+#[test]
+fn quaternion_rotation_matches_axis_helpers() {
+    let p = v(0.3, -1.2, 4.0);
+    let q = Quaternion::from_axis_angle(&v(0.0, 1.0, 0.0), 0.8);
+    assert_close(q.rotate(&p), p.rotate_y_axis(0.8));
+    assert_close(q.to_matrix3() * p, p.rotate_y_axis(0.8));
+    let e = Quaternion::from_euler(0.1, 0.2, 0.3);
+    assert_close(e.rotate(&p), p.rotate_x_axis(0.1).rotate_y_axis(0.2).rotate_z_axis(0.3));
+}
+
+#[test]
+fn quaternion_composition_and_conjugate() {
+    let a = Quaternion::from_axis_angle(&v(1.0, 0.0, 0.0), 0.4);
+    let b = Quaternion::from_axis_angle(&v(0.0, 0.0, 1.0), -1.1);
+    let p = v(1.0, 2.0, 3.0);
+    assert_close((a * b).rotate(&p), a.rotate(&b.rotate(&p)));
+    assert_close(a.conjugate().rotate(&a.rotate(&p)), p);
+}
+
+#[test]
+fn slerp_keeps_constant_angular_speed() {
+    let axis = v(0.0, 0.0, 1.0);
+    let a = Quaternion::IDENTITY;
+    let b = Quaternion::from_axis_angle(&axis, FRAC_PI_2);
+    for i in 0..=4 {
+        let t = i as Float / 4.0;
+        let q = a.slerp(&b, t);
+        assert!((q.len() - 1.0).abs() < TOL);
+        assert_close(q.rotate(&v(1.0, 0.0, 0.0)), v((t * FRAC_PI_2).cos(), (t * FRAC_PI_2).sin(), 0.0));
+    }
+}
+
+#[test]
+fn slerp_takes_shortest_arc() {
+    let a = Quaternion::from_axis_angle(&v(0.0, 1.0, 0.0), 0.1);
+    //Тот же поворот на -0.1, но заданный через 2pi - 0.1 с противоположным знаком кватерниона
+    let b = Quaternion::from_axis_angle(&v(0.0, 1.0, 0.0), 2.0 * PI - 0.1);
+    let mid = a.slerp(&b, 0.5);
+    assert_close(mid.rotate(&v(1.0, 0.0, 0.0)), v(1.0, 0.0, 0.0));
+    //Почти одинаковые повороты не дают NaN
+    let c = a.slerp(&a, 0.3);
+    assert!((c.dot(&a).abs() - 1.0).abs() < TOL);
+}
+
+#[test]
+fn trs_matches_matrix_chain() {
+    let q = Quaternion::from_axis_angle(&v(1.0, 1.0, 0.0), 0.6);
+    let t = Transform::from_trs(&v(1.0, 2.0, 3.0), &q, &v(2.0, 2.0, 0.5));
+    let p = v(0.5, -0.5, 1.0);
+    assert_close(t.transform_point(&p), q.rotate(&v(1.0, -1.0, 0.5)) + v(1.0, 2.0, 3.0));
+    assert_close(t.inverse().transform_point(&t.transform_point(&p)), p);
+}
+
+#[test]
+fn typed_points_normals_and_vectors_transform_differently() {
+    let t = Transform::scale(&v(4.0, 1.0, 1.0)).then(&Transform::translate(&v(10.0, 0.0, 0.0)));
+    let p = t.apply(&Point3::new(1.0, 1.0, 0.0));
+    assert_close(p.to_vector3(), v(14.0, 1.0, 0.0));
+    let d = t.apply(&v(1.0, 1.0, 0.0));
+    assert_close(d, v(4.0, 1.0, 0.0));
+    let n = t.apply(&Normal3::new(&v(1.0, 1.0, 0.0)));
+    assert_close(n.to_vector3(), v(0.25, 1.0, 0.0).normalize());
+    //Нормаль остаётся перпендикулярной преобразованной касательной
+    let tangent = t.apply(&v(1.0, -1.0, 0.0));
+    assert!(n.to_vector3().scalar_product(&tangent).abs() < TOL);
+    assert_close(p - Point3::new(14.0, 0.0, 0.0), v(0.0, 1.0, 0.0));
+}
+
+#[test]
+fn camera_can_be_animated() {
+    let s = Scene::get_room();
+    let start = Quaternion::IDENTITY;
+    let end = Quaternion::from_axis_angle(&v(0.0, 1.0, 0.0), 2.0);
+    let half = Transform::from_rotation(&start.slerp(&end, 0.5)).apply(&s.image);
+    let ray: Ray = half.get_rays(1, 1)[0];
+    //Камера смотрела вдоль +z, на середине анимации повёрнута на 1 радиан вокруг y
+    assert_close(ray.dir, v((1.0 as Float).sin(), 0.0, (1.0 as Float).cos()));
+}
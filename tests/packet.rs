@@ -0,0 +1,70 @@
+mod common;
+
+use common::v;
+use raytracer::{
+    figure::HitRecord,
+    math::{Float, Ray},
+    packet::{closest_hits, RayPacket, LANES},
+    raytracer::{closest_hit, render, render_packets, RenderSettings},
+    scene::Scene,
+    stats::{RayCounters, RenderStats},
+};
+
+#[track_caller]
+fn assert_packet_matches_scalar(scene: &Scene, rays: &[Ray]) {
+    let bounds: Vec<_> = scene.figures.iter().map(|f| f.bounds()).collect();
+    let mut stats = RayCounters::default();
+    for c in rays.chunks(LANES) {
+        let hits = closest_hits(scene, &bounds, &RayPacket::new(c), &mut stats);
+        for (r, h) in c.iter().zip(hits) {
+            //Пакет даёт ту же запись о пересечении, по которой затем считается цвет
+            let key = |(i, h): (usize, HitRecord)| (i, h.t, h.normal, h.uv, h.front_face, h.material().color);
+            let expected = closest_hit(scene, r, &mut stats).map(key);
+            assert_eq!(h.map(key), expected, "ray {r:?}");
+        }
+    }
+}
+
+#[test]
+fn camera_packets_match_scalar_hits() {
+    let s = Scene::get_room();
+    //Размеры не кратны ширине пакета, последний пакет неполный
+    assert_packet_matches_scalar(&s, &s.image.get_rays(37, 23));
+}
+
+#[test]
+fn primitive_packets_match_scalar_hits() {
+    for s in [Scene::get_primitives(), Scene::get_csg(), Scene::get_sdf()] {
+        assert_packet_matches_scalar(&s, &s.image.get_rays(37, 23));
+    }
+}
+
+#[test]
+fn incoherent_packets_match_scalar_hits() {
+    let s = Scene::get_room();
+    //Лучи из разных точек комнаты, в том числе изнутри сферы и повёрнутого куба
+    let origins = [v(0.0, 0.0, -4.0), v(-0.5, 1.0, 0.5), v(0.5, 0.5, 1.0), v(1.5, -1.5, 2.0)];
+    let mut rays = vec![];
+    for (k, o) in origins.iter().enumerate() {
+        for i in 0..50 {
+            let a = i as Float * 0.37 + k as Float;
+            rays.push(Ray::new_normalize(*o, &v(a.cos(), (a * 1.7).sin(), (a * 0.3).sin())));
+        }
+    }
+    assert_packet_matches_scalar(&s, &rays);
+}
+
+#[test]
+fn packet_render_is_identical_to_scalar() {
+    let s = Scene::get_room();
+    let mut scalar = RenderStats::default();
+    let mut packet = RenderStats::default();
+    let a = render(&s, 48, 32, &RenderSettings::default(), &mut scalar);
+    let b = render_packets(&s, 48, 32, &RenderSettings::default(), &mut packet);
+    assert!(a.iter().zip(&b).all(|(a, b)| (a.r, a.g, a.b) == (b.r, b.g, b.b)));
+    assert_eq!(scalar.counters.total_rays(), packet.counters.total_rays());
+    assert_eq!(scalar.counters.depth, packet.counters.depth);
+    //Отсечение по ограничивающим параллелепипедам экономит проверки пересечений
+    let tests = |s: &RenderStats| s.counters.intersection_tests.iter().sum::<u64>();
+    assert!(tests(&packet) <= tests(&scalar));
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::v;
+use raytracer::{
+    figure::FigureKind,
+    lights::LightSampler,
+    material::{Material, GLASS_REFRACTION},
+    math::{Float, Ray, Vector3},
+    photon::{Photon, PhotonMap},
+    random::Rng,
+    raytracer::raytrace,
+    scene::{Falloff, LightSource, Scene},
+    stats::RayCounters,
+};
+
+fn glass() -> Material {
+    Material { transparency: 1.0, refl: 0.0, color: v(1.0, 1.0, 1.0), refraction: GLASS_REFRACTION, ..Material::CUBETRANSPARENT }
+}
+
+//Пол y = 1.6, над ним шар-линза радиуса 0.5 в начале координат и источник над шаром.
+//Линза собирает свет источника в точку около y = 1.2, на полу каустика - яркое пятно.
+fn lens_scene(lens: Material) -> Scene {
+    let mut s = Scene::get_room();
+    let floor = Material { refl: 0.0, specular: 0.0, diff: 1.0, base_illumination: 0.0, ..Material::FRONTWALLS };
+    s.figures = vec![
+        FigureKind::new_side(&v(-4.0, 1.6, -4.0), &v(4.0, 1.6, -4.0), &v(-4.0, 1.6, 4.0), floor),
+        FigureKind::Sphere { r: 0.5, pos: v(0.0, 0.0, 0.0), m: lens },
+    ];
+    s.lights = vec![LightSource { pos: v(0.0, -2.0, 0.0), color: v(1.0, 1.0, 1.0), intencity: 4.0, falloff: Falloff::default() }];
+    s
+}
+
+//Яркость точки пола, видимой сверху под небольшим углом
+fn floor_brightness(s: &Scene, x: Float) -> Float {
+    let r = Ray::new_normalize(v(x - 0.1, 0.0, -1.0), &v(0.1, 1.6, 1.0));
+    raytrace(0, s, &r, 1.0, &LightSampler::new(s, 0), &mut Rng::new(0, 0), &mut RayCounters::default()).x
+}
+
+#[test]
+fn nearest_matches_brute_force() {
+    let mut rng = Rng::new(5, 0);
+    let mut p = || rng.next_f32() as Float;
+    let points: Vec<Vector3> = (0..2000).map(|_| v(p(), p(), p() * 0.1)).collect();
+    let map = PhotonMap::new(points.iter().map(|q| Photon::new(*q, v(0.0, 1.0, 0.0), v(1.0, 1.0, 1.0))).collect());
+    assert_eq!(map.len(), points.len());
+    for _ in 0..50 {
+        let q = v(p(), p(), p() * 0.1);
+        for (k, radius) in [(1, 1.0), (20, 1.0), (20, 0.02), (3000, 0.1)] {
+            let found = map.nearest(&q, k, radius);
+            let mut expected: Vec<Float> = points.iter().map(|x| (*x - q).len_sq()).filter(|d| *d < radius * radius).collect();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            expected.truncate(k);
+            let found: Vec<Float> = found.iter().map(|(d, _)| *d).collect();
+            assert_eq!(found, expected);
+        }
+    }
+}
+
+#[test]
+fn glass_focuses_light_into_caustic() {
+    let mut s = lens_scene(glass());
+    let shadow = floor_brightness(&s, 0.0);
+    let open = floor_brightness(&s, 2.0);
+    s.caustics = Some(PhotonMap::build(&s, 100_000, 1));
+    let map = s.caustics.as_ref().unwrap();
+    assert!(!map.is_empty());
+    let focus = floor_brightness(&s, 0.0);
+    assert!(focus > 3.0 * open && focus > 3.0 * shadow, "focus {focus}, shadow {shadow}, open {open}");
+    //Вдали от пятна остаются только лучи, сильно отклонённые у края шара
+    let far = floor_brightness(&s, 2.0);
+    assert!(far >= open && far < 1.2 * open, "far {far}, open {open}");
+}
+
+#[test]
+fn opaque_scene_has_no_caustics() {
+    let s = lens_scene(Material { transparency: 0.0, refl: 0.0, ..Material::CUBE });
+    assert!(PhotonMap::build(&s, 10_000, 1).is_empty());
+    //Зеркало на месте линзы отбрасывает фотоны
+    let s = lens_scene(Material::MIRRORMATERIAL);
+    assert!(!PhotonMap::build(&s, 10_000, 1).is_empty());
+}
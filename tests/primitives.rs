@@ -0,0 +1,129 @@
+mod common;
+
+use common::v;
+use raytracer::{
+    figure::FigureKind,
+    material::Material,
+    math::{Float, Ray, Vector3},
+};
+
+const TOL: Float = 1e-3;
+
+fn ray(pos: Vector3, dir: Vector3) -> Ray {
+    Ray::new_normalize(pos, &dir)
+}
+
+#[track_caller]
+fn assert_close(a: Vector3, b: Vector3) {
+    assert!((a - b).len() < TOL, "expected {b}, got {a}");
+}
+
+#[track_caller]
+fn assert_hit(f: &FigureKind, r: &Ray, point: Vector3, normal: Vector3) {
+    let h = f.hit(r).expect("no hit");
+    assert_close(h.point, point);
+    assert_close(h.normal, normal);
+    assert!(h.front_face);
+}
+
+fn cylinder() -> FigureKind {
+    FigureKind::new_cylinder(&v(0.0, 0.0, 0.0), &v(0.0, -2.0, 0.0), 1.0, Material::CUBE)
+}
+
+fn cone() -> FigureKind {
+    FigureKind::new_cone(&v(0.0, 0.0, 0.0), &v(0.0, -1.0, 0.0), 1.0, Material::CUBE)
+}
+
+fn torus() -> FigureKind {
+    FigureKind::new_torus(&v(0.0, 0.0, 0.0), &v(0.0, -1.0, 0.0), 1.0, 0.25, Material::CUBE)
+}
+
+#[test]
+fn plane_is_infinite() {
+    let p = FigureKind::new_plane(&v(0.0, 1.0, 0.0), &v(0.0, -1.0, 0.0), Material::CUBE);
+    assert_hit(&p, &ray(v(1e3, 0.0, -2e3), v(0.0, 1.0, 0.0)), v(1e3, 1.0, -2e3), v(0.0, -1.0, 0.0));
+    //Почти горизонтальный луч попадает далеко у горизонта
+    let h = p.hit(&ray(v(0.0, 0.0, 0.0), v(0.0, 0.01, 1.0))).unwrap();
+    assert!((h.point.z - 100.0).abs() < 0.1);
+    assert!(p.hit(&ray(v(0.0, 0.0, 0.0), v(1.0, 0.0, 1.0))).is_none());
+    assert!(p.hit(&ray(v(0.0, 0.0, 0.0), v(0.0, -1.0, 0.0))).is_none());
+}
+
+#[test]
+fn disk_respects_radius() {
+    let d = FigureKind::new_disk(&v(0.0, 0.0, 2.0), &v(0.0, 0.0, -1.0), 1.0, Material::CUBE);
+    assert_hit(&d, &ray(v(0.6, 0.6, 0.0), v(0.0, 0.0, 1.0)), v(0.6, 0.6, 2.0), v(0.0, 0.0, -1.0));
+    assert!(d.hit(&ray(v(0.8, 0.8, 0.0), v(0.0, 0.0, 1.0))).is_none());
+    let h = d.hit(&ray(v(0.0, 0.5, 0.0), v(0.0, 0.0, 1.0))).unwrap();
+    assert!((h.uv.1 - 0.5).abs() < TOL);
+}
+
+#[test]
+fn cylinder_side_and_caps() {
+    let c = cylinder();
+    assert_hit(&c, &ray(v(-5.0, -1.0, 0.0), v(1.0, 0.0, 0.0)), v(-1.0, -1.0, 0.0), v(-1.0, 0.0, 0.0));
+    assert_hit(&c, &ray(v(0.3, -5.0, 0.2), v(0.0, 1.0, 0.0)), v(0.3, -2.0, 0.2), v(0.0, -1.0, 0.0));
+    assert_hit(&c, &ray(v(0.3, 5.0, 0.2), v(0.0, -1.0, 0.0)), v(0.3, 0.0, 0.2), v(0.0, 1.0, 0.0));
+    //Над верхней крышкой и мимо боковой поверхности
+    assert!(c.hit(&ray(v(-5.0, -2.1, 0.0), v(1.0, 0.0, 0.0))).is_none());
+    assert!(c.hit(&ray(v(-5.0, -1.0, 1.1), v(1.0, 0.0, 0.0))).is_none());
+}
+
+#[test]
+fn cylinder_from_inside_hits_exit() {
+    let c = cylinder();
+    let h = c.hit(&ray(v(0.0, -1.0, 0.0), v(0.0, 0.0, 1.0))).unwrap();
+    assert_close(h.point, v(0.0, -1.0, 1.0));
+    assert!(!h.front_face);
+}
+
+#[test]
+fn cone_side_normal_is_tilted() {
+    let c = cone();
+    //На половине высоты радиус 0.5, образующая наклонена на 45 градусов
+    let s = (0.5 as Float).sqrt();
+    assert_hit(&c, &ray(v(-5.0, -0.5, 0.0), v(1.0, 0.0, 0.0)), v(-0.5, -0.5, 0.0), v(-s, -s, 0.0));
+    assert_hit(&c, &ray(v(0.2, 5.0, 0.1), v(0.0, -1.0, 0.0)), v(0.2, 0.0, 0.1), v(0.0, 1.0, 0.0));
+    //Выше вершины продолжение конуса не существует
+    assert!(c.hit(&ray(v(-5.0, -1.5, 0.0), v(1.0, 0.0, 0.0))).is_none());
+    assert!(c.hit(&ray(v(-5.0, -0.5, 0.6), v(1.0, 0.0, 0.0))).is_none());
+}
+
+#[test]
+fn torus_hits_tube_and_misses_hole() {
+    let t = torus();
+    assert_hit(&t, &ray(v(-5.0, 0.0, 0.0), v(1.0, 0.0, 0.0)), v(-1.25, 0.0, 0.0), v(-1.0, 0.0, 0.0));
+    assert_hit(&t, &ray(v(1.0, -5.0, 0.0), v(0.0, 1.0, 0.0)), v(1.0, -0.25, 0.0), v(0.0, -1.0, 0.0));
+    assert!(t.hit(&ray(v(0.0, -5.0, 0.0), v(0.0, 1.0, 0.0))).is_none());
+    assert!(t.hit(&ray(v(-5.0, 0.3, 0.0), v(1.0, 0.0, 0.0))).is_none());
+    //Луч через отверстие пересекает трубку четыре раза, первый раз в x = -1.25
+    let far = t.hit(&ray(v(-50.0, 0.0, 0.0), v(1.0, 0.0, 0.0))).unwrap();
+    assert_close(far.point, v(-1.25, 0.0, 0.0));
+    let inner = t.hit_in(&ray(v(-5.0, 0.0, 0.0), v(1.0, 0.0, 0.0)), 3.8, Float::INFINITY).unwrap();
+    assert_close(inner.point, v(-0.75, 0.0, 0.0));
+    assert_close(inner.normal, v(1.0, 0.0, 0.0));
+}
+
+#[test]
+fn oblique_hits_lie_on_surfaces() {
+    let figures = [cylinder(), cone(), torus()];
+    for (k, f) in figures.iter().enumerate() {
+        let mut hits = 0;
+        for i in 0..200 {
+            let a = i as Float * 0.31 + k as Float;
+            let o = v(a.cos(), (a * 0.7).sin(), a.sin()).mult(4.0);
+            let target = v((a * 1.3).sin() * 0.8, -0.5 + (a * 2.1).cos() * 0.3, (a * 0.9).cos() * 0.8);
+            let Some(h) = f.hit(&ray(o, target - o)) else { continue };
+            hits += 1;
+            assert!((h.normal.len() - 1.0).abs() < TOL);
+            assert!((0.0..=1.0 + TOL).contains(&h.uv.0) && (-TOL..=1.0 + TOL).contains(&h.uv.1), "uv {:?}", h.uv);
+            //Из точки чуть внутри по нормали луч выходит через заднюю сторону поверхности.
+            //У рёбер цилиндра и конуса точка внутри может оказаться за соседней гранью.
+            if !(0.02..=0.98).contains(&h.uv.1) { continue; }
+            let inside = h.point - h.normal.mult(1e-2);
+            let exit = f.hit(&ray(inside, h.normal)).unwrap();
+            assert!(!exit.front_face && exit.t < 2e-2, "figure {k}: normal points inwards at {}", h.point);
+        }
+        assert!(hits > 50, "figure {k}: only {hits} hits");
+    }
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use common::v;
+use raytracer::{
+    figure::FigureKind,
+    material::Material,
+    math::{consts, Float, Ray, Vector3},
+    raytracer::closest_hit,
+    scene::Scene,
+    sdf::{Sdf, HIT_EPSILON},
+    stats::RayCounters,
+};
+
+const TOL: Float = 1e-3;
+
+fn ray(pos: Vector3, dir: Vector3) -> Ray {
+    Ray::new_normalize(pos, &dir)
+}
+
+#[track_caller]
+fn assert_close(a: Vector3, b: Vector3) {
+    assert!((a - b).len() < TOL, "expected {b}, got {a}");
+}
+
+#[test]
+fn primitive_distances() {
+    let s = Sdf::Sphere { r: 1.0 };
+    assert!((s.distance(&v(3.0, 0.0, 0.0)) - 2.0).abs() < TOL);
+    assert!((s.distance(&v(0.0, 0.5, 0.0)) + 0.5).abs() < TOL);
+    let b = Sdf::Box { half: v(1.0, 2.0, 3.0) };
+    assert!((b.distance(&v(2.0, 0.0, 0.0)) - 1.0).abs() < TOL);
+    assert!((b.distance(&v(2.0, 3.0, 0.0)) - (2.0 as Float).sqrt()).abs() < TOL);
+    assert!((b.distance(&v(0.0, 0.0, 0.0)) + 1.0).abs() < TOL);
+    //Скруглённый куб совпадает с кубом на гранях и отступает на углах
+    let rb = Sdf::RoundBox { half: v(1.0, 1.0, 1.0), r: 0.2 };
+    assert!(rb.distance(&v(1.0, 0.0, 0.0)).abs() < TOL);
+    assert!(rb.distance(&v(1.0, 1.0, 1.0)) > 0.1);
+}
+
+#[test]
+fn operators() {
+    let a = Sdf::Sphere { r: 1.0 }.translate(&v(-1.0, 0.0, 0.0));
+    let b = Sdf::Sphere { r: 1.0 }.translate(&v(1.0, 0.0, 0.0));
+    let p = v(0.0, 1.0, 0.0);
+    let hard = a.clone().union(b.clone()).distance(&p);
+    assert!((hard - ((2.0 as Float).sqrt() - 1.0)).abs() < TOL);
+    //Гладкое объединение заполняет шов
+    assert!(a.smooth_union(b, 0.5).distance(&p) < hard);
+    //Закрутка не меняет сечение y = 0 и поворачивает сечение y = 1 на k радиан
+    let bx = Sdf::Box { half: v(1.0, 5.0, 0.1) };
+    let tw = bx.clone().twist(consts::FRAC_PI_2);
+    assert!((tw.distance(&v(0.9, 0.0, 0.0)) - bx.distance(&v(0.9, 0.0, 0.0))).abs() < TOL);
+    assert!(tw.distance(&v(0.0, 1.0, 0.9)) < 0.0);
+    assert!(tw.distance(&v(0.9, 1.0, 0.0)) > 0.0);
+    let rep = Sdf::Sphere { r: 0.3 }.repeat(&v(2.0, 0.0, 0.0));
+    for x in [-4.0, 0.0, 2.0, 100.0] {
+        assert!((rep.distance(&v(x, 0.0, 0.0)) + 0.3).abs() < TOL);
+    }
+    assert!((rep.distance(&v(1.0, 0.0, 0.0)) - 0.7).abs() < TOL);
+    assert!(rep.bounds().is_none());
+}
+
+#[test]
+fn marching_matches_analytic_sphere() {
+    let sdf = FigureKind::Sdf { sdf: Sdf::Sphere { r: 1.0 }.translate(&v(0.5, 0.0, 3.0)), m: Material::CUBE };
+    let analytic = FigureKind::Sphere { r: 1.0, pos: v(0.5, 0.0, 3.0), m: Material::CUBE };
+    for i in 0..50 {
+        let a = i as Float * 0.13;
+        let r = ray(v(0.0, 0.0, -2.0), v(a.sin() * 0.2, a.cos() * 0.15, 1.0));
+        match (sdf.hit(&r), analytic.hit(&r)) {
+            (Some(s), Some(e)) => {
+                assert!(((s.point - v(0.5, 0.0, 3.0)).len() - 1.0).abs() < HIT_EPSILON * s.t, "{} is off the surface", s.point);
+                assert!(s.front_face);
+                //По касательной точка может сместиться вдоль поверхности
+                if e.normal.scalar_product(&r.dir) < -0.3 {
+                    assert!((s.t - e.t).abs() < HIT_EPSILON * 10.0, "t {} vs {}", s.t, e.t);
+                    assert_close(s.normal, e.normal);
+                }
+            }
+            (s, e) => assert_eq!(s.is_some(), e.is_some(), "ray {r:?}"),
+        }
+    }
+}
+
+#[test]
+fn secondary_rays_leave_and_cross_surface() {
+    let f = FigureKind::Sdf { sdf: Sdf::RoundBox { half: v(1.0, 1.0, 1.0), r: 0.3 }, m: Material::CUBE };
+    for i in 0..40 {
+        let a = i as Float * 0.17;
+        let r = ray(v(a.sin() * 4.0, a.cos() * 1.5, -5.0), v(-a.sin() * 0.6, -a.cos() * 0.3, 1.0));
+        let Some(h) = f.hit(&r) else { continue };
+        //Отражённый луч уходит от выпуклого тела
+        let refl = r.reflect(&h.point, &h.normal);
+        assert!(f.hit(&Ray::spawn(&h.point, &h.normal, refl.dir)).is_none(), "re-hit at {}", h.point);
+        //Прошедший луч выходит с другой стороны, а не у точки входа
+        let exit = f.hit(&Ray::spawn(&h.point, &h.normal, r.dir)).unwrap();
+        assert!(!exit.front_face && exit.t > 0.1, "exit t = {}", exit.t);
+    }
+}
+
+#[test]
+fn mandelbulb_is_bounded() {
+    let f = FigureKind::Sdf { sdf: Sdf::Mandelbulb { power: 8.0, iterations: 8 }, m: Material::CUBE };
+    let h = f.hit(&ray(v(0.0, 0.0, -5.0), v(0.0, 0.0, 1.0))).unwrap();
+    assert!(h.point.len() < 1.3 && h.point.len() > 0.3, "{}", h.point);
+    assert!((h.normal.len() - 1.0).abs() < TOL);
+    assert!(f.hit(&ray(v(0.0, 1.6, -5.0), v(0.0, 0.0, 1.0))).is_none());
+}
+
+#[test]
+fn sdf_and_analytic_figures_share_scene() {
+    let mut s = Scene::get_primitives();
+    s.figures = vec![
+        FigureKind::Sdf { sdf: Sdf::Box { half: v(0.5, 0.5, 0.5) }.translate(&v(0.0, 0.0, 3.0)), m: Material::CUBE },
+        FigureKind::Sphere { r: 0.5, pos: v(0.3, 0.0, 2.0), m: Material::CUBE },
+    ];
+    let mut stats = RayCounters::default();
+    let (i, h) = closest_hit(&s, &ray(v(0.0, 0.0, -2.0), v(0.0, 0.0, 1.0)), &mut stats).unwrap();
+    assert_eq!(i, 1);
+    assert!(h.point.z < 2.0);
+    let (i, h) = closest_hit(&s, &ray(v(-0.3, 0.0, -2.0), v(0.0, 0.0, 1.0)), &mut stats).unwrap();
+    assert_eq!(i, 0);
+    assert!((h.point.z - 2.5).abs() < TOL);
+}
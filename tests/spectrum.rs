@@ -0,0 +1,78 @@
+mod common;
+
+use common::v;
+use raytracer::{
+    figure::FigureKind,
+    lights::LightSampler,
+    material::{Material, REFERENCE_WAVELENGTH},
+    math::{Float, Ray},
+    path::path_trace_at,
+    random::Rng,
+    scene::Scene,
+    spectrum::{cie_xyz, rgb_basis, Spectrum, MAX_WAVELENGTH, MIN_WAVELENGTH},
+    stats::RayCounters,
+};
+
+#[test]
+fn colour_matching_functions() {
+    //ȳ - кривая видности с максимумом около 555 нм
+    let peak = (400..700).max_by(|a, b| cie_xyz(*a as Float).y.partial_cmp(&cie_xyz(*b as Float).y).unwrap()).unwrap();
+    assert!((550..=562).contains(&peak), "{peak}");
+    //Площади под тремя кривыми почти равны: ровный спектр - белая точка E
+    let mut sum = v(0.0, 0.0, 0.0);
+    for w in MIN_WAVELENGTH as usize..MAX_WAVELENGTH as usize {
+        sum += cie_xyz(w as Float + 0.5);
+    }
+    assert!((sum.x / sum.y - 1.0).abs() < 0.03 && (sum.z / sum.y - 1.0).abs() < 0.03, "{sum}");
+    //Базисные веса делят длину волны между каналами без остатка
+    for w in [380.0, 450.0, 500.0, 589.3, 700.0] {
+        let b = rgb_basis(w);
+        assert!((b.x + b.y + b.z - 1.0).abs() < 1e-5, "{w}: {b}");
+    }
+}
+
+#[test]
+fn constant_radiance_round_trips() {
+    let spectrum = Spectrum::new();
+    for rgb in [v(1.0, 1.0, 1.0), v(0.8, 0.3, 0.1), v(0.0, 0.2, 1.0)] {
+        //Среднее по стратифицированным длинам волн
+        let n = 4000;
+        let mut sum = v(0.0, 0.0, 0.0);
+        for i in 0..n {
+            let (w, pdf) = spectrum.sample((i as Float + 0.5) / n as Float);
+            sum += spectrum.to_rgb(w, pdf, &rgb);
+        }
+        let avg = sum.div(n as Float);
+        assert!((avg - rgb).len() < 1e-2, "{rgb} -> {avg}");
+    }
+}
+
+#[test]
+fn refraction_index_depends_on_wavelength() {
+    let m = Material::FLINTGLASS;
+    assert_eq!(m.ior(None), m.refraction);
+    assert!((m.ior(Some(REFERENCE_WAVELENGTH)) - m.refraction).abs() < 1e-4);
+    //Нормальная дисперсия: синий преломляется сильнее красного
+    assert!(m.ior(Some(450.0)) > m.refraction && m.refraction > m.ior(Some(650.0)));
+    let plain = Material::CUBETRANSPARENT;
+    assert_eq!(plain.ior(Some(450.0)), plain.ior(Some(650.0)));
+}
+
+#[test]
+fn only_dispersive_glass_marks_path_as_dispersed() {
+    let mut s = Scene::get_primitives();
+    s.figures.push(FigureKind::Sphere { r: 0.5, pos: v(0.0, 0.0, 2.0), m: Material::FLINTGLASS });
+    let lights = LightSampler::new(&s, 0);
+    let through_glass = Ray::new_normalize(v(0.0, 0.0, 0.0), &v(0.0, 0.0, 1.0));
+    let mut rng = Rng::new(1, 0);
+    //Отражение от стекла выбирается случайно, преломление хотя бы раз на несколько путей
+    let dispersed = (0..16).any(|_| path_trace_at(&s, &through_glass, &lights, Some(500.0), &mut rng, &mut RayCounters::default()).1);
+    assert!(dispersed);
+    let (_, plain) = path_trace_at(&s, &through_glass, &lights, None, &mut rng, &mut RayCounters::default());
+    assert!(!plain);
+    s.figures.pop();
+    s.figures.push(FigureKind::Sphere { r: 0.5, pos: v(0.0, 0.0, 2.0), m: Material::CUBETRANSPARENT });
+    let lights = LightSampler::new(&s, 0);
+    let dispersed = (0..16).any(|_| path_trace_at(&s, &through_glass, &lights, Some(500.0), &mut rng, &mut RayCounters::default()).1);
+    assert!(!dispersed);
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use common::v;
+use std::sync::Arc;
+
+use raytracer::{
+    figure::FigureKind,
+    material::Material,
+    math::{consts, Float, Matrix4, Ray, Transform, Vector3},
+};
+
+const TOL: Float = 1e-4;
+
+#[track_caller]
+fn assert_close(a: Vector3, b: Vector3) {
+    assert!((a - b).len() < TOL, "expected {b}, got {a}");
+}
+
+#[test]
+fn inverse_of_composition() {
+    let t = Transform::scale(&v(2.0, 0.5, 3.0))
+        .then(&Transform::rotate(&v(1.0, 2.0, -1.0), 0.7))
+        .then(&Transform::translate(&v(1.0, -2.0, 5.0)));
+    let inv = t.matrix.inverse().unwrap();
+    for (a, b) in inv.m.iter().flatten().zip(t.inverse.m.iter().flatten()) {
+        assert!((a - b).abs() < TOL);
+    }
+    let p = v(0.3, -1.2, 4.0);
+    assert_close(t.inverse().transform_point(&t.transform_point(&p)), p);
+}
+
+#[test]
+fn singular_matrix_has_no_inverse() {
+    assert!(Matrix4::scaling(&v(1.0, 0.0, 1.0)).inverse().is_none());
+    assert!(Transform::from_matrix(Matrix4::scaling(&v(1.0, 0.0, 1.0))).is_none());
+}
+
+#[test]
+fn rotations_match_vector_helpers() {
+    let p = v(0.3, -1.2, 4.0);
+    let a = 0.9;
+    assert_close(Transform::rotate_x(a).transform_vector(&p), p.rotate_x_axis(a));
+    assert_close(Transform::rotate_y(a).transform_vector(&p), p.rotate_y_axis(a));
+    assert_close(Transform::rotate_z(a).transform_vector(&p), p.rotate_z_axis(a));
+    assert_close(Transform::rotate(&v(0.0, 2.0, 0.0), a).transform_vector(&p), p.rotate_y_axis(a));
+}
+
+#[test]
+fn then_applies_left_to_right() {
+    let t = Transform::translate(&v(1.0, 0.0, 0.0)).then(&Transform::rotate_z(consts::FRAC_PI_2));
+    assert_close(t.transform_point(&v(0.0, 0.0, 0.0)), v(0.0, 1.0, 0.0));
+    //Векторы не сдвигаются
+    assert_close(t.transform_vector(&v(1.0, 0.0, 0.0)), v(0.0, 1.0, 0.0));
+}
+
+#[test]
+fn normals_use_inverse_transpose() {
+    //Плоскость x + y = 0 после растяжения по x вдвое становится x/2 + y = 0
+    let t = Transform::scale(&v(2.0, 1.0, 1.0));
+    let n = t.transform_normal(&v(1.0, 1.0, 0.0).normalize());
+    assert_close(n, v(0.5, 1.0, 0.0).normalize());
+    let tangent = t.transform_vector(&v(1.0, -1.0, 0.0));
+    assert!(n.scalar_product(&tangent).abs() < TOL);
+}
+
+#[test]
+fn instanced_sphere_becomes_ellipsoid() {
+    let sphere = Arc::new(FigureKind::Sphere { r: 1.0, pos: v(0.0, 0.0, 0.0), m: Material::CUBE });
+    let e = FigureKind::new_instance(&sphere, Transform::scale(&v(3.0, 1.0, 1.0)).then(&Transform::translate(&v(0.0, 0.0, 10.0))));
+    let h = e.hit(&Ray::new_normalize(v(-10.0, 0.0, 10.0), &v(1.0, 0.0, 0.0))).unwrap();
+    assert!((h.t - 7.0).abs() < TOL);
+    assert_close(h.point, v(-3.0, 0.0, 10.0));
+    assert_close(h.normal, v(-1.0, 0.0, 0.0));
+    assert!(h.front_face);
+    let h = e.hit(&Ray::new_normalize(v(0.0, 0.0, 0.0), &v(0.0, 0.0, 1.0))).unwrap();
+    assert!((h.t - 9.0).abs() < TOL);
+    assert!(e.hit(&Ray::new_normalize(v(0.0, 1.5, 0.0), &v(0.0, 0.0, 1.0))).is_none());
+}
+
+#[test]
+fn instance_matches_prerotated_cube() {
+    let a = consts::PI / 6.0;
+    let o = v(0.5, 0.5, 1.0);
+    let direct = FigureKind::new_cube_from_d(&o,
+        &v(1.0, 0.0, 0.0).rotate_y_axis(a),
+        &v(0.0, 1.5, 0.0).rotate_y_axis(a),
+        &v(0.0, 0.0, -1.0).rotate_y_axis(a), Material::CUBE);
+    let base = Arc::new(FigureKind::new_cube_from_d(&v(0.0, 0.0, 0.0),
+        &v(1.0, 0.0, 0.0), &v(0.0, 1.5, 0.0), &v(0.0, 0.0, -1.0), Material::CUBE));
+    let inst = FigureKind::new_instance(&base, Transform::rotate_y(a).then(&Transform::translate(&o)));
+    let eye = v(0.0, 0.0, -5.0);
+    for i in 0..400 {
+        let target = v((i % 20) as Float / 10.0 - 0.5, (i / 20) as Float / 10.0 - 0.5, 0.5);
+        let r = Ray::new_normalize(eye, &(target - eye));
+        match (direct.hit(&r), inst.hit(&r)) {
+            (Some(a), Some(b)) => {
+                assert!((a.t - b.t).abs() < TOL);
+                //На рёбрах грань выбирается произвольно
+                let on_edge = [a.uv.0, a.uv.1].iter().any(|x| !(0.01..=0.99).contains(x));
+                if !on_edge {
+                    assert_close(a.normal, b.normal);
+                }
+            }
+            (None, None) => {}
+            (a, b) => panic!("mismatch at {target}: {:?} vs {:?}", a.map(|h| h.t), b.map(|h| h.t)),
+        }
+        assert_eq!(direct.occludes(&r, 0.0, 5.0), inst.occludes(&r, 0.0, 5.0));
+    }
+}
+
+#[test]
+fn instances_share_geometry() {
+    let base = Arc::new(FigureKind::Sphere { r: 0.5, pos: v(0.0, 0.0, 0.0), m: Material::CUBE });
+    let copies: Vec<_> = (0..100)
+        .map(|i| FigureKind::new_instance(&base, Transform::translate(&v(i as Float, 0.0, 0.0))))
+        .collect();
+    assert_eq!(Arc::strong_count(&base), 101);
+    let h = copies[42].hit(&Ray::new_normalize(v(42.0, 0.0, -5.0), &v(0.0, 0.0, 1.0))).unwrap();
+    assert_close(h.point, v(42.0, 0.0, -0.5));
+}